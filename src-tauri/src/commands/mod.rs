@@ -1,7 +1,14 @@
 use crate::config::{AppConfig, AppSettings};
-use crate::download::{DownloadManager, DownloadProgress, DownloadStatus, DownloadTask};
-use crate::game::{self, GameManifest};
-use std::collections::HashMap;
+use crate::download::journal::{self, JournalPack, PackStatus};
+use crate::download::{
+    ConcurrencyStatus, DownloadManager, DownloadProgress, DownloadStatus, DownloadTask,
+    MirrorResult, SpeedBenchmark, TaskDetail,
+};
+use crate::game::sessions::{self, GameSession};
+use crate::game::verify::{self, BadFile};
+use crate::game::{self, GameChannel, GameManifest, Platform};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use sysinfo::{Pid as SysPid, ProcessesToUpdate, System as SysInfo};
 use tauri::{AppHandle, Emitter, Manager, State};
@@ -12,6 +19,11 @@ pub struct AppState {
     pub http_client: reqwest::Client,
     /// game_id → sysinfo PID of the running game process
     pub running_games: HashMap<String, SysPid>,
+    /// game_id → download task ids of its current install, so simultaneous
+    /// installs report progress and cancel independently
+    pub install_groups: HashMap<String, HashSet<String>>,
+    /// game_id → cancel flag of its running extraction
+    pub extractions: HashMap<String, Arc<AtomicBool>>,
 }
 
 // ─── Game status event ────────────────────────────────────────────────────────
@@ -37,7 +49,37 @@ pub async fn set_settings(
     settings: AppSettings,
     app: AppHandle,
     config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
 ) -> Result<(), String> {
+    let network = settings.network_options();
+    if let Some(proxy) = &network.proxy_url {
+        crate::net::validate_proxy_url(proxy).map_err(|e| e.to_string())?;
+    }
+    for (game_id, channel) in &settings.game_channels {
+        check_channel(game_id, *channel)?;
+    }
+    // Proxy, DNS and IP family are baked into the HTTP clients; rebuild them
+    // when any of those change.
+    if network != config.read().await.settings.network_options() {
+        let http_client = crate::net::http_client(&network).map_err(|e| e.to_string())?;
+        let mut s = state.write().await;
+        s.download_manager
+            .set_network(&network)
+            .map_err(|e| e.to_string())?;
+        s.http_client = http_client;
+    }
+    {
+        let s = state.read().await;
+        s.download_manager.set_verify_mode(settings.verify_mode);
+        s.download_manager
+            .set_keep_files(settings.debug_keep_all_files);
+        s.download_manager
+            .set_concurrency(settings.download_concurrency);
+        s.download_manager
+            .set_download_threads(settings.download_threads);
+        s.download_manager
+            .set_speed_limit(settings.max_download_speed_kbps);
+    }
     {
         let mut c = config.write().await;
         c.settings = settings;
@@ -48,6 +90,52 @@ pub async fn set_settings(
         .map_err(|e| e.to_string())
 }
 
+/// Several games configured with the same install directory.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathConflict {
+    pub path: String,
+    pub game_ids: Vec<String>,
+}
+
+/// Comparable form of a directory path: canonical when it exists, without
+/// trailing separators, and case-folded on Windows.
+fn normalize_dir(path: &str) -> String {
+    let p = std::fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string());
+    let p = p.trim_end_matches(['/', '\\']).to_string();
+    if cfg!(windows) {
+        p.to_lowercase()
+    } else {
+        p
+    }
+}
+
+/// Group `game_paths` by normalized directory, keeping groups of 2+ games.
+fn path_conflicts(game_paths: &HashMap<String, String>) -> Vec<PathConflict> {
+    let mut by_dir: HashMap<String, PathConflict> = HashMap::new();
+    for (game_id, path) in game_paths {
+        by_dir
+            .entry(normalize_dir(path))
+            .or_insert_with(|| PathConflict {
+                path: path.clone(),
+                game_ids: Vec::new(),
+            })
+            .game_ids
+            .push(game_id.clone());
+    }
+    let mut conflicts: Vec<PathConflict> = by_dir
+        .into_values()
+        .filter(|c| c.game_ids.len() > 1)
+        .collect();
+    for c in &mut conflicts {
+        c.game_ids.sort();
+    }
+    conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+    conflicts
+}
+
 #[tauri::command]
 pub async fn set_game_path(
     game_id: String,
@@ -59,6 +147,25 @@ pub async fn set_game_path(
         let mut c = config.write().await;
         match path {
             Some(p) => {
+                let target = normalize_dir(&p);
+                let other = c
+                    .game_paths
+                    .iter()
+                    .find(|(id, existing)| **id != game_id && normalize_dir(existing) == target)
+                    .map(|(id, _)| id.clone());
+                if let Some(other) = other {
+                    if c.settings.reject_path_conflicts {
+                        return Err(format!("该目录已被 {} 使用：{}", other, p));
+                    }
+                    log::warn!("[game] {} path {} is also used by {}", game_id, p, other);
+                    let _ = app.emit(
+                        "game:path-conflict",
+                        PathConflict {
+                            path: p.clone(),
+                            game_ids: vec![other, game_id.clone()],
+                        },
+                    );
+                }
                 c.game_paths.insert(game_id, p);
             }
             None => {
@@ -72,6 +179,43 @@ pub async fn set_game_path(
         .map_err(|e| e.to_string())
 }
 
+fn check_channel(game_id: &str, channel: GameChannel) -> Result<(), String> {
+    if game::supports_channel(game_id, channel) {
+        Ok(())
+    } else {
+        Err(format!("{} 不支持 {:?} 渠道", game_id, channel))
+    }
+}
+
+/// Choose the server (官服 / B 服) a game's downloads, updates and gacha
+/// history come from.
+#[tauri::command]
+pub async fn set_game_channel(
+    game_id: String,
+    channel: GameChannel,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+) -> Result<(), String> {
+    check_channel(&game_id, channel)?;
+    log::info!("[game] {} channel -> {:?}", game_id, channel);
+    {
+        let mut c = config.write().await;
+        c.settings.game_channels.insert(game_id, channel);
+    }
+    let c = config.read().await.clone();
+    crate::config::save_config(&app, &c)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Install directories currently shared by more than one game.
+#[tauri::command]
+pub async fn find_path_conflicts(
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+) -> Result<Vec<PathConflict>, String> {
+    Ok(path_conflicts(&config.read().await.game_paths))
+}
+
 // ─── Window controls ─────────────────────────────────────────────────────────
 
 #[tauri::command]
@@ -105,29 +249,72 @@ pub async fn window_close(app: AppHandle) -> Result<(), String> {
 
 // ─── Game management ─────────────────────────────────────────────────────────
 
+/// Start the game. `args` / `env` replace the game's saved launch options
+/// and are remembered for next time; omitted ones fall back to the saved
+/// values.
 #[tauri::command]
 pub async fn launch_game(
     game_id: String,
     install_path: String,
+    args: Option<Vec<String>>,
+    env: Option<HashMap<String, String>>,
     app: AppHandle,
+    config: State<'_, Arc<RwLock<crate::config::AppConfig>>>,
     state: State<'_, Arc<RwLock<AppState>>>,
 ) -> Result<(), String> {
-    use tauri_plugin_opener::OpenerExt;
-
     if state.read().await.running_games.contains_key(&game_id) {
         return Err("游戏已在运行中".into());
     }
 
-    let exe_path = game::require_game_exe(&game_id, &install_path).map_err(|e| e.to_string())?;
+    if args.is_some() || env.is_some() {
+        {
+            let mut c = config.write().await;
+            let options = c.launch_options.entry(game_id.clone()).or_default();
+            if let Some(args) = args {
+                options.args = args;
+            }
+            if let Some(env) = env {
+                options.env = env;
+            }
+        }
+        let c = config.read().await.clone();
+        if let Err(e) = crate::config::save_config(&app, &c).await {
+            log::warn!("[game] failed to save launch options: {e}");
+        }
+    }
+    let (exe_override, options) = {
+        let c = config.read().await;
+        (
+            c.exe_overrides.get(&game_id).cloned(),
+            c.launch_options.get(&game_id).cloned().unwrap_or_default(),
+        )
+    };
+    let exe_path = game::require_game_exe(&game_id, &install_path, exe_override.as_deref())
+        .map_err(|e| e.to_string())?;
 
     let exe_name = exe_path
         .file_name()
         .map(|n| n.to_string_lossy().to_lowercase())
         .unwrap_or_default();
 
-    app.opener()
-        .open_path(exe_path.to_string_lossy(), None::<&str>)
+    if let Some(cmd) = &options.pre_launch_cmd {
+        run_hook(&app, &game_id, HookKind::PreLaunch, cmd).await?;
+    }
+
+    log::info!(
+        "[game] launching {} args={:?} env={:?}",
+        exe_path.display(),
+        options.args,
+        options.env.keys().collect::<Vec<_>>()
+    );
+    let mut child = launch_command(&exe_path, &options)
+        .spawn()
         .map_err(|e| format!("无法启动 {}: {}", exe_path.display(), e))?;
+    // Reap the child when it exits; the game itself is tracked by name in
+    // monitor_game since launchers often hand off to another process.
+    tauri::async_runtime::spawn_blocking(move || {
+        let _ = child.wait();
+    });
 
     let app_clone = app.clone();
     let state_arc = Arc::clone(state.inner());
@@ -139,6 +326,191 @@ pub async fn launch_game(
     Ok(())
 }
 
+/// Command that starts `exe_path` with the game's launch options, from the
+/// exe's directory (games load their data relative to the working dir).
+/// macOS `.app` bundles go through `open`, which forwards args and env.
+fn launch_command(
+    exe_path: &std::path::Path,
+    options: &crate::config::LaunchOptions,
+) -> std::process::Command {
+    let mut cmd = if cfg!(target_os = "macos") && exe_path.is_dir() {
+        let mut cmd = std::process::Command::new("open");
+        cmd.arg("-a").arg(exe_path);
+        for (key, value) in &options.env {
+            cmd.arg("--env").arg(format!("{key}={value}"));
+        }
+        cmd.arg("--args");
+        cmd
+    } else {
+        let mut cmd = std::process::Command::new(exe_path);
+        cmd.envs(&options.env);
+        cmd
+    };
+    cmd.args(&options.args);
+    if let Some(dir) = exe_path.parent() {
+        cmd.current_dir(dir);
+    }
+    cmd
+}
+
+/// Save a game's launch options (args, env and hook commands) without
+/// launching it.
+#[tauri::command]
+pub async fn set_launch_options(
+    game_id: String,
+    options: crate::config::LaunchOptions,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+) -> Result<(), String> {
+    {
+        let mut c = config.write().await;
+        c.launch_options.insert(game_id, options);
+    }
+    let c = config.read().await.clone();
+    crate::config::save_config(&app, &c)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// ─── Launch hooks ─────────────────────────────────────────────────────────────
+
+/// Hooks that don't finish in this time are killed and count as failed.
+const HOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HookKind {
+    PreLaunch,
+    PostExit,
+}
+
+/// Emitted as `game:hook-failed` when a hook command fails.
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookFailure {
+    pub game_id: String,
+    pub hook: HookKind,
+    pub command: String,
+    /// None when the command couldn't be started, timed out or was killed
+    pub exit_code: Option<i32>,
+    pub message: String,
+}
+
+/// Run a user hook through the platform shell and wait for it. The exit code
+/// is logged; failures are emitted as `game:hook-failed` and returned.
+async fn run_hook(app: &AppHandle, game_id: &str, hook: HookKind, cmd: &str) -> Result<(), String> {
+    let mut command = if cfg!(windows) {
+        let mut c = tokio::process::Command::new("cmd");
+        c.arg("/C").arg(cmd);
+        c
+    } else {
+        let mut c = tokio::process::Command::new("sh");
+        c.arg("-c").arg(cmd);
+        c
+    };
+    command.kill_on_drop(true);
+
+    log::info!("[game] {} {:?} hook: {}", game_id, hook, cmd);
+    let (exit_code, message) = match tokio::time::timeout(HOOK_TIMEOUT, command.output()).await {
+        Ok(Ok(out)) => {
+            log::info!(
+                "[game] {} {:?} hook exited with {}",
+                game_id,
+                hook,
+                out.status
+            );
+            if out.status.success() {
+                return Ok(());
+            }
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            (out.status.code(), stderr.trim().to_string())
+        }
+        Ok(Err(e)) => (None, format!("无法执行：{e}")),
+        Err(_) => (None, format!("超过 {} 秒未结束", HOOK_TIMEOUT.as_secs())),
+    };
+
+    log::warn!("[game] {} {:?} hook failed: {}", game_id, hook, message);
+    let failure = HookFailure {
+        game_id: game_id.to_string(),
+        hook,
+        command: cmd.to_string(),
+        exit_code,
+        message,
+    };
+    let _ = app.emit("game:hook-failed", &failure);
+    let label = match hook {
+        HookKind::PreLaunch => "启动前命令",
+        HookKind::PostExit => "退出后命令",
+    };
+    Err(match exit_code {
+        Some(code) => format!("{label}失败（退出码 {code}）：{}", failure.message),
+        None => format!("{label}失败：{}", failure.message),
+    })
+}
+
+/// How long `stop_game` waits after asking the game to quit before killing it.
+const STOP_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Terminate a running (possibly hung) game. Where the platform supports
+/// signals the game is asked to quit first and killed only if it is still
+/// running after STOP_GRACE_PERIOD; on Windows it is killed directly.
+#[tauri::command]
+pub async fn stop_game(
+    game_id: String,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<(), String> {
+    let pid = state
+        .read()
+        .await
+        .running_games
+        .get(&game_id)
+        .copied()
+        .ok_or_else(|| "游戏未在运行".to_string())?;
+
+    let mut sys = SysInfo::new();
+    let alive = |sys: &mut SysInfo| {
+        sys.refresh_processes(ProcessesToUpdate::Some(&[pid]));
+        sys.process(pid).is_some()
+    };
+
+    if alive(&mut sys) {
+        let graceful = sys
+            .process(pid)
+            .and_then(|p| p.kill_with(sysinfo::Signal::Term))
+            .unwrap_or(false);
+        if graceful {
+            log::info!("[game] asked {} (pid {}) to quit", game_id, pid);
+            let deadline = std::time::Instant::now() + STOP_GRACE_PERIOD;
+            while alive(&mut sys) && std::time::Instant::now() < deadline {
+                tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+            }
+        }
+        // Exited since the last refresh: nothing left to kill.
+        if let Some(proc) = sys.process(pid) {
+            log::warn!("[game] killing {} (pid {})", game_id, pid);
+            if !proc.kill() && alive(&mut sys) {
+                return Err(format!("无法结束游戏进程 {}", pid));
+            }
+        }
+    } else {
+        log::info!("[game] {} (pid {}) had already exited", game_id, pid);
+    }
+
+    // The exit watcher records the session; clear the state now so the UI
+    // doesn't wait for its next poll.
+    if release_running_game(&state, &game_id, pid).await {
+        let _ = app.emit(
+            "game:status",
+            GameStatus {
+                game_id,
+                running: false,
+            },
+        );
+    }
+    Ok(())
+}
+
 /// Background task: find the game process after launch, then watch for it to exit.
 async fn monitor_game(
     app: AppHandle,
@@ -153,15 +525,7 @@ async fn monitor_game(
     for _ in 0..20 {
         tokio::time::sleep(std::time::Duration::from_millis(500)).await;
         sys.refresh_processes(ProcessesToUpdate::All);
-        for (pid, proc) in sys.processes() {
-            let name = proc.name().to_string_lossy().to_lowercase();
-            if name == exe_name
-                || name.trim_end_matches(".exe") == exe_name.trim_end_matches(".exe")
-            {
-                game_pid = Some(*pid);
-                break;
-            }
-        }
+        game_pid = find_game_process(&sys, &exe_name);
         if game_pid.is_some() {
             break;
         }
@@ -178,11 +542,56 @@ async fn monitor_game(
         return;
     };
 
-    state
-        .write()
-        .await
-        .running_games
-        .insert(game_id.clone(), pid);
+    // The startup scan / reconcile may have picked it up first.
+    if !claim_running_game(&state, &game_id, pid).await {
+        return;
+    }
+    watch_game(app, state, sys, game_id, pid, unix_now()).await;
+}
+
+/// PID of a process whose name matches `exe_name` (case-insensitive, with or
+/// without `.exe`).
+fn find_game_process(sys: &SysInfo, exe_name: &str) -> Option<SysPid> {
+    sys.processes().iter().find_map(|(pid, proc)| {
+        let name = proc.name().to_string_lossy().to_lowercase();
+        (name == exe_name || name.trim_end_matches(".exe") == exe_name.trim_end_matches(".exe"))
+            .then_some(*pid)
+    })
+}
+
+/// Record `pid` as the game's process unless one is already tracked.
+/// Returns false when another watcher owns the game.
+async fn claim_running_game(state: &RwLock<AppState>, game_id: &str, pid: SysPid) -> bool {
+    let mut s = state.write().await;
+    if s.running_games.contains_key(game_id) {
+        return false;
+    }
+    s.running_games.insert(game_id.to_string(), pid);
+    true
+}
+
+/// Stop tracking `pid` as the game's process. Returns false, leaving the
+/// entry alone, when the game is now tracked under another pid (stopped and
+/// relaunched before this watcher noticed the exit).
+async fn release_running_game(state: &RwLock<AppState>, game_id: &str, pid: SysPid) -> bool {
+    let mut s = state.write().await;
+    if s.running_games.get(game_id) != Some(&pid) {
+        return false;
+    }
+    s.running_games.remove(game_id);
+    true
+}
+
+/// Emit `running`, wait for `pid` to exit, then clear the running state and
+/// record the play session.
+async fn watch_game(
+    app: AppHandle,
+    state: Arc<RwLock<AppState>>,
+    mut sys: SysInfo,
+    game_id: String,
+    pid: SysPid,
+    start_ts: i64,
+) {
     let _ = app.emit(
         "game:status",
         GameStatus {
@@ -203,14 +612,160 @@ async fn monitor_game(
         }
     }
 
-    let _ = app.emit(
-        "game:status",
-        GameStatus {
-            game_id: game_id.clone(),
-            running: false,
-        },
-    );
-    state.write().await.running_games.remove(&game_id);
+    if release_running_game(&state, &game_id, pid).await {
+        let _ = app.emit(
+            "game:status",
+            GameStatus {
+                game_id: game_id.clone(),
+                running: false,
+            },
+        );
+    }
+
+    let post_exit = app
+        .state::<Arc<RwLock<AppConfig>>>()
+        .read()
+        .await
+        .launch_options
+        .get(&game_id)
+        .and_then(|o| o.post_exit_cmd.clone());
+    if let Some(cmd) = post_exit {
+        // Already reported through the event; nothing else to fail here.
+        let _ = run_hook(&app, &game_id, HookKind::PostExit, &cmd).await;
+    }
+
+    if let Ok(path) = sessions_path(&app) {
+        let session = GameSession {
+            game_id,
+            start_ts,
+            end_ts: unix_now(),
+        };
+        if let Err(e) = sessions::record_session(&path, session) {
+            log::warn!("[game] failed to record session: {e}");
+        }
+    }
+}
+
+/// How often `reconcile_running_games` rescans the process list.
+const RUNNING_GAMES_SCAN_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Background task started at launch: pick up games started outside the
+/// launcher (before it opened, or from a desktop shortcut) so the UI shows
+/// them as running and `launch_game` refuses a duplicate. Scans once right
+/// away, then every RUNNING_GAMES_SCAN_INTERVAL.
+pub async fn reconcile_running_games(app: AppHandle) {
+    let config = Arc::clone(app.state::<Arc<RwLock<AppConfig>>>().inner());
+    let state = Arc::clone(app.state::<Arc<RwLock<AppState>>>().inner());
+    let mut sys = SysInfo::new();
+
+    loop {
+        let exe_names = configured_exe_names(&*config.read().await);
+        if !exe_names.is_empty() {
+            sys.refresh_processes(ProcessesToUpdate::All);
+            for (game_id, exe_name) in exe_names {
+                let Some(pid) = find_game_process(&sys, &exe_name) else {
+                    continue;
+                };
+                if !claim_running_game(&state, &game_id, pid).await {
+                    continue;
+                }
+                log::info!("[game] {} already running (pid {})", game_id, pid);
+                let start_ts = sys
+                    .process(pid)
+                    .map(|p| p.start_time() as i64)
+                    .filter(|ts| *ts > 0)
+                    .unwrap_or_else(unix_now);
+                tauri::async_runtime::spawn(watch_game(
+                    app.clone(),
+                    Arc::clone(&state),
+                    SysInfo::new(),
+                    game_id,
+                    pid,
+                    start_ts,
+                ));
+            }
+        }
+        tokio::time::sleep(RUNNING_GAMES_SCAN_INTERVAL).await;
+    }
+}
+
+/// Lowercase exe file name per game whose install path is configured and
+/// whose executable can be resolved. Games without a path (or with an
+/// ambiguous exe and no override) can't be matched and are skipped.
+fn configured_exe_names(config: &AppConfig) -> Vec<(String, String)> {
+    game::GAME_IDS
+        .iter()
+        .filter_map(|game_id| {
+            let install_path = config.game_paths.get(*game_id)?;
+            let exe_override = config.exe_overrides.get(*game_id).map(String::as_str);
+            let game::ExeLookup::Found(exe) =
+                game::find_game_exe(game_id, install_path, exe_override)
+            else {
+                return None;
+            };
+            let name = exe.file_name()?.to_string_lossy().to_lowercase();
+            Some((game_id.to_string(), name))
+        })
+        .collect()
+}
+
+fn sessions_path(app: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|d| d.join("sessions.json"))
+        .map_err(|e| e.to_string())
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Recent play sessions of a game, newest first (powers "最近游玩").
+#[tauri::command]
+pub async fn get_recent_sessions(
+    game_id: String,
+    limit: usize,
+    app: AppHandle,
+) -> Result<Vec<GameSession>, String> {
+    let path = sessions_path(&app)?;
+    Ok(sessions::recent_sessions(&path, &game_id, limit))
+}
+
+/// Executables the user can choose between when auto-detection is ambiguous.
+#[tauri::command]
+pub async fn list_game_exe_candidates(game_id: String, install_path: String) -> Vec<String> {
+    match game::find_game_exe(&game_id, &install_path, None) {
+        game::ExeLookup::Found(p) => vec![p.to_string_lossy().into_owned()],
+        game::ExeLookup::Ambiguous(candidates) => candidates
+            .into_iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect(),
+        game::ExeLookup::NotFound => Vec::new(),
+    }
+}
+
+/// Persist the executable `launch_game` should start for this game.
+#[tauri::command]
+pub async fn select_game_exe(
+    game_id: String,
+    path: String,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<crate::config::AppConfig>>>,
+) -> Result<(), String> {
+    if !std::path::Path::new(&path).is_file() {
+        return Err(format!("文件不存在：{path}"));
+    }
+    {
+        let mut c = config.write().await;
+        c.exe_overrides.insert(game_id, path);
+    }
+    let c = config.read().await.clone();
+    crate::config::save_config(&app, &c)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -218,13 +773,27 @@ pub async fn validate_game_path(game_id: String, path: String) -> bool {
     game::check_game_installed(&game_id, &path)
 }
 
+/// Install directories found in the registry, the launcher's default
+/// locations and Steam libraries, for the UI to offer in one click. Empty
+/// when nothing is found.
+#[tauri::command]
+pub async fn auto_detect_game_paths() -> Result<Vec<game::DetectedInstall>, String> {
+    tokio::task::spawn_blocking(game::detect_install_paths)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn fetch_game_version(
     game_id: String,
+    platform: Option<Platform>,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
     state: State<'_, Arc<RwLock<AppState>>>,
 ) -> Result<Option<String>, String> {
+    let platform = platform.unwrap_or_else(Platform::host);
+    let channel = config.read().await.settings.game_channel(&game_id);
     let s = state.read().await;
-    game::fetch_latest_version(&game_id, &s.http_client)
+    game::fetch_latest_version(&game_id, channel, platform, &s.http_client)
         .await
         .map_err(|e| e.to_string())
 }
@@ -276,55 +845,125 @@ pub async fn select_download_path(app: AppHandle) -> Result<Option<String>, Stri
 
 // ─── Game download (Hypergryph API) ──────────────────────────────────────────
 
-/// Fetch the full-install pack manifest from Hypergryph API.
+/// Fetch the full-install pack manifest from Hypergryph API for `platform`
+/// (default: the host OS). Served from a short-lived cache shared with the
+/// install commands; `force_refresh` re-queries the API.
 #[tauri::command]
 pub async fn fetch_game_manifest(
     game_id: String,
+    platform: Option<Platform>,
+    force_refresh: Option<bool>,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
     state: State<'_, Arc<RwLock<AppState>>>,
 ) -> Result<GameManifest, String> {
-    let s = state.read().await;
-    game::fetch_game_manifest(&game_id, &s.http_client)
+    let channel = config.read().await.settings.game_channel(&game_id);
+    let platform = platform.unwrap_or_else(Platform::host);
+    let client = state.read().await.http_client.clone();
+    let refresh = force_refresh.unwrap_or(false);
+    game::fetch_game_manifest_cached(&game_id, channel, platform, &client, refresh)
         .await
         .map_err(|e| e.to_string())
 }
 
 /// Start downloading all packs for a full game install.
 /// Each pack becomes a separate download task; progress is emitted via events.
-/// Returns a list of task IDs (one per pack).
+/// Installs above the configured size ceiling are refused unless
+/// `confirm_large` is set. Returns a list of task IDs (one per pack).
 #[tauri::command]
 pub async fn start_game_install(
     game_id: String,
     dest_dir: String,
+    confirm_large: Option<bool>,
     app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
     state: State<'_, Arc<RwLock<AppState>>>,
 ) -> Result<Vec<String>, String> {
-    let manifest = {
+    let channel = config.read().await.settings.game_channel(&game_id);
+    let mut manifest = {
         let s = state.read().await;
-        game::fetch_game_manifest(&game_id, &s.http_client)
+        game::fetch_game_manifest_cached(&game_id, channel, Platform::host(), &s.http_client, false)
             .await
             .map_err(|e| e.to_string())?
     };
+    game::add_mirrors(&mut manifest, &config.read().await.settings.mirror_bases);
 
-    let mut task_ids = Vec::with_capacity(manifest.packs.len());
-
+    // Guard against garbage sizes from a malformed manifest filling the disk.
+    let ceiling_gb = config.read().await.settings.max_install_size_gb;
+    let ceiling = ceiling_gb.saturating_mul(1024 * 1024 * 1024);
     log::info!(
-        "[install] game={} packs={} dest={}",
+        "[install] game={} total_size={} ceiling={}",
         game_id,
-        manifest.packs.len(),
-        dest_dir
+        manifest.total_size,
+        ceiling
     );
+    if manifest.total_size > ceiling && !confirm_large.unwrap_or(false) {
+        return Err(format!(
+            "安装包总大小 {:.1} GB 超过上限 {} GB，请确认后重试",
+            manifest.total_size as f64 / 1024.0 / 1024.0 / 1024.0,
+            ceiling_gb
+        ));
+    }
 
-    for pack in &manifest.packs {
-        let dest_path = format!("{}/{}", dest_dir.trim_end_matches('/'), pack.filename);
-        log::info!(
-            "[install] pack={} size={} dest={}",
-            pack.filename,
-            pack.size,
-            dest_path
+    // Pack filenames come from CDN URLs; refuse anything that could resolve
+    // outside the download directory before creating any task.
+    check_pack_filenames(&manifest)?;
+
+    let (dest_dir, pack_dir, keep_archives) = {
+        let c = config.read().await;
+        let (dest_dir, pack_dir) = install_dirs(&c.settings, &game_id, dest_dir);
+        (dest_dir, pack_dir, c.settings.keeps_archives())
+    };
+
+    // Fail here rather than with a write error halfway through the download.
+    let space = install_space(&manifest, &pack_dir, &dest_dir, keep_archives);
+    if let Some(v) = space.volumes.iter().find(|v| !v.sufficient) {
+        let available = v.available.unwrap_or(0);
+        log::error!(
+            "[install] not enough space on {}: required={} available={}",
+            v.path,
+            v.required,
+            available
         );
-        let task_id = {
-            let s = state.read().await;
-            s.download_manager
+        return Err(format!(
+            "磁盘空间不足（{}）：需要 {:.1} GB，可用 {:.1} GB",
+            v.path,
+            v.required as f64 / 1024.0 / 1024.0 / 1024.0,
+            available as f64 / 1024.0 / 1024.0 / 1024.0
+        ));
+    }
+
+    {
+        let mut c = config.write().await;
+        c.game_paths.insert(game_id.clone(), dest_dir.clone());
+    }
+    let c = config.read().await.clone();
+    crate::config::save_config(&app, &c)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut task_ids = Vec::with_capacity(manifest.packs.len());
+    let tracker = InstallTracker::default();
+    reset_install_group(&state, &game_id).await;
+
+    log::info!(
+        "[install] game={} packs={} dest={} pack_dir={}",
+        game_id,
+        manifest.packs.len(),
+        dest_dir,
+        pack_dir.display()
+    );
+
+    for pack in &manifest.packs {
+        let dest_path = pack_dir.join(&pack.filename).to_string_lossy().into_owned();
+        log::info!(
+            "[install] pack={} size={} dest={}",
+            pack.filename,
+            pack.size,
+            dest_path
+        );
+        let task_id = {
+            let s = state.read().await;
+            s.download_manager
                 .create_task(
                     game_id.clone(),
                     pack.filename.clone(),
@@ -333,19 +972,35 @@ pub async fn start_game_install(
                     Some(pack.size), // known from manifest — skips HEAD
                     None,
                     Some(pack.md5.clone()),
+                    HashMap::new(),
                 )
                 .await
                 .map_err(|e| e.to_string())?
         };
+        if !pack.mirrors.is_empty() {
+            let s = state.read().await;
+            s.download_manager
+                .set_task_mirrors(&task_id, pack.mirrors.clone())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        tracker.add(&task_id, 0, pack.size);
+        state
+            .write()
+            .await
+            .install_groups
+            .entry(game_id.clone())
+            .or_default()
+            .insert(task_id.clone());
 
-        let app_clone = app.clone();
         let tid = task_id.clone();
         {
             let s = state.read().await;
             s.download_manager
-                .start_task(task_id.clone(), move |progress: DownloadProgress| {
-                    let _ = app_clone.emit("download:progress", &progress);
-                })
+                .start_task(
+                    task_id.clone(),
+                    install_progress_callback(&app, &game_id, &tracker),
+                )
                 .await
                 .map_err(|e| e.to_string())?;
         }
@@ -356,6 +1011,438 @@ pub async fn start_game_install(
     Ok(task_ids)
 }
 
+/// Install directory and pack download directory for an install into
+/// `dest_dir`.
+fn install_dirs(
+    settings: &AppSettings,
+    game_id: &str,
+    dest_dir: String,
+) -> (String, std::path::PathBuf) {
+    // Optionally nest the install under a game-named subdirectory. Extraction
+    // targets the recorded game path, so this also moves the extraction target.
+    let nested = settings
+        .nest_install_dir
+        .get(game_id)
+        .copied()
+        .unwrap_or(false);
+    let dest_dir = if nested {
+        std::path::Path::new(&dest_dir)
+            .join(game_id)
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        dest_dir
+    };
+
+    let pack_dir = pack_dir(settings, game_id, &dest_dir);
+    (dest_dir, pack_dir)
+}
+
+/// Directory packs for an install or patch into `dest_dir` download to.
+/// Packs may land on a separate (faster) drive and are extracted into
+/// `dest_dir` afterwards; extraction deletes them once unpacked.
+fn pack_dir(settings: &AppSettings, game_id: &str, dest_dir: &str) -> std::path::PathBuf {
+    match settings.temp_download_dir.as_deref() {
+        Some(temp) if !temp.is_empty() => std::path::Path::new(temp).join(game_id),
+        _ => std::path::PathBuf::from(dest_dir),
+    }
+}
+
+/// Free space kept on top of the estimate.
+const INSTALL_SPACE_MARGIN: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Unpacked size relative to the packs. The manifest doesn't list it; game
+/// assets are mostly pre-compressed, so the zips shrink them only a little.
+const EXTRACTED_SIZE_RATIO: f64 = 1.1;
+
+/// Space needed on one disk touched by an install.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeSpace {
+    pub path: String,
+    pub required: u64,
+    /// None when the disk couldn't be queried
+    pub available: Option<u64>,
+    pub sufficient: bool,
+}
+
+/// Result of `check_install_space`. The top-level numbers describe the disk
+/// with the least headroom; `volumes` lists every disk involved.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallSpace {
+    pub required: u64,
+    pub available: Option<u64>,
+    pub sufficient: bool,
+    pub volumes: Vec<VolumeSpace>,
+}
+
+/// Peak disk usage of downloading `manifest` into `pack_dir` and extracting
+/// into `dest_dir`. Archives are deleted one by one as they are unpacked, so
+/// when both share a disk the peak is roughly everything unpacked plus the
+/// last archive — or all archives too when `keep_archives` is set.
+fn install_space(
+    manifest: &GameManifest,
+    pack_dir: &std::path::Path,
+    dest_dir: &str,
+    keep_archives: bool,
+) -> InstallSpace {
+    use crate::download::manager::disk_space;
+
+    let packs = manifest.total_size;
+    let largest = manifest.packs.iter().map(|p| p.size).max().unwrap_or(0);
+    let extracted = (packs as f64 * EXTRACTED_SIZE_RATIO) as u64;
+    let dest_path = std::path::Path::new(dest_dir);
+
+    let dest_disk = disk_space(dest_path);
+    let pack_disk = disk_space(pack_dir);
+    let shared = match (&dest_disk, &pack_disk) {
+        (Some((a, _)), Some((b, _))) => a == b,
+        _ => pack_dir == dest_path,
+    };
+
+    let mut needs = Vec::new();
+    if shared {
+        let archives = if keep_archives { packs } else { largest };
+        needs.push((dest_dir.to_string(), extracted + archives, dest_disk));
+    } else {
+        needs.push((pack_dir.to_string_lossy().into_owned(), packs, pack_disk));
+        needs.push((dest_dir.to_string(), extracted, dest_disk));
+    }
+
+    let volumes: Vec<VolumeSpace> = needs
+        .into_iter()
+        .map(|(path, need, disk)| {
+            let required = need + INSTALL_SPACE_MARGIN;
+            let available = disk.map(|(_, free)| free);
+            VolumeSpace {
+                path,
+                required,
+                // Unknown free space must not block the install.
+                sufficient: available.is_none_or(|free| free >= required),
+                available,
+            }
+        })
+        .collect();
+
+    let headroom = |v: &VolumeSpace| v.available.map(|a| a as i128 - v.required as i128);
+    let tightest = volumes
+        .iter()
+        .min_by_key(|v| headroom(v).unwrap_or(i128::MAX))
+        .expect("at least one volume");
+    InstallSpace {
+        required: tightest.required,
+        available: tightest.available,
+        sufficient: volumes.iter().all(|v| v.sufficient),
+        volumes,
+    }
+}
+
+/// Disk space an install of `game_id` into `dest_dir` needs, for the UI to
+/// check before calling `start_game_install`.
+#[tauri::command]
+pub async fn check_install_space(
+    game_id: String,
+    dest_dir: String,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<InstallSpace, String> {
+    let channel = config.read().await.settings.game_channel(&game_id);
+    let manifest = {
+        let s = state.read().await;
+        game::fetch_game_manifest_cached(&game_id, channel, Platform::host(), &s.http_client, false)
+            .await
+            .map_err(|e| e.to_string())?
+    };
+    let c = config.read().await;
+    let (dest_dir, pack_dir) = install_dirs(&c.settings, &game_id, dest_dir);
+    Ok(install_space(
+        &manifest,
+        &pack_dir,
+        &dest_dir,
+        c.settings.keeps_archives(),
+    ))
+}
+
+/// Combined progress of one game's install, emitted as `game:install_progress`.
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallProgress {
+    pub game_id: String,
+    pub downloaded_size: u64,
+    pub total_size: u64,
+    pub progress: f64,
+    /// Sum of the group's task speeds, bytes/s
+    pub speed: u64,
+    pub eta_secs: Option<u64>,
+}
+
+impl InstallProgress {
+    fn new(game_id: &str, downloaded_size: u64, total_size: u64, speed: u64) -> Self {
+        let remaining = total_size.saturating_sub(downloaded_size);
+        Self {
+            game_id: game_id.to_string(),
+            downloaded_size,
+            total_size,
+            progress: if total_size == 0 {
+                0.0
+            } else {
+                downloaded_size as f64 / total_size as f64 * 100.0
+            },
+            speed,
+            eta_secs: (speed > 0).then(|| remaining.div_ceil(speed)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TaskProgress {
+    downloaded: u64,
+    total: u64,
+    speed: u64,
+}
+
+/// Latest progress per task of one install group, shared by the group's
+/// progress callbacks.
+#[derive(Clone, Default)]
+struct InstallTracker(Arc<std::sync::Mutex<HashMap<String, TaskProgress>>>);
+
+impl InstallTracker {
+    fn from_tasks<'a>(tasks: impl IntoIterator<Item = &'a DownloadTask>) -> Self {
+        let tracker = Self::default();
+        for t in tasks {
+            tracker.add(&t.id, t.downloaded_size, t.total_size);
+        }
+        tracker
+    }
+
+    fn add(&self, task_id: &str, downloaded: u64, total: u64) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).insert(
+            task_id.to_string(),
+            TaskProgress {
+                downloaded,
+                total,
+                speed: 0,
+            },
+        );
+    }
+
+    fn update(&self, game_id: &str, p: &DownloadProgress) -> InstallProgress {
+        let mut tasks = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let speed = if p.status == DownloadStatus::Downloading {
+            p.speed
+        } else {
+            0
+        };
+        tasks.insert(
+            p.task_id.clone(),
+            TaskProgress {
+                downloaded: p.downloaded_size,
+                total: p.total_size,
+                speed,
+            },
+        );
+        InstallProgress::new(
+            game_id,
+            tasks.values().map(|t| t.downloaded).sum(),
+            tasks.values().map(|t| t.total).sum(),
+            tasks.values().map(|t| t.speed).sum(),
+        )
+    }
+}
+
+/// Progress callback for an install task: forwards `download:progress` and
+/// emits the group total as `game:install_progress`.
+fn install_progress_callback(
+    app: &AppHandle,
+    game_id: &str,
+    tracker: &InstallTracker,
+) -> impl Fn(DownloadProgress) + Send + Sync + 'static {
+    let app = app.clone();
+    let game_id = game_id.to_string();
+    let tracker = tracker.clone();
+    move |progress: DownloadProgress| {
+        let _ = app.emit("download:progress", &progress);
+        let _ = app.emit("game:install_progress", tracker.update(&game_id, &progress));
+    }
+}
+
+/// Tasks of a game's install: its tracked group, or after a restart (groups
+/// aren't persisted) every unfinished task of the game, which then becomes
+/// the group.
+async fn install_tasks(state: &RwLock<AppState>, game_id: &str) -> Vec<DownloadTask> {
+    let mut s = state.write().await;
+    let all = s.download_manager.get_tasks().await;
+    let tasks: Vec<DownloadTask> = match s.install_groups.get(game_id) {
+        Some(ids) => all.into_iter().filter(|t| ids.contains(&t.id)).collect(),
+        None => all
+            .into_iter()
+            .filter(|t| t.game_id == game_id && t.status != DownloadStatus::Completed)
+            .collect(),
+    };
+    if !tasks.is_empty() {
+        s.install_groups.insert(
+            game_id.to_string(),
+            tasks.iter().map(|t| t.id.clone()).collect(),
+        );
+    }
+    tasks
+}
+
+/// Start a fresh group for a new install, patch or repair of `game_id`, so
+/// tasks left over from the previous one don't count towards its progress
+/// or get suspended and cancelled with it.
+async fn reset_install_group(state: &RwLock<AppState>, game_id: &str) {
+    state
+        .write()
+        .await
+        .install_groups
+        .insert(game_id.to_string(), HashSet::new());
+}
+
+/// Remaining time for a game's install from its tasks' current speeds.
+#[tauri::command]
+pub async fn get_install_eta(
+    game_id: String,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Option<InstallProgress>, String> {
+    let tasks = install_tasks(&state, &game_id).await;
+    if tasks.is_empty() {
+        return Ok(None);
+    }
+    let downloaded = tasks.iter().map(|t| t.downloaded_size).sum();
+    let total = tasks.iter().map(|t| t.total_size).sum();
+    let speed = tasks
+        .iter()
+        .filter(|t| t.status == DownloadStatus::Downloading)
+        .map(|t| t.speed)
+        .sum();
+    Ok(Some(InstallProgress::new(
+        &game_id, downloaded, total, speed,
+    )))
+}
+
+/// Cancel a game's install: every task in its group is cancelled and the
+/// group dropped. Other games' installs are unaffected.
+#[tauri::command]
+pub async fn cancel_game_install(
+    game_id: String,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<String>, String> {
+    let tasks = install_tasks(&state, &game_id).await;
+    let s = state.read().await;
+    let mut cancelled = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        if task.status == DownloadStatus::Completed {
+            continue;
+        }
+        s.download_manager
+            .cancel_task(&task.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        cancelled.push(task.id);
+    }
+    drop(s);
+    state.write().await.install_groups.remove(&game_id);
+    log::info!(
+        "[install] cancelled game={} tasks={}",
+        game_id,
+        cancelled.len()
+    );
+    Ok(cancelled)
+}
+
+/// Pause every unfinished task of a game install, keeping completed and
+/// partial packs on disk so the install can be resumed later.
+/// Returns the IDs of the tasks that were paused.
+#[tauri::command]
+pub async fn suspend_game_install(
+    game_id: String,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<String>, String> {
+    let tasks = install_tasks(&state, &game_id).await;
+    let s = state.read().await;
+    let mut paused = Vec::new();
+    for task in tasks {
+        if !matches!(
+            task.status,
+            DownloadStatus::Pending | DownloadStatus::Downloading | DownloadStatus::Verifying
+        ) {
+            continue;
+        }
+        s.download_manager
+            .pause_task(&task.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        paused.push(task.id);
+    }
+    log::info!(
+        "[install] suspended game={} tasks={}",
+        game_id,
+        paused.len()
+    );
+    Ok(paused)
+}
+
+/// Resume every paused task of a previously suspended game install.
+/// Returns the IDs of the tasks that were restarted.
+#[tauri::command]
+pub async fn resume_game_install(
+    game_id: String,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<String>, String> {
+    let tasks = install_tasks(&state, &game_id).await;
+    let tracker = InstallTracker::from_tasks(&tasks);
+    let s = state.read().await;
+    let mut resumed = Vec::new();
+    for task in tasks {
+        if !matches!(
+            task.status,
+            DownloadStatus::Paused | DownloadStatus::Pending
+        ) {
+            continue;
+        }
+        s.download_manager
+            .start_task(
+                task.id.clone(),
+                install_progress_callback(&app, &game_id, &tracker),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        resumed.push(task.id);
+    }
+    log::info!("[install] resumed game={} tasks={}", game_id, resumed.len());
+    Ok(resumed)
+}
+
+/// Error for the first pack of `manifest` whose filename isn't safe.
+fn check_pack_filenames(manifest: &GameManifest) -> Result<(), String> {
+    match manifest
+        .packs
+        .iter()
+        .find(|p| !is_safe_pack_filename(&p.filename))
+    {
+        Some(bad) => {
+            log::error!("[install] unsafe pack filename {:?}", bad.filename);
+            Err(format!("安装包文件名不安全，已拒绝安装：{}", bad.filename))
+        }
+        None => Ok(()),
+    }
+}
+
+/// A pack filename must be a single plain path component: no separators,
+/// drive prefixes, `.`/`..`, or control characters.
+fn is_safe_pack_filename(name: &str) -> bool {
+    use std::path::Component;
+
+    let mut components = std::path::Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\', ':'])
+        && !name.chars().any(char::is_control)
+}
+
 // ─── Generic download management ─────────────────────────────────────────────
 
 #[tauri::command]
@@ -366,286 +1453,2062 @@ pub async fn get_download_tasks(
     Ok(s.download_manager.get_tasks().await)
 }
 
+/// Completed downloads, newest first, including earlier sessions.
+#[tauri::command]
+pub async fn get_download_history(
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<DownloadTask>, String> {
+    let s = state.read().await;
+    Ok(s.download_manager.get_history().await)
+}
+
+/// One task with per-chunk progress, remaining bytes, ETA and active mirror.
+#[tauri::command]
+pub async fn get_task_detail(
+    task_id: String,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Option<TaskDetail>, String> {
+    let s = state.read().await;
+    Ok(s.download_manager.get_task_detail(&task_id).await)
+}
+
+#[tauri::command]
+pub async fn start_download_task(
+    task_id: String,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<(), String> {
+    let s = state.read().await;
+    let app_clone = app.clone();
+    s.download_manager
+        .start_task(task_id, move |progress: DownloadProgress| {
+            let _ = app_clone.emit("download:progress", &progress);
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Run a failed task again: resume from disk, or start over if the file was
+/// found corrupt. Emits the reset state before restarting.
+#[tauri::command]
+pub async fn retry_download_task(
+    task_id: String,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<(), String> {
+    let s = state.read().await;
+    let reset = s
+        .download_manager
+        .reset_failed_task(&task_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit("download:progress", &reset);
+    let app_clone = app.clone();
+    s.download_manager
+        .start_task(task_id, move |progress: DownloadProgress| {
+            let _ = app_clone.emit("download:progress", &progress);
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn pause_download_task(
+    task_id: String,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<(), String> {
+    let s = state.read().await;
+    s.download_manager
+        .pause_task(&task_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn cancel_download_task(
+    task_id: String,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<(), String> {
+    let s = state.read().await;
+    s.download_manager
+        .cancel_task(&task_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Re-check a completed download's file (fast BLAKE3 path when available).
+#[tauri::command]
+pub async fn reverify_download(
+    task_id: String,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<(), String> {
+    let s = state.read().await;
+    s.download_manager
+        .reverify_task(&task_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Resolve (and cache) the real filename for a download URL via Content-Disposition.
+#[tauri::command]
+pub async fn resolve_download_filename(
+    url: String,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<String, String> {
+    let s = state.read().await;
+    Ok(s.download_manager
+        .resolve_filename(&url, &reqwest::header::HeaderMap::new())
+        .await)
+}
+
+/// Probe candidate mirror URLs for latency and throughput (results cached briefly).
+#[tauri::command]
+pub async fn probe_mirrors(
+    urls: Vec<String>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<MirrorResult>, String> {
+    let s = state.read().await;
+    Ok(s.download_manager
+        .probe_mirrors(&urls, &reqwest::header::HeaderMap::new())
+        .await)
+}
+
+/// Check that a proxy both answers and can tunnel HTTPS to the game's CDN.
+#[tauri::command]
+pub async fn test_proxy(
+    proxy_url: String,
+    game_id: Option<String>,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<crate::net::ProxyTestResult, String> {
+    let game_id = game_id.unwrap_or_else(|| game::GAME_IDS[0].to_string());
+    let client = state.read().await.http_client.clone();
+    let (network, channel) = {
+        let c = config.read().await;
+        (
+            c.settings.network_options(),
+            c.settings.game_channel(&game_id),
+        )
+    };
+
+    // Test against the host packs are served from; the version endpoint is
+    // the fallback when the manifest can't be fetched directly.
+    let platform = Platform::host();
+    let manifest = game::fetch_game_manifest_cached(&game_id, channel, platform, &client, false);
+    let cdn_url = match manifest.await {
+        Ok(m) => m.packs.first().map(|p| p.url.clone()),
+        Err(e) => {
+            log::warn!("[net] manifest unavailable for proxy test: {}", e);
+            None
+        }
+    }
+    .or_else(|| game::manager::version_url(&game_id, channel, platform))
+    .ok_or_else(|| format!("未知游戏：{}", game_id))?;
+
+    crate::net::test_proxy(&proxy_url, &cdn_url, &network)
+        .await
+        .map_err(|e| format!("代理测试失败：{}", e))
+}
+
+/// Measure download speed against the game's CDN by fetching a slice of its
+/// largest pack.
+#[tauri::command]
+pub async fn benchmark_download(
+    game_id: String,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<SpeedBenchmark, String> {
+    let channel = config.read().await.settings.game_channel(&game_id);
+    let s = state.read().await;
+    let manifest = game::fetch_game_manifest_cached(
+        &game_id,
+        channel,
+        Platform::host(),
+        &s.http_client,
+        false,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let pack = manifest
+        .packs
+        .iter()
+        .max_by_key(|p| p.size)
+        .ok_or_else(|| format!("{} 的清单中没有安装包", game_id))?;
+    s.download_manager
+        .benchmark(&pack.url)
+        .await
+        .map_err(|e| format!("测速失败：{}", e))
+}
+
+/// Adjust download-module log verbosity without restarting:
+/// "off" | "error" | "warn" | "info" | "debug" | "trace".
+#[tauri::command]
+pub async fn set_download_log_level(level: String) -> Result<(), String> {
+    let level: log::LevelFilter = level
+        .parse()
+        .map_err(|_| format!("无效的日志级别：{level}"))?;
+    crate::download::logging::set_level(level);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_download_log_level() -> String {
+    crate::download::logging::level().to_string().to_lowercase()
+}
+
+/// Current download slot usage: max permits, permits in use, and queued tasks.
+#[tauri::command]
+pub async fn get_concurrency_status(
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<ConcurrencyStatus, String> {
+    let s = state.read().await;
+    Ok(s.download_manager.concurrency_status())
+}
+
+// ─── Cache management ─────────────────────────────────────────────────────────
+
+/// Delete the hot-update cache directory for a game.
+/// Arknights / Endfield both store cached assets in `HotUpdate/`.
+#[tauri::command]
+pub async fn clear_game_cache(game_id: String, install_path: String) -> Result<(), String> {
+    let cache_dirs: &[&str] = match game_id.as_str() {
+        "arknights" | "endfield" => &["HotUpdate"],
+        _ => return Err(format!("未知游戏：{}", game_id)),
+    };
+
+    let base = std::path::Path::new(&install_path);
+    for dir_name in cache_dirs {
+        let path = base.join(dir_name);
+        if path.is_dir() {
+            tokio::fs::remove_dir_all(&path)
+                .await
+                .map_err(|e| format!("清除 {} 失败：{}", dir_name, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Bytes used by the launcher's own files (not game installs).
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageBreakdown {
+    pub config: u64,
+    /// downloads.json plus the extraction journal
+    pub downloads: u64,
+    /// game_id → gacha data file size
+    pub gacha: HashMap<String, u64>,
+    pub snapshots: u64,
+    pub logs: u64,
+    pub cache: u64,
+    pub total: u64,
+}
+
+#[tauri::command]
+pub async fn get_app_storage_breakdown(app: AppHandle) -> Result<StorageBreakdown, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let log_dir = app.path().app_log_dir().ok();
+    let cache_dir = app.path().app_cache_dir().ok();
+
+    tokio::task::spawn_blocking(move || {
+        let gacha: HashMap<String, u64> = game::GAME_IDS
+            .iter()
+            .map(|id| {
+                let size = path_size(&data_dir.join(format!("{id}_gacha.json")));
+                (id.to_string(), size)
+            })
+            .collect();
+        let mut b = StorageBreakdown {
+            config: path_size(&data_dir.join("config.json")),
+            downloads: path_size(&data_dir.join("downloads.json"))
+                + path_size(&data_dir.join("extract_journal.json")),
+            gacha,
+            snapshots: path_size(&data_dir.join(SNAPSHOTS_DIR)),
+            logs: log_dir.as_deref().map(path_size).unwrap_or(0),
+            cache: cache_dir.as_deref().map(path_size).unwrap_or(0),
+            total: 0,
+        };
+        b.total =
+            b.config + b.downloads + b.gacha.values().sum::<u64>() + b.snapshots + b.logs + b.cache;
+        b
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Remove logs, cached files and snapshots. Config, download state and gacha
+/// data are left alone. Returns the number of bytes freed.
+#[tauri::command]
+pub async fn clear_app_cache(app: AppHandle) -> Result<u64, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let mut targets = vec![data_dir.join(SNAPSHOTS_DIR)];
+    targets.extend(app.path().app_log_dir().ok());
+    targets.extend(app.path().app_cache_dir().ok());
+
+    let mut freed = 0;
+    for dir in targets {
+        if !dir.is_dir() {
+            continue;
+        }
+        freed += path_size(&dir);
+        // Clear contents rather than the directory itself: the log plugin
+        // keeps its directory (and current file handle) open.
+        let mut entries = tokio::fs::read_dir(&dir)
+            .await
+            .map_err(|e| format!("读取 {} 失败：{}", dir.display(), e))?;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let res = if path.is_dir() {
+                tokio::fs::remove_dir_all(&path).await
+            } else {
+                tokio::fs::remove_file(&path).await
+            };
+            if let Err(e) = res {
+                log::warn!("[cache] could not remove {}: {}", path.display(), e);
+            }
+        }
+    }
+    log::info!("[cache] cleared app cache, freed {} bytes", freed);
+    Ok(freed)
+}
+
+/// Size of a file, or the recursive size of a directory; 0 if missing.
+fn path_size(path: &std::path::Path) -> u64 {
+    let Ok(meta) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| path_size(&e.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+// ─── Version / update check ───────────────────────────────────────────────────
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckUpdateResult {
+    pub local_version: Option<String>,
+    pub latest_version: Option<String>,
+    pub update_available: bool,
+}
+
+/// Correct the detected local version when the game's own version file is
+/// missing or unreadable. An empty `version` clears the correction.
+#[tauri::command]
+pub async fn set_local_version(
+    game_id: String,
+    install_path: String,
+    version: String,
+) -> Result<Option<String>, String> {
+    if !std::path::Path::new(&install_path).is_dir() {
+        return Err(format!("安装目录不存在：{install_path}"));
+    }
+    game::write_local_version(&install_path, &version).map_err(|e| e.to_string())?;
+    Ok(game::read_local_version(&game_id, &install_path))
+}
+
+/// Compare the installed game version against the latest available on Hypergryph's CDN.
+/// The latest version is cached briefly; `refresh` forces a new query.
+#[tauri::command]
+pub async fn check_game_update(
+    game_id: String,
+    install_path: String,
+    refresh: Option<bool>,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<CheckUpdateResult, String> {
+    let local = game::read_local_version(&game_id, &install_path);
+    let client = state.read().await.http_client.clone();
+    let channel = config.read().await.settings.game_channel(&game_id);
+    let latest = game::latest_version_cached(&game_id, channel, &client, refresh.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())?;
+    let update_available = match (&local, &latest) {
+        (Some(l), Some(r)) => l != r,
+        _ => false,
+    };
+    Ok(CheckUpdateResult {
+        local_version: local,
+        latest_version: latest,
+        update_available,
+    })
+}
+
+/// `check_game_update` bypassing the version cache, e.g. right after an
+/// announcement.
+#[tauri::command]
+pub async fn force_check_update(
+    game_id: String,
+    install_path: String,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<CheckUpdateResult, String> {
+    check_game_update(game_id, install_path, Some(true), config, state).await
+}
+
+/// Fetch the incremental patch manifest from the current version to the latest.
+/// Returns `None` if a patch is unavailable (clean install required).
+#[tauri::command]
+pub async fn fetch_update_manifest(
+    game_id: String,
+    current_version: String,
+    platform: Option<Platform>,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Option<GameManifest>, String> {
+    let channel = config.read().await.settings.game_channel(&game_id);
+    let platform = platform.unwrap_or_else(Platform::host);
+    let s = state.read().await;
+    game::fetch_patch_manifest(
+        &game_id,
+        channel,
+        platform,
+        &current_version,
+        &s.http_client,
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Bytes to download for the patch from `current_version` to latest, for the
+/// update confirmation dialog. None means no patch exists (full reinstall).
+#[tauri::command]
+pub async fn get_update_download_size(
+    game_id: String,
+    current_version: String,
+    platform: Option<Platform>,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Option<u64>, String> {
+    let channel = config.read().await.settings.game_channel(&game_id);
+    let platform = platform.unwrap_or_else(Platform::host);
+    let client = state.read().await.http_client.clone();
+    let patch = game::fetch_patch_manifest(&game_id, channel, platform, &current_version, &client)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(patch.map(|m| m.total_size))
+}
+
+/// Outcome of `start_game_patch`.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchOutcome {
+    pub from_version: String,
+    /// Version the install is at now (unchanged unless a patch was applied)
+    pub to_version: String,
+    /// A patch was downloaded and extracted over the install
+    pub applied: bool,
+    /// No patch exists from the local version; only a clean install
+    /// (`start_game_install`) can update it
+    pub clean_install_required: bool,
+    /// Patch archives left on disk
+    pub kept_archives: Vec<String>,
+    /// Files still missing or mismatched after patching, for
+    /// `repair_game_files`
+    pub failed: Vec<BadFile>,
+}
+
+/// Update an install in place with the incremental patch from its local
+/// version: download the patch packs, extract them over the install, record
+/// the new version and verify against the latest file index. Patch packs
+/// replace whole files, so they are applied as an overlay, through the same
+/// journaled extraction as an install. Download progress is emitted like an
+/// install and the tasks join the game's install group, so
+/// `cancel_game_install` stops them; `cancel_extraction` stops the extraction.
+/// A failed or paused download aborts the update with its tasks left in the
+/// list, and the next call reuses any pack that already finished.
+#[tauri::command]
+pub async fn start_game_patch(
+    game_id: String,
+    install_path: String,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<PatchOutcome, String> {
+    {
+        let s = state.read().await;
+        if s.running_games.contains_key(&game_id) {
+            return Err("游戏正在运行，请先关闭游戏再更新".into());
+        }
+        if s.extractions.contains_key(&game_id) {
+            return Err(format!("{} 正在解压", game_id));
+        }
+    }
+    let from_version = game::read_local_version(&game_id, &install_path)
+        .ok_or("无法读取本地版本，请先设置本地版本")?;
+    let (client, dm) = {
+        let s = state.read().await;
+        (s.http_client.clone(), s.download_manager.clone())
+    };
+    // The install already exists, so only the pack directory is derived;
+    // `install_dirs` would nest `install_path` once more.
+    let (channel, pack_dir, keep_archives, concurrency, verify_concurrency, mirrors) = {
+        let c = config.read().await;
+        (
+            c.settings.game_channel(&game_id),
+            pack_dir(&c.settings, &game_id, &install_path),
+            c.settings.keeps_archives(),
+            c.settings.extract_concurrency,
+            c.settings.effective_verify_concurrency(),
+            c.settings.mirror_bases.clone(),
+        )
+    };
+
+    let latest = game::latest_version_cached(&game_id, channel, &client, true)
+        .await
+        .map_err(|e| e.to_string())?;
+    if latest.as_deref() == Some(from_version.as_str()) {
+        log::info!(
+            "[install] patch game={} already at {}",
+            game_id,
+            from_version
+        );
+        return Ok(PatchOutcome {
+            to_version: from_version.clone(),
+            from_version,
+            applied: false,
+            clean_install_required: false,
+            kept_archives: Vec::new(),
+            failed: Vec::new(),
+        });
+    }
+
+    let patch =
+        game::fetch_patch_manifest(&game_id, channel, Platform::host(), &from_version, &client)
+            .await
+            .map_err(|e| e.to_string())?;
+    let Some(mut manifest) = patch else {
+        log::info!(
+            "[install] no patch for game={} from {}",
+            game_id,
+            from_version
+        );
+        return Ok(PatchOutcome {
+            to_version: from_version.clone(),
+            from_version,
+            applied: false,
+            clean_install_required: true,
+            kept_archives: Vec::new(),
+            failed: Vec::new(),
+        });
+    };
+    game::add_mirrors(&mut manifest, &mirrors);
+
+    check_pack_filenames(&manifest)?;
+
+    let space = install_space(&manifest, &pack_dir, &install_path, keep_archives);
+    if let Some(v) = space.volumes.iter().find(|v| !v.sufficient) {
+        return Err(format!(
+            "磁盘空间不足（{}）：需要 {:.1} GB，可用 {:.1} GB",
+            v.path,
+            v.required as f64 / 1024.0 / 1024.0 / 1024.0,
+            v.available.unwrap_or(0) as f64 / 1024.0 / 1024.0 / 1024.0
+        ));
+    }
+
+    log::info!(
+        "[install] patch game={} {} -> {} packs={} size={}",
+        game_id,
+        from_version,
+        manifest.version,
+        manifest.packs.len(),
+        manifest.total_size
+    );
+
+    // Tasks of an earlier attempt: finished packs are reused, the rest
+    // restarted.
+    let leftovers: HashMap<String, DownloadTask> = dm
+        .get_tasks()
+        .await
+        .into_iter()
+        .filter(|t| t.game_id == game_id)
+        .map(|t| (t.dest_path.clone(), t))
+        .collect();
+
+    let tracker = InstallTracker::default();
+    reset_install_group(&state, &game_id).await;
+    let mut task_ids = Vec::with_capacity(manifest.packs.len());
+    for pack in &manifest.packs {
+        let dest_path = pack_dir.join(&pack.filename).to_string_lossy().into_owned();
+        if let Some(old) = leftovers.get(&dest_path) {
+            let on_disk = std::path::Path::new(&dest_path).is_file();
+            if old.status == DownloadStatus::Completed && on_disk {
+                log::info!("[install] patch pack {} already downloaded", pack.filename);
+                tracker.add(&old.id, old.total_size, old.total_size);
+                state
+                    .write()
+                    .await
+                    .install_groups
+                    .entry(game_id.clone())
+                    .or_default()
+                    .insert(old.id.clone());
+                task_ids.push(old.id.clone());
+                continue;
+            }
+            let _ = dm.cancel_task(&old.id).await;
+        }
+        let task_id = dm
+            .create_task(
+                game_id.clone(),
+                pack.filename.clone(),
+                pack.url.clone(),
+                dest_path,
+                Some(pack.size),
+                None,
+                Some(pack.md5.clone()),
+                HashMap::new(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        if !pack.mirrors.is_empty() {
+            dm.set_task_mirrors(&task_id, pack.mirrors.clone())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        tracker.add(&task_id, 0, pack.size);
+        state
+            .write()
+            .await
+            .install_groups
+            .entry(game_id.clone())
+            .or_default()
+            .insert(task_id.clone());
+        dm.start_task(
+            task_id.clone(),
+            install_progress_callback(&app, &game_id, &tracker),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        task_ids.push(task_id);
+    }
+
+    let mut tasks = Vec::with_capacity(task_ids.len());
+    for task_id in &task_ids {
+        if wait_for_task(&dm, task_id).await != Some(DownloadStatus::Completed) {
+            return Err("补丁下载未完成，请检查下载任务后重试更新".into());
+        }
+        if let Some(task) = dm.get_task(task_id).await {
+            tasks.push(task);
+        }
+    }
+
+    let packs: Vec<JournalPack> = tasks
+        .iter()
+        .map(|task| JournalPack {
+            name: task.name.clone(),
+            zip_path: task.dest_path.clone(),
+            dest_dir: install_path.clone(),
+            url: task
+                .chunks
+                .first()
+                .map(|c| c.url.clone())
+                .unwrap_or_default(),
+            md5: task.md5.clone(),
+            size: task.total_size,
+            status: PackStatus::Pending,
+        })
+        .collect();
+    let journal_path = extract_journal_path(&app)?;
+    let journal_packs = packs.clone();
+    let game_id_clone = game_id.clone();
+    journal::update_journal(&journal_path, move |j| {
+        j.insert(game_id_clone, journal_packs);
+    })
+    .map_err(|e| e.to_string())?;
+
+    let cancel = begin_extraction(&state, &game_id).await?;
+    let state_arc = Arc::clone(state.inner());
+    let (app_clone, game_id_clone) = (app.clone(), game_id.clone());
+    let result = tokio::task::spawn_blocking(move || {
+        extract_packs_journaled(
+            &app_clone,
+            &journal_path,
+            &game_id_clone,
+            &packs,
+            keep_archives,
+            concurrency,
+            &cancel,
+        )
+    })
+    .await;
+    state_arc.write().await.extractions.remove(&game_id);
+    let kept_archives = result.map_err(|e| format!("解压线程崩溃：{e}"))??;
+
+    // The packs are applied; drop their finished tasks so a later
+    // `extract_game_packs` doesn't pick them up again.
+    for task_id in &task_ids {
+        let _ = dm.cancel_task(task_id).await;
+    }
+    state.write().await.install_groups.remove(&game_id);
+    if pack_dir != std::path::Path::new(&install_path) {
+        let _ = tokio::fs::remove_dir(&pack_dir).await;
+    }
+
+    game::write_local_version(&install_path, &manifest.version).map_err(|e| e.to_string())?;
+
+    let failed = verify_against_latest(
+        &app,
+        &game_id,
+        &install_path,
+        channel,
+        verify_concurrency,
+        &client,
+    )
+    .await?;
+    log::info!(
+        "[install] patched game={} to {} ({} file(s) failed verification)",
+        game_id,
+        manifest.version,
+        failed.len()
+    );
+    Ok(PatchOutcome {
+        from_version,
+        to_version: manifest.version,
+        applied: true,
+        clean_install_required: false,
+        kept_archives,
+        failed,
+    })
+}
+
+// ─── Installed file verification ──────────────────────────────────────────────
+
+/// Hash every file listed in the latest manifest's file index and return the
+/// missing or mismatched ones. Emits `verify:progress` as files complete.
+#[tauri::command]
+pub async fn verify_game_files(
+    game_id: String,
+    install_path: String,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<BadFile>, String> {
+    let client = state.read().await.http_client.clone();
+    let (concurrency, channel) = {
+        let c = config.read().await;
+        (
+            c.settings.effective_verify_concurrency(),
+            c.settings.game_channel(&game_id),
+        )
+    };
+    verify_against_latest(&app, &game_id, &install_path, channel, concurrency, &client).await
+}
+
+/// Verify `install_path` against the file index of the latest full manifest,
+/// emitting `verify:progress`.
+async fn verify_against_latest(
+    app: &AppHandle,
+    game_id: &str,
+    install_path: &str,
+    channel: GameChannel,
+    concurrency: usize,
+    client: &reqwest::Client,
+) -> Result<Vec<BadFile>, String> {
+    let manifest =
+        game::fetch_game_manifest_cached(game_id, channel, Platform::host(), client, false)
+            .await
+            .map_err(|e| e.to_string())?;
+    let entries = verify::fetch_file_list(&manifest, client)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    log::info!(
+        "[verify] game={} files={} concurrency={}",
+        game_id,
+        entries.len(),
+        concurrency
+    );
+
+    verify::verify_files(
+        std::path::Path::new(install_path),
+        entries,
+        concurrency,
+        |files_done, files_total| {
+            let _ = app.emit(
+                "verify:progress",
+                verify::VerifyProgress {
+                    game_id: game_id.to_string(),
+                    files_done,
+                    files_total,
+                },
+            );
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Outcome of `repair_game_files`.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairSummary {
+    /// Files that were broken and now match the index
+    pub repaired: Vec<String>,
+    /// Files still missing or mismatched after the repair attempt
+    pub failed: Vec<BadFile>,
+}
+
+/// Suffix of an asset being re-downloaded. The broken file stays in place
+/// until the replacement is complete, and an interrupted repair resumes from
+/// the partial file on the next run.
+const REPAIR_SUFFIX: &str = ".repair";
+
+/// Re-download the files `verify_game_files` reports as missing or corrupt,
+/// then verify them again. `paths` limits the repair to those index paths;
+/// without it the install is verified first. Progress is emitted like an
+/// install (`download:progress`, `game:install_progress`), and the tasks join
+/// the game's install group so `cancel_game_install` stops them.
+#[tauri::command]
+pub async fn repair_game_files(
+    game_id: String,
+    install_path: String,
+    paths: Option<Vec<String>>,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<RepairSummary, String> {
+    let (client, dm) = {
+        let s = state.read().await;
+        (s.http_client.clone(), s.download_manager.clone())
+    };
+    let (concurrency, channel) = {
+        let c = config.read().await;
+        (
+            c.settings.effective_verify_concurrency(),
+            c.settings.game_channel(&game_id),
+        )
+    };
+    let install_dir = std::path::Path::new(&install_path);
+
+    let manifest =
+        game::fetch_game_manifest_cached(&game_id, channel, Platform::host(), &client, false)
+            .await
+            .map_err(|e| e.to_string())?;
+    let entries = verify::fetch_file_list(&manifest, &client)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let wanted: HashSet<String> = match paths {
+        Some(paths) => paths.into_iter().collect(),
+        None => verify::verify_files(install_dir, entries.clone(), concurrency, |_, _| {})
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|b| b.path)
+            .collect(),
+    };
+    // verify_files skips unsafe paths, which would then read as repaired.
+    let broken: Vec<verify::GameFileEntry> = entries
+        .into_iter()
+        .filter(|e| wanted.contains(&e.path))
+        .filter(|e| verify::resolve_entry_path(install_dir, &e.path).is_some())
+        .collect();
+    log::info!("[verify] repair game={} files={}", game_id, broken.len());
+    if broken.is_empty() {
+        return Ok(RepairSummary {
+            repaired: Vec::new(),
+            failed: Vec::new(),
+        });
+    }
+
+    let tracker = InstallTracker::default();
+    reset_install_group(&state, &game_id).await;
+    let mut started = Vec::new();
+    let mut not_started = Vec::new();
+    for entry in &broken {
+        let Some(target) = verify::resolve_entry_path(install_dir, &entry.path) else {
+            continue;
+        };
+        let mut part = target.clone().into_os_string();
+        part.push(REPAIR_SUFFIX);
+        let part = std::path::PathBuf::from(part);
+        let task = dm
+            .create_task(
+                game_id.clone(),
+                entry.path.clone(),
+                verify::asset_url(&manifest, &entry.path),
+                part.to_string_lossy().into_owned(),
+                (entry.size > 0).then_some(entry.size),
+                None,
+                Some(entry.md5.clone()),
+                HashMap::new(),
+            )
+            .await;
+        let task_id = match task {
+            Ok(id) => id,
+            Err(e) => {
+                log::error!("[verify] repair task for {} failed: {}", entry.path, e);
+                not_started.push(entry.path.clone());
+                continue;
+            }
+        };
+        tracker.add(&task_id, 0, entry.size);
+        state
+            .write()
+            .await
+            .install_groups
+            .entry(game_id.clone())
+            .or_default()
+            .insert(task_id.clone());
+        if let Err(e) = dm
+            .start_task(
+                task_id.clone(),
+                install_progress_callback(&app, &game_id, &tracker),
+            )
+            .await
+        {
+            log::error!("[verify] repair of {} failed to start: {}", entry.path, e);
+            not_started.push(entry.path.clone());
+            continue;
+        }
+        started.push((task_id, part, target));
+    }
+
+    // Swap in every replacement that downloaded and passed its checksum.
+    // Anything else keeps its partial file for the next attempt.
+    for (task_id, part, target) in &started {
+        let status = wait_for_task(&dm, task_id).await;
+        if status == Some(DownloadStatus::Completed) {
+            if let Err(e) = tokio::fs::rename(part, target).await {
+                log::error!("[verify] replace {} failed: {}", target.display(), e);
+            }
+            // Completed tasks are only dropped from the list; the file stays.
+            let _ = dm.cancel_task(task_id).await;
+        }
+    }
+    state.write().await.install_groups.remove(&game_id);
+
+    let still_bad = verify::verify_files(install_dir, broken.clone(), concurrency, |_, _| {})
+        .await
+        .map_err(|e| e.to_string())?;
+    let bad_paths: HashSet<&str> = still_bad.iter().map(|b| b.path.as_str()).collect();
+    let repaired: Vec<String> = broken
+        .iter()
+        .filter(|e| !bad_paths.contains(e.path.as_str()))
+        .map(|e| e.path.clone())
+        .collect();
+    log::info!(
+        "[verify] repair game={} repaired={} failed={} (not started: {})",
+        game_id,
+        repaired.len(),
+        still_bad.len(),
+        not_started.len()
+    );
+    Ok(RepairSummary {
+        repaired,
+        failed: still_bad,
+    })
+}
+
+/// Poll a task until it stops running. Paused counts as stopped so a
+/// suspended repair doesn't hang; None means the task was removed.
+async fn wait_for_task(dm: &DownloadManager, task_id: &str) -> Option<DownloadStatus> {
+    loop {
+        let status = dm.get_task(task_id).await.map(|t| t.status)?;
+        if matches!(
+            status,
+            DownloadStatus::Completed | DownloadStatus::Error | DownloadStatus::Paused
+        ) {
+            return Some(status);
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+}
+
+// ─── API self-check ───────────────────────────────────────────────────────────
+
+/// Probe the known Hypergryph endpoints and report, per endpoint, whether the
+/// fields our parsers depend on are still present.
+#[tauri::command]
+pub async fn check_api_compat(
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<game::EndpointCompat>, String> {
+    let client = state.read().await.http_client.clone();
+    Ok(game::check_api_compat(&client).await)
+}
+
+// ─── ZIP extraction ───────────────────────────────────────────────────────────
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractionProgress {
+    pub game_id: String,
+    /// Packs finished so far, counting the one just completed. Packs extract
+    /// concurrently, so this follows completion order, not the pack list.
+    pub pack_index: usize,
+    pub total_packs: usize,
+    pub pack_name: String,
+    /// true when all packs are done.
+    pub done: bool,
+    pub error: Option<String>,
+    /// The run was stopped by `cancel_extraction`
+    pub cancelled: bool,
+}
+
+/// Extract all completed download packs for a game, then remove the zip files
+/// unless `keep_archives` (default: the `keepArchives` setting) is set. A
+/// pack's zip is only deleted after that whole pack extracted successfully.
+/// Emits `extract:progress` events as each pack finishes. Progress is recorded
+/// in an extraction journal so a run killed midway can be resumed. Returns
+/// the archives left on disk.
+#[tauri::command]
+pub async fn extract_game_packs(
+    game_id: String,
+    keep_archives: Option<bool>,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<String>, String> {
+    let tasks: Vec<DownloadTask> = {
+        let s = state.read().await;
+        s.download_manager
+            .get_tasks()
+            .await
+            .into_iter()
+            .filter(|t| t.game_id == game_id && t.status == DownloadStatus::Completed)
+            .collect()
+    };
+
+    if tasks.is_empty() {
+        return Err("没有可解压的已完成下载".into());
+    }
+
+    // Packs downloaded to a temp dir extract into the recorded install path;
+    // otherwise they sit in the install dir and extract next to themselves.
+    let (install_dir, temp_dir, keep_archives, concurrency) = {
+        let c = config.read().await;
+        (
+            c.game_paths.get(&game_id).cloned(),
+            c.settings.temp_download_dir.clone(),
+            keep_archives.unwrap_or(c.settings.keep_archives) || c.settings.debug_keep_all_files,
+            c.settings.extract_concurrency,
+        )
+    };
+
+    let packs: Vec<JournalPack> = tasks
+        .iter()
+        .map(|task| JournalPack {
+            name: task.name.clone(),
+            zip_path: task.dest_path.clone(),
+            dest_dir: install_dir.clone().unwrap_or_else(|| {
+                std::path::Path::new(&task.dest_path)
+                    .parent()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            }),
+            url: task
+                .chunks
+                .first()
+                .map(|c| c.url.clone())
+                .unwrap_or_default(),
+            md5: task.md5.clone(),
+            size: task.total_size,
+            status: PackStatus::Pending,
+        })
+        .collect();
+
+    let journal_path = extract_journal_path(&app)?;
+    let journal_packs = packs.clone();
+    let game_id_clone = game_id.clone();
+    journal::update_journal(&journal_path, move |j| {
+        j.insert(game_id_clone, journal_packs);
+    })
+    .map_err(|e| e.to_string())?;
+
+    let pack_dir = temp_dir
+        .filter(|t| !t.is_empty())
+        .map(|t| std::path::Path::new(&t).join(&game_id));
+
+    let cancel = begin_extraction(&state, &game_id).await?;
+    let state_arc = Arc::clone(state.inner());
+    let game_id_clone = game_id.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        extract_packs_journaled(
+            &app,
+            &journal_path,
+            &game_id_clone,
+            &packs,
+            keep_archives,
+            concurrency,
+            &cancel,
+        )
+    })
+    .await;
+    state_arc.write().await.extractions.remove(&game_id);
+    let kept = result.map_err(|e| format!("解压线程崩溃：{e}"))??;
+    // The install is done; the next one starts its own group.
+    state_arc.write().await.install_groups.remove(&game_id);
+
+    // Extracted packs are already deleted; drop the per-game temp folder if empty.
+    if let Some(dir) = pack_dir {
+        let _ = tokio::fs::remove_dir(&dir).await;
+    }
+
+    Ok(kept)
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterruptedExtraction {
+    pub game_id: String,
+    pub total_packs: usize,
+    /// Packs not yet fully extracted.
+    pub pending_packs: Vec<String>,
+}
+
+/// Extraction runs that were interrupted (e.g. the app was killed) and can be resumed.
+#[tauri::command]
+pub async fn get_interrupted_extractions(
+    app: AppHandle,
+) -> Result<Vec<InterruptedExtraction>, String> {
+    let journal_path = extract_journal_path(&app)?;
+    Ok(journal::load_journal(&journal_path)
+        .into_iter()
+        .map(|(game_id, packs)| InterruptedExtraction {
+            game_id,
+            total_packs: packs.len(),
+            pending_packs: packs
+                .iter()
+                .filter(|p| p.status != PackStatus::Done)
+                .map(|p| p.name.clone())
+                .collect(),
+        })
+        .collect())
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumeExtractionResult {
+    /// Packs re-extracted from archives still on disk.
+    pub extracted: usize,
+    /// Download task IDs for packs whose archive was already deleted;
+    /// call `extract_game_packs` again once they complete.
+    pub redownload_task_ids: Vec<String>,
+    /// Archives left on disk after extraction (`keepArchives`).
+    pub kept_archives: Vec<String>,
+}
+
+/// Resume an interrupted extraction: re-extract incomplete packs whose archive
+/// is still present, and re-download those whose archive is gone.
+#[tauri::command]
+pub async fn resume_extraction(
+    game_id: String,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<ResumeExtractionResult, String> {
+    let (keep_archives, concurrency) = {
+        let c = config.read().await;
+        (c.settings.keeps_archives(), c.settings.extract_concurrency)
+    };
+    let journal_path = extract_journal_path(&app)?;
+    let packs = journal::load_journal(&journal_path)
+        .remove(&game_id)
+        .ok_or_else(|| format!("{} 没有未完成的解压", game_id))?;
+
+    let (to_extract, to_download): (Vec<JournalPack>, Vec<JournalPack>) = packs
+        .into_iter()
+        .filter(|p| p.status != PackStatus::Done)
+        .partition(|p| std::path::Path::new(&p.zip_path).is_file());
+
+    let mut redownload_task_ids = Vec::with_capacity(to_download.len());
+    let tracker = InstallTracker::default();
+    for pack in &to_download {
+        log::info!("[extract] archive missing, re-downloading {}", pack.name);
+        let s = state.read().await;
+        let task_id = s
+            .download_manager
+            .create_task(
+                game_id.clone(),
+                pack.name.clone(),
+                pack.url.clone(),
+                pack.zip_path.clone(),
+                Some(pack.size),
+                None,
+                pack.md5.clone(),
+                HashMap::new(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        tracker.add(&task_id, 0, pack.size);
+        s.download_manager
+            .start_task(
+                task_id.clone(),
+                install_progress_callback(&app, &game_id, &tracker),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        redownload_task_ids.push(task_id);
+    }
+    // Only the re-downloads are still part of the install.
+    if redownload_task_ids.is_empty() {
+        state.write().await.install_groups.remove(&game_id);
+    } else {
+        state.write().await.install_groups.insert(
+            game_id.clone(),
+            redownload_task_ids.iter().cloned().collect(),
+        );
+    }
+
+    // Re-downloaded packs get a fresh journal entry from extract_game_packs.
+    let extracted = to_extract.len();
+    let game_id_clone = game_id.clone();
+    let journal_packs = to_extract.clone();
+    journal::update_journal(&journal_path, move |j| {
+        if journal_packs.is_empty() {
+            j.remove(&game_id_clone);
+        } else {
+            j.insert(game_id_clone, journal_packs);
+        }
+    })
+    .map_err(|e| e.to_string())?;
+
+    let mut kept_archives = Vec::new();
+    if !to_extract.is_empty() {
+        let cancel = begin_extraction(&state, &game_id).await?;
+        let game_id_clone = game_id.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            extract_packs_journaled(
+                &app,
+                &journal_path,
+                &game_id_clone,
+                &to_extract,
+                keep_archives,
+                concurrency,
+                &cancel,
+            )
+        })
+        .await;
+        state.write().await.extractions.remove(&game_id);
+        kept_archives = result.map_err(|e| format!("解压线程崩溃：{e}"))??;
+    }
+
+    Ok(ResumeExtractionResult {
+        extracted,
+        redownload_task_ids,
+        kept_archives,
+    })
+}
+
+/// Register a running extraction for `game_id` and return its cancel flag.
+async fn begin_extraction(
+    state: &RwLock<AppState>,
+    game_id: &str,
+) -> Result<Arc<AtomicBool>, String> {
+    let mut s = state.write().await;
+    if s.extractions.contains_key(game_id) {
+        return Err(format!("{} 正在解压", game_id));
+    }
+    let cancel = Arc::new(AtomicBool::new(false));
+    s.extractions
+        .insert(game_id.to_string(), Arc::clone(&cancel));
+    Ok(cancel)
+}
+
+/// Stop a running extraction after the entry being written. Finished packs
+/// stay extracted; the rest keep their archives and stay in the journal, so
+/// `resume_extraction` can pick them up later.
+#[tauri::command]
+pub async fn cancel_extraction(
+    game_id: String,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<(), String> {
+    let s = state.read().await;
+    let cancel = s
+        .extractions
+        .get(&game_id)
+        .ok_or_else(|| format!("{} 没有正在进行的解压", game_id))?;
+    log::info!("[extract] cancel requested for {}", game_id);
+    cancel.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+fn extract_journal_path(app: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|d| d.join("extract_journal.json"))
+        .map_err(|e| e.to_string())
+}
+
+/// Extract `packs` on up to `concurrency` threads, recording each pack's
+/// status in the journal and emitting `extract:progress`. Packs that write
+/// any of the same files run one after another in list order (see
+/// `overlap_groups`). The first failure, or `cancel` being set, stops the
+/// remaining packs; those are left pending in the journal for
+/// `resume_extraction`. The game's journal entry is dropped once all packs
+/// succeed. Returns the archives kept on disk (all of them with
+/// `keep_archives`).
+fn extract_packs_journaled(
+    app: &AppHandle,
+    journal_path: &std::path::Path,
+    game_id: &str,
+    packs: &[JournalPack],
+    keep_archives: bool,
+    concurrency: usize,
+    cancel: &AtomicBool,
+) -> Result<Vec<String>, String> {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    if keep_archives {
+        log::info!("[extract] archives will be kept after extraction");
+    }
+    // Refuse unsupported formats before anything is written.
+    for pack in packs {
+        ArchiveKind::detect(&pack.zip_path).map_err(|e| e.to_string())?;
+    }
+    let total_packs = packs.len();
+    let groups = overlap_groups(packs);
+    let workers = concurrency.clamp(1, groups.len().max(1));
+    log::info!(
+        "[extract] {} pack(s) in {} group(s) on {} thread(s)",
+        total_packs,
+        groups.len(),
+        workers
+    );
+
+    let queue = Mutex::new(groups);
+    let completed = AtomicUsize::new(0);
+    let kept = Mutex::new(Vec::new());
+    let first_error: Mutex<Option<String>> = Mutex::new(None);
+
+    let extract_one = |pack: &JournalPack| {
+        let mark = |status| {
+            if let Err(e) = journal::mark_pack(journal_path, game_id, &pack.zip_path, status) {
+                log::error!("[extract] journal write failed: {}", e);
+            }
+        };
+        mark(PackStatus::Extracting);
+        log::info!("[extract] start {}", pack.name);
+
+        match extract_archive_sync(&pack.zip_path, &pack.dest_dir, keep_archives, cancel) {
+            Ok(()) => {
+                mark(PackStatus::Done);
+                if keep_archives {
+                    kept.lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push(pack.zip_path.clone());
+                }
+                let n = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                log::info!("[extract] {}/{} — {}", n, total_packs, pack.name);
+                let _ = app.emit(
+                    "extract:progress",
+                    ExtractionProgress {
+                        game_id: game_id.to_string(),
+                        pack_index: n,
+                        total_packs,
+                        pack_name: pack.name.clone(),
+                        done: n == total_packs,
+                        error: None,
+                        cancelled: false,
+                    },
+                );
+            }
+            Err(e) => {
+                mark(PackStatus::Pending);
+                let mut first = first_error.lock().unwrap_or_else(|e| e.into_inner());
+                if first.is_some() {
+                    // Stopped because another pack failed.
+                    log::info!("[extract] stopped {}: {}", pack.name, e);
+                    return;
+                }
+                // Failing packs set the flag under this lock, so a set flag
+                // with no recorded error is a user cancel.
+                let cancelled = cancel.swap(true, Ordering::SeqCst);
+                let message = if cancelled {
+                    log::info!("[extract] cancelled {} during {}", game_id, pack.name);
+                    "解压已取消".to_string()
+                } else {
+                    log::error!("[extract] failed {}: {}", pack.name, e);
+                    format!("解压 {} 失败：{}", pack.name, e)
+                };
+                let _ = app.emit(
+                    "extract:progress",
+                    ExtractionProgress {
+                        game_id: game_id.to_string(),
+                        pack_index: completed.load(Ordering::SeqCst),
+                        total_packs,
+                        pack_name: pack.name.clone(),
+                        done: false,
+                        error: Some(if cancelled {
+                            message.clone()
+                        } else {
+                            e.to_string()
+                        }),
+                        cancelled,
+                    },
+                );
+                *first = Some(message);
+            }
+        }
+    };
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let group = queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+                let Some(group) = group else {
+                    break;
+                };
+                for i in group {
+                    if cancel.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    extract_one(&packs[i]);
+                }
+            });
+        }
+    });
+
+    if let Some(e) = first_error.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        return Err(e);
+    }
+    // Cancelled between packs, so no pack reported it.
+    let done = completed.load(Ordering::SeqCst);
+    if done < total_packs {
+        log::info!("[extract] cancelled {} after {} pack(s)", game_id, done);
+        let _ = app.emit(
+            "extract:progress",
+            ExtractionProgress {
+                game_id: game_id.to_string(),
+                pack_index: done,
+                total_packs,
+                pack_name: String::new(),
+                done: false,
+                error: Some("解压已取消".to_string()),
+                cancelled: true,
+            },
+        );
+        return Err("解压已取消".to_string());
+    }
+
+    let game_id = game_id.to_string();
+    if let Err(e) = journal::update_journal(journal_path, move |j| {
+        j.remove(&game_id);
+    }) {
+        log::error!("[extract] journal cleanup failed: {}", e);
+    }
+    Ok(kept.into_inner().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Partition pack indices into groups that must extract sequentially: two
+/// packs share a group when any of their files land on the same output path.
+/// Groups and the packs within them keep list order. If any archive's file
+/// list can't be read up front (tar.gz, or unreadable), everything runs as
+/// one group.
+fn overlap_groups(packs: &[JournalPack]) -> std::collections::VecDeque<Vec<usize>> {
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    let mut parent: Vec<usize> = (0..packs.len()).collect();
+    let mut owner: HashMap<String, usize> = HashMap::new();
+    for (i, pack) in packs.iter().enumerate() {
+        let Some(names) = archive_output_names(&pack.zip_path) else {
+            // Could write anything; don't risk a clash with any other pack.
+            log::info!(
+                "[extract] can't list {}, extracting sequentially",
+                pack.name
+            );
+            return std::collections::VecDeque::from([(0..packs.len()).collect()]);
+        };
+        for name in names {
+            let key = std::path::Path::new(&pack.dest_dir)
+                .join(name)
+                .to_string_lossy()
+                .to_string();
+            let key = if cfg!(windows) {
+                key.to_lowercase()
+            } else {
+                key
+            };
+            match owner.get(&key) {
+                Some(&j) => {
+                    let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                    parent[a.max(b)] = a.min(b);
+                }
+                None => {
+                    owner.insert(key, i);
+                }
+            }
+        }
+    }
+
+    let mut groups: std::collections::BTreeMap<usize, Vec<usize>> = Default::default();
+    for i in 0..packs.len() {
+        let root = find(&mut parent, i);
+        groups.entry(root).or_default().push(i);
+    }
+    groups.into_values().collect()
+}
+
+/// Relative paths of the files an archive would write (directories and
+/// unsafe names excluded), from its index. None for tar.gz, which has no
+/// index, and for archives that can't be read.
+fn archive_output_names(path: &str) -> Option<Vec<std::path::PathBuf>> {
+    match ArchiveKind::detect(path).ok()? {
+        ArchiveKind::Zip => {
+            let file = std::fs::File::open(path).ok()?;
+            let mut archive = zip::ZipArchive::new(file).ok()?;
+            let mut names = Vec::with_capacity(archive.len());
+            for i in 0..archive.len() {
+                let entry = archive.by_index_raw(i).ok()?;
+                if entry.is_dir() {
+                    continue;
+                }
+                if let Some(name) = zip_entry_path(&entry) {
+                    names.push(name);
+                }
+            }
+            Some(names)
+        }
+        ArchiveKind::SevenZ => {
+            let reader =
+                sevenz_rust::SevenZReader::open(path, sevenz_rust::Password::empty()).ok()?;
+            Some(
+                reader
+                    .archive()
+                    .files
+                    .iter()
+                    .filter(|f| !f.is_directory())
+                    .filter_map(|f| safe_relative_path(f.name()))
+                    .collect(),
+            )
+        }
+        ArchiveKind::TarGz => None,
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveEntry {
+    pub name: String,
+    /// Uncompressed size in bytes.
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+/// List the contents of a pack without extracting or deleting anything.
+#[tauri::command]
+pub async fn list_archive_entries(zip_path: String) -> Result<Vec<ArchiveEntry>, String> {
+    tokio::task::spawn_blocking(move || list_archive_entries_sync(&zip_path))
+        .await
+        .map_err(|e| format!("读取线程崩溃：{e}"))?
+        .map_err(|e| e.to_string())
+}
+
+/// Extract a single file from a pack (e.g. a hotfixed asset) without
+/// unpacking or deleting the rest of the archive.
+#[tauri::command]
+pub async fn extract_entry(
+    zip_path: String,
+    entry_name: String,
+    dest_dir: String,
+) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || extract_entry_sync(&zip_path, &entry_name, &dest_dir))
+        .await
+        .map_err(|e| format!("解压线程崩溃：{e}"))?
+        .map_err(|e| e.to_string())
+}
+
+// ─── Gacha analysis ───────────────────────────────────────────────────────────
+
+use crate::gacha::{CompactReport, GachaManager, SNAPSHOTS_DIR};
+
+#[tauri::command]
+pub async fn scan_gacha_url(
+    game_id: String,
+    install_path: String,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Option<String>, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    Ok(mgr.scan_gacha_url(&game_id, &install_path))
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchGachaResult {
+    pub uid: String,
+    pub total: usize,
+    /// Records not stored before this fetch
+    pub new_records: usize,
+    /// The fetch stopped early; the records read so far were saved.
+    pub partial: bool,
+    pub error: Option<String>,
+    /// Seconds the server's newest record is ahead of the local clock
+    pub clock_skew_secs: Option<i64>,
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ClockSkewWarning {
+    game_id: String,
+    skew_secs: i64,
+}
+
+/// Emit `gacha:clock-skew` so the UI can explain odd incremental syncs.
+fn warn_clock_skew(app: &AppHandle, game_id: &str, result: &FetchGachaResult) {
+    if let Some(skew_secs) = result.clock_skew_secs {
+        let _ = app.emit(
+            "gacha:clock-skew",
+            ClockSkewWarning {
+                game_id: game_id.to_string(),
+                skew_secs,
+            },
+        );
+    }
+}
+
+/// Fetch from `url` and save. Unless `full_refresh`, paging stops at the
+/// records already stored and the new ones are merged in.
+async fn fetch_and_save(
+    mgr: &GachaManager,
+    game_id: &str,
+    url: &str,
+    full_refresh: bool,
+) -> Result<FetchGachaResult, String> {
+    let known = if full_refresh {
+        None
+    } else {
+        mgr.load_data(game_id)
+    };
+    let crate::gacha::FetchedRecords {
+        uid,
+        records,
+        new_records,
+        error,
+        clock_skew_secs,
+    } = mgr
+        .fetch_all_records(game_id, url, known.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let total = records.len();
+    let fetched_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    mgr.save_data(&crate::gacha::GachaData {
+        uid: uid.clone(),
+        game_id: game_id.to_string(),
+        records,
+        fetched_at,
+    })
+    .map_err(|e| e.to_string())?;
+
+    Ok(FetchGachaResult {
+        uid,
+        total,
+        new_records,
+        partial: error.is_some(),
+        error,
+        clock_skew_secs,
+    })
+}
+
+/// Check a pasted gacha URL before `fetch_gacha_records` uses it.
+#[tauri::command]
+pub async fn validate_gacha_url(
+    game_id: String,
+    url: String,
+) -> Result<crate::gacha::GachaUrlCheck, String> {
+    Ok(GachaManager::validate_gacha_url(&game_id, &url))
+}
+
+/// Fetch records from a gacha URL. Incremental by default; `full_refresh`
+/// re-reads the whole history and replaces what is stored.
+#[tauri::command]
+pub async fn fetch_gacha_records(
+    game_id: String,
+    url: String,
+    full_refresh: Option<bool>,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<FetchGachaResult, String> {
+    let check = GachaManager::validate_gacha_url(&game_id, &url);
+    if !check.valid {
+        return Err(check.problems.join("；"));
+    }
+    let url = url.trim();
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+
+    let result = fetch_and_save(&mgr, &game_id, url, full_refresh.unwrap_or(false)).await?;
+    warn_clock_skew(&app, &game_id, &result);
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn get_local_gacha_records(
+    game_id: String,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Option<crate::gacha::GachaData>, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    let anchors = config.read().await.pity_anchors.clone();
+    Ok(mgr.load_data(&game_id).map(|mut d| {
+        GachaManager::apply_pity_anchors(&mut d, &anchors);
+        d
+    }))
+}
+
+#[tauri::command]
+pub async fn get_gacha_stats(
+    game_id: String,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Option<crate::gacha::GachaStatsResult>, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    let anchors = config.read().await.pity_anchors.clone();
+    Ok(mgr
+        .load_data(&game_id)
+        .map(|d| GachaManager::compute_stats_with_anchors(&d, &anchors)))
+}
+
+/// "还需约 X 抽": pulls needed for `copies_wanted` more 6★ in a pool type,
+/// from the current pity (anchors applied).
+#[tauri::command]
+pub async fn estimate_pulls_for_target(
+    game_id: String,
+    pool_type: String,
+    copies_wanted: u32,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<crate::gacha::PullEstimate, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    let anchors = config.read().await.pity_anchors.clone();
+    let stats = mgr
+        .load_data(&game_id)
+        .map(|d| GachaManager::compute_stats_with_anchors(&d, &anchors));
+    let pool = stats.as_ref().and_then(|s| s.by_pool.get(&pool_type));
+    Ok(crate::gacha::estimate::estimate_pulls(
+        &game_id,
+        &pool_type,
+        pool,
+        copies_wanted,
+    ))
+}
+
+/// "如果我再抽 N 次": Monte Carlo distribution of 6★ counts for `count` more
+/// pulls, starting from the player's current pity in the pool type.
+#[tauri::command]
+pub async fn simulate_pulls(
+    game_id: String,
+    pool_type: String,
+    count: u32,
+    trials: u32,
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<crate::gacha::SimulationResult, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    let anchors = config.read().await.pity_anchors.clone();
+    let start_pity = mgr
+        .load_data(&game_id)
+        .map(|d| GachaManager::compute_stats_with_anchors(&d, &anchors))
+        .and_then(|s| s.by_pool.get(&pool_type).map(|p| p.current_pity))
+        .unwrap_or(0);
+    tokio::task::spawn_blocking(move || {
+        crate::gacha::estimate::simulate_pulls(&game_id, &pool_type, start_pity, count, trials)
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Record the player's known pity in a pool as of a timestamp. Replaces any
+/// existing anchor for the same game / UID / pool type.
 #[tauri::command]
-pub async fn start_download_task(
-    task_id: String,
+pub async fn set_pity_anchor(
+    game_id: String,
+    uid: String,
+    pool_type: String,
+    known_pity: u32,
+    as_of_ts: i64,
     app: AppHandle,
-    state: State<'_, Arc<RwLock<AppState>>>,
+    config: State<'_, Arc<RwLock<AppConfig>>>,
 ) -> Result<(), String> {
-    let s = state.read().await;
-    let app_clone = app.clone();
-    s.download_manager
-        .start_task(task_id, move |progress: DownloadProgress| {
-            let _ = app_clone.emit("download:progress", &progress);
-        })
+    {
+        let mut c = config.write().await;
+        c.pity_anchors
+            .retain(|a| !(a.game_id == game_id && a.uid == uid && a.pool_type == pool_type));
+        c.pity_anchors.push(crate::config::PityAnchor {
+            game_id,
+            uid,
+            pool_type,
+            known_pity,
+            as_of_ts,
+        });
+    }
+    let c = config.read().await.clone();
+    crate::config::save_config(&app, &c)
         .await
         .map_err(|e| e.to_string())
 }
 
+/// Headline gacha numbers for every game that has stored records.
 #[tauri::command]
-pub async fn pause_download_task(
-    task_id: String,
+pub async fn get_all_games_summary(
+    app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
-) -> Result<(), String> {
-    let s = state.read().await;
-    s.download_manager
-        .pause_task(&task_id)
-        .await
-        .map_err(|e| e.to_string())
+) -> Result<Vec<crate::gacha::GameGachaSummary>, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    Ok(mgr.summarize_games(game::GAME_IDS))
 }
 
+/// Per-week or per-month pull count and 6★ rate for one UID, oldest first.
 #[tauri::command]
-pub async fn cancel_download_task(
-    task_id: String,
+pub async fn gacha_rate_timeline(
+    game_id: String,
+    uid: String,
+    bucket: crate::gacha::TimeBucket,
+    app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
-) -> Result<(), String> {
-    let s = state.read().await;
-    s.download_manager
-        .cancel_task(&task_id)
-        .await
-        .map_err(|e| e.to_string())
+) -> Result<Vec<crate::gacha::RateBucket>, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    Ok(mgr
+        .load_data(&game_id)
+        .map(|d| GachaManager::rate_timeline(&d, &uid, bucket))
+        .unwrap_or_default())
 }
 
-// ─── Cache management ─────────────────────────────────────────────────────────
-
-/// Delete the hot-update cache directory for a game.
-/// Arknights / Endfield both store cached assets in `HotUpdate/`.
+/// Ten-pull vs single-pull counts per pool type, e.g. "共 X 次十连 + Y 次单抽".
 #[tauri::command]
-pub async fn clear_game_cache(game_id: String, install_path: String) -> Result<(), String> {
-    let cache_dirs: &[&str] = match game_id.as_str() {
-        "arknights" | "endfield" => &["HotUpdate"],
-        _ => return Err(format!("未知游戏：{}", game_id)),
-    };
-
-    let base = std::path::Path::new(&install_path);
-    for dir_name in cache_dirs {
-        let path = base.join(dir_name);
-        if path.is_dir() {
-            tokio::fs::remove_dir_all(&path)
-                .await
-                .map_err(|e| format!("清除 {} 失败：{}", dir_name, e))?;
-        }
-    }
-    Ok(())
+pub async fn pull_breakdown(
+    game_id: String,
+    uid: String,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<crate::gacha::PullBreakdown, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    let data = mgr
+        .load_data(&game_id)
+        .ok_or_else(|| "没有本地抽卡记录".to_string())?;
+    Ok(GachaManager::pull_breakdown(&data, &uid))
 }
 
-// ─── Version / update check ───────────────────────────────────────────────────
-
-#[derive(serde::Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CheckUpdateResult {
-    pub local_version: Option<String>,
-    pub latest_version: Option<String>,
-    pub update_available: bool,
+/// "干员图鉴": each 6★ pulled with its copy count.
+#[tauri::command]
+pub async fn six_star_inventory(
+    game_id: String,
+    uid: String,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<crate::gacha::SixStarEntry>, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    Ok(mgr
+        .load_data(&game_id)
+        .map(|d| GachaManager::six_star_inventory(&d, &uid))
+        .unwrap_or_default())
 }
 
-/// Compare the installed game version against the latest available on Hypergryph's CDN.
+/// "收藏": every item pulled with its copy count, rarity desc then count desc.
 #[tauri::command]
-pub async fn check_game_update(
+pub async fn get_gacha_item_summary(
     game_id: String,
-    install_path: String,
+    app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
-) -> Result<CheckUpdateResult, String> {
-    let local = game::read_local_version(&install_path);
-    let s = state.read().await;
-    let latest = game::fetch_latest_version(&game_id, &s.http_client)
-        .await
-        .map_err(|e| e.to_string())?;
-    let update_available = match (&local, &latest) {
-        (Some(l), Some(r)) => l != r,
-        _ => false,
-    };
-    Ok(CheckUpdateResult {
-        local_version: local,
-        latest_version: latest,
-        update_available,
-    })
+) -> Result<Vec<crate::gacha::ItemSummary>, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    Ok(mgr
+        .load_data(&game_id)
+        .map(|d| GachaManager::compute_item_summary(&d))
+        .unwrap_or_default())
 }
 
-/// Fetch the incremental patch manifest from the current version to the latest.
-/// Returns `None` if a patch is unavailable (clean install required).
+/// Deduplicate and expire gacha snapshots and rewrite data files compactly.
 #[tauri::command]
-pub async fn fetch_update_manifest(
-    game_id: String,
-    current_version: String,
+pub async fn compact_gacha_storage(
+    app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
-) -> Result<Option<GameManifest>, String> {
-    let s = state.read().await;
-    game::fetch_patch_manifest(&game_id, &current_version, &s.http_client)
+) -> Result<CompactReport, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    tokio::task::spawn_blocking(move || mgr.compact_storage(game::GAME_IDS))
         .await
+        .map_err(|e| e.to_string())?
         .map_err(|e| e.to_string())
 }
 
-// ─── ZIP extraction ───────────────────────────────────────────────────────────
-
-#[derive(Clone, serde::Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ExtractionProgress {
-    pub game_id: String,
-    /// 1-based index of the pack just completed.
-    pub pack_index: usize,
-    pub total_packs: usize,
-    /// true when all packs are done.
-    pub done: bool,
-    pub error: Option<String>,
-}
-
-/// Extract all completed download packs for a game, then remove the zip files.
-/// Emits `extract:progress` events as each pack finishes.
+/// Repair saved gacha data with an empty uid. Returns the recovered uid.
 #[tauri::command]
-pub async fn extract_game_packs(
+pub async fn fix_gacha_uid(
     game_id: String,
     app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
-) -> Result<(), String> {
-    let tasks: Vec<DownloadTask> = {
-        let s = state.read().await;
-        s.download_manager
-            .get_tasks()
-            .await
-            .into_iter()
-            .filter(|t| t.game_id == game_id && t.status == DownloadStatus::Completed)
-            .collect()
-    };
-
-    if tasks.is_empty() {
-        return Err("没有可解压的已完成下载".into());
-    }
-
-    let total_packs = tasks.len();
-    let game_id_clone = game_id.clone();
-
-    tokio::task::spawn_blocking(move || {
-        for (i, task) in tasks.iter().enumerate() {
-            let dest_dir = std::path::Path::new(&task.dest_path)
-                .parent()
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_default();
-
-            log::info!("[extract] {}/{} — {}", i + 1, total_packs, task.name);
-
-            match extract_zip_sync(&task.dest_path, &dest_dir) {
-                Ok(()) => {
-                    let _ = app.emit(
-                        "extract:progress",
-                        ExtractionProgress {
-                            game_id: game_id_clone.clone(),
-                            pack_index: i + 1,
-                            total_packs,
-                            done: i + 1 == total_packs,
-                            error: None,
-                        },
-                    );
-                }
-                Err(e) => {
-                    log::error!("[extract] failed {}: {}", task.name, e);
-                    let _ = app.emit(
-                        "extract:progress",
-                        ExtractionProgress {
-                            game_id: game_id_clone.clone(),
-                            pack_index: i + 1,
-                            total_packs,
-                            done: false,
-                            error: Some(e.to_string()),
-                        },
-                    );
-                    return Err(format!("解压 {} 失败：{}", task.name, e));
-                }
-            }
-        }
-        Ok::<(), String>(())
-    })
-    .await
-    .map_err(|e| format!("解压线程崩溃：{e}"))??;
-
-    Ok(())
+) -> Result<String, String> {
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let client = state.read().await.http_client.clone();
+    let mgr = GachaManager::new(data_dir, client);
+    mgr.fix_uid(&game_id).map_err(|e| e.to_string())
 }
 
-// ─── Gacha analysis ───────────────────────────────────────────────────────────
-
-use crate::gacha::GachaManager;
+/// Parse an official export file. Stored records are left alone; use
+/// `import_gacha_records` to merge the file into them.
+#[tauri::command]
+pub async fn import_official_export(
+    game_id: String,
+    path: String,
+) -> Result<crate::gacha::GachaData, String> {
+    GachaManager::import_official_export(&game_id, &path).map_err(|e| e.to_string())
+}
 
+/// Merge an exported records file (ours, official or UIGF-like) into the
+/// stored history, skipping records already present.
 #[tauri::command]
-pub async fn scan_gacha_url(
+pub async fn import_gacha_records(
     game_id: String,
-    install_path: String,
+    path: String,
     app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
-) -> Result<Option<String>, String> {
+) -> Result<crate::gacha::ImportReport, String> {
     let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
     let client = state.read().await.http_client.clone();
     let mgr = GachaManager::new(data_dir, client);
-    Ok(mgr.scan_gacha_url(&game_id, &install_path))
+    mgr.import_json(&game_id, &path).map_err(|e| e.to_string())
 }
 
-#[derive(serde::Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct FetchGachaResult {
-    pub uid: String,
-    pub total: usize,
+/// "临时查看": compute stats for an export file (e.g. a friend's) without
+/// touching the locally stored records. Accepts the same formats as
+/// `import_gacha_records`.
+#[tauri::command]
+pub async fn analyze_import(
+    game_id: String,
+    path: String,
+) -> Result<crate::gacha::GachaStatsResult, String> {
+    let data = GachaManager::parse_import_file(&game_id, &path).map_err(|e| e.to_string())?;
+    log::info!(
+        "[gacha] analyzing {} record(s) from {} (not saved)",
+        data.records.len(),
+        path
+    );
+    Ok(GachaManager::compute_stats(&data))
 }
 
+/// Export stored records, optionally restricted by time range, rarity and
+/// pool type; no `filter` exports everything.
 #[tauri::command]
-pub async fn fetch_gacha_records(
+pub async fn export_gacha_records(
     game_id: String,
-    url: String,
+    format: String,
+    dest_path: String,
+    filter: Option<crate::gacha::ExportFilter>,
     app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
-) -> Result<FetchGachaResult, String> {
+) -> Result<(), String> {
     let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
     let client = state.read().await.http_client.clone();
     let mgr = GachaManager::new(data_dir, client);
 
-    let (uid, records) = mgr
-        .fetch_all_records(&game_id, &url)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    let total = records.len();
-    let fetched_at = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64;
-
-    let data = crate::gacha::GachaData {
-        uid: uid.clone(),
-        game_id,
-        records,
-        fetched_at,
-    };
+    let data = mgr
+        .load_data(&game_id)
+        .ok_or_else(|| "没有可导出的记录".to_string())?;
 
-    mgr.save_data(&data).map_err(|e| e.to_string())?;
-    Ok(FetchGachaResult { uid, total })
+    let records = GachaManager::filter_records(&data.records, &filter.unwrap_or_default());
+    if records.is_empty() {
+        return Err("筛选条件下没有可导出的记录".to_string());
+    }
+    GachaManager::export_as(&records, &format, &dest_path).map_err(|e| e.to_string())
 }
 
+/// The "markdown" export as a string, for copying to the clipboard instead
+/// of saving a file. `min_rarity` (e.g. 6) keeps the table short enough to
+/// paste into a chat message.
 #[tauri::command]
-pub async fn get_local_gacha_records(
+pub async fn export_gacha_markdown(
     game_id: String,
+    min_rarity: Option<u8>,
     app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
-) -> Result<Option<crate::gacha::GachaData>, String> {
+) -> Result<String, String> {
     let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
     let client = state.read().await.http_client.clone();
     let mgr = GachaManager::new(data_dir, client);
-    Ok(mgr.load_data(&game_id))
+
+    let data = mgr
+        .load_data(&game_id)
+        .ok_or_else(|| "没有可导出的记录".to_string())?;
+    let filter = crate::gacha::ExportFilter {
+        min_rarity,
+        ..Default::default()
+    };
+    let records = GachaManager::filter_records(&data.records, &filter);
+    Ok(GachaManager::to_markdown(&records))
 }
 
+/// Export only pulls of at least `min_rarity` (e.g. 5 for 5★ and 6★).
 #[tauri::command]
-pub async fn get_gacha_stats(
+pub async fn export_rare_pulls(
     game_id: String,
+    min_rarity: u8,
+    format: String,
+    dest_path: String,
     app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
-) -> Result<Option<crate::gacha::GachaStatsResult>, String> {
+) -> Result<usize, String> {
     let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
     let client = state.read().await.http_client.clone();
     let mgr = GachaManager::new(data_dir, client);
-    Ok(mgr.load_data(&game_id).map(|d| GachaManager::compute_stats(&d)))
+
+    let data = mgr
+        .load_data(&game_id)
+        .ok_or_else(|| "没有可导出的记录".to_string())?;
+
+    let filter = crate::gacha::ExportFilter {
+        min_rarity: Some(min_rarity),
+        ..Default::default()
+    };
+    let rare = GachaManager::filter_records(&data.records, &filter);
+    if rare.is_empty() {
+        return Err(format!("没有 {min_rarity}★ 及以上的记录"));
+    }
+
+    GachaManager::export_as(&rare, &format, &dest_path).map_err(|e| e.to_string())?;
+    Ok(rare.len())
 }
 
+/// "完整导出": records, stats and an HTML report for one uid in a zip.
 #[tauri::command]
-pub async fn export_gacha_records(
+pub async fn export_gacha_bundle(
     game_id: String,
-    format: String,
+    uid: String,
     dest_path: String,
     app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
@@ -657,15 +3520,7 @@ pub async fn export_gacha_records(
     let data = mgr
         .load_data(&game_id)
         .ok_or_else(|| "没有可导出的记录".to_string())?;
-
-    match format.as_str() {
-        "json" => GachaManager::export_json(&data.records, &dest_path).map_err(|e| e.to_string()),
-        "csv" => GachaManager::export_csv(&data.records, &dest_path).map_err(|e| e.to_string()),
-        "xlsx" => {
-            GachaManager::export_xlsx(&data.records, &dest_path).map_err(|e| e.to_string())
-        }
-        _ => Err(format!("不支持的导出格式：{format}")),
-    }
+    GachaManager::export_bundle(&data, &uid, &dest_path).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -678,6 +3533,7 @@ pub async fn select_gacha_export_path(
         "json" => ("json", "JSON 文件"),
         "csv" => ("csv", "CSV 文件"),
         "xlsx" => ("xlsx", "Excel 文件"),
+        "markdown" => ("md", "Markdown 文件"),
         _ => return Err(format!("未知格式：{format}")),
     };
     let path = app
@@ -689,6 +3545,18 @@ pub async fn select_gacha_export_path(
     Ok(path.map(|p| p.to_string()))
 }
 
+#[tauri::command]
+pub async fn select_gacha_import_path(app: AppHandle) -> Result<Option<String>, String> {
+    use tauri_plugin_dialog::DialogExt;
+    let path = app
+        .dialog()
+        .file()
+        .set_title("选择要导入的寻访记录")
+        .add_filter("JSON 文件", &["json"])
+        .blocking_pick_file();
+    Ok(path.map(|p| p.to_string()))
+}
+
 // ─── Hypergryph account auth ──────────────────────────────────────────────────
 
 use crate::gacha::auth;
@@ -699,6 +3567,33 @@ use crate::config::HypergryphSession;
 pub struct HyperSessionInfo {
     pub phone_masked: String,
     pub uid: String,
+    pub expires_at: Option<u64>,
+    /// The token has expired (or is about to); prompt for login before
+    /// starting a fetch.
+    pub needs_relogin: bool,
+}
+
+impl From<&HypergryphSession> for HyperSessionInfo {
+    fn from(s: &HypergryphSession) -> Self {
+        HyperSessionInfo {
+            phone_masked: s.phone_masked.clone(),
+            uid: s.uid.clone(),
+            expires_at: s.expires_at,
+            needs_relogin: !s.session_valid(),
+        }
+    }
+}
+
+/// The stored session's `(uid, token)`, refusing one that has expired.
+fn valid_session(c: &crate::config::AppConfig) -> Result<(String, String), String> {
+    let s = c
+        .hypergryph_session
+        .as_ref()
+        .ok_or("未登录鹰角账号，请先登录")?;
+    if !s.session_valid() {
+        return Err("鹰角账号登录已过期，请重新登录".to_string());
+    }
+    Ok((s.uid.clone(), s.token.clone()))
 }
 
 /// Return the currently stored session (if any), without the raw token.
@@ -707,35 +3602,57 @@ pub async fn get_hypergryph_session(
     config: State<'_, Arc<RwLock<crate::config::AppConfig>>>,
 ) -> Result<Option<HyperSessionInfo>, String> {
     let c = config.read().await;
-    Ok(c.hypergryph_session.as_ref().map(|s| HyperSessionInfo {
-        phone_masked: s.phone_masked.clone(),
-        uid: s.uid.clone(),
-    }))
+    Ok(c.hypergryph_session.as_ref().map(HyperSessionInfo::from))
 }
 
-/// Login with phone + password, persist session token.
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CaptchaPrompt {
+    /// "password" or "sms": the command to call again with `captcha`
+    action: &'static str,
+    #[serde(flatten)]
+    challenge: auth::CaptchaChallenge,
+}
+
+/// Turn an auth error into the command's error string. A captcha
+/// challenge is also emitted as `hypergryph:captcha` so the UI can show the
+/// Geetest widget and retry the same command with the solution.
+fn auth_error(app: &AppHandle, action: &'static str, e: anyhow::Error) -> String {
+    if let Some(auth::CaptchaRequired(challenge)) = e.downcast_ref::<auth::CaptchaRequired>() {
+        let _ = app.emit(
+            "hypergryph:captcha",
+            CaptchaPrompt {
+                action,
+                challenge: challenge.clone(),
+            },
+        );
+    }
+    e.to_string()
+}
+
+/// Login with phone + password, persist session token. Pass `captcha`
+/// when retrying after a `hypergryph:captcha` challenge.
 #[tauri::command]
 pub async fn hypergryph_login_password(
     phone: String,
     password: String,
+    captcha: Option<auth::CaptchaSolution>,
     app: AppHandle,
     config: State<'_, Arc<RwLock<crate::config::AppConfig>>>,
     state: State<'_, Arc<RwLock<AppState>>>,
 ) -> Result<HyperSessionInfo, String> {
     let client = state.read().await.http_client.clone();
-    let (uid, token, _token_type) = auth::login_by_password(&phone, &password, &client)
+    let login = auth::login_by_password(&phone, &password, captcha.as_ref(), &client)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| auth_error(&app, "password", e))?;
 
     let session = HypergryphSession {
         phone_masked: auth::mask_phone(&phone),
-        uid: uid.clone(),
-        token,
-    };
-    let info = HyperSessionInfo {
-        phone_masked: session.phone_masked.clone(),
-        uid: uid.clone(),
+        uid: login.uid,
+        token: login.token,
+        expires_at: Some(login.expires_at),
     };
+    let info = HyperSessionInfo::from(&session);
 
     {
         let mut c = config.write().await;
@@ -749,16 +3666,19 @@ pub async fn hypergryph_login_password(
     Ok(info)
 }
 
-/// Send an SMS verification code to the given phone number.
+/// Send an SMS verification code to the given phone number. Pass `captcha`
+/// when retrying after a `hypergryph:captcha` challenge.
 #[tauri::command]
 pub async fn hypergryph_send_sms(
     phone: String,
+    captcha: Option<auth::CaptchaSolution>,
+    app: AppHandle,
     state: State<'_, Arc<RwLock<AppState>>>,
 ) -> Result<(), String> {
     let client = state.read().await.http_client.clone();
-    auth::send_sms_code(&phone, &client)
+    auth::send_sms_code(&phone, captcha.as_ref(), &client)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| auth_error(&app, "sms", e))
 }
 
 /// Login with phone + SMS code, persist session token.
@@ -771,19 +3691,17 @@ pub async fn hypergryph_login_by_code(
     state: State<'_, Arc<RwLock<AppState>>>,
 ) -> Result<HyperSessionInfo, String> {
     let client = state.read().await.http_client.clone();
-    let (uid, token, _) = auth::login_by_code(&phone, &code, &client)
+    let login = auth::login_by_code(&phone, &code, &client)
         .await
         .map_err(|e| e.to_string())?;
 
     let session = HypergryphSession {
         phone_masked: auth::mask_phone(&phone),
-        uid: uid.clone(),
-        token,
-    };
-    let info = HyperSessionInfo {
-        phone_masked: session.phone_masked.clone(),
-        uid: uid.clone(),
+        uid: login.uid,
+        token: login.token,
+        expires_at: Some(login.expires_at),
     };
+    let info = HyperSessionInfo::from(&session);
 
     {
         let mut c = config.write().await;
@@ -814,89 +3732,925 @@ pub async fn hypergryph_logout(
 }
 
 /// One-shot command: exchange the stored auth token for a game grant,
-/// then fetch and persist all gacha records. Returns fetch stats.
+/// then fetch and persist gacha records (incrementally unless
+/// `full_refresh`). Returns fetch stats.
 #[tauri::command]
 pub async fn fetch_gacha_with_login(
     game_id: String,
+    full_refresh: Option<bool>,
     app: AppHandle,
     config: State<'_, Arc<RwLock<crate::config::AppConfig>>>,
     state: State<'_, Arc<RwLock<AppState>>>,
 ) -> Result<FetchGachaResult, String> {
-    let (uid, auth_token) = {
+    let (uid, auth_token, channel) = {
         let c = config.read().await;
-        let s = c
-            .hypergryph_session
-            .as_ref()
-            .ok_or("未登录鹰角账号，请先登录")?;
-        (s.uid.clone(), s.token.clone())
+        let (uid, auth_token) = valid_session(&c)?;
+        (uid, auth_token, c.settings.game_channel(&game_id))
     };
 
     let client = state.read().await.http_client.clone();
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
 
+    let result = fetch_with_grant(
+        &game_id,
+        channel,
+        &uid,
+        &auth_token,
+        client,
+        data_dir,
+        full_refresh.unwrap_or(false),
+    )
+    .await?;
+    warn_clock_skew(&app, &game_id, &result);
+    Ok(result)
+}
+
+/// Grant → fetch → save for one game using the stored account token.
+async fn fetch_with_grant(
+    game_id: &str,
+    channel: GameChannel,
+    uid: &str,
+    auth_token: &str,
+    client: reqwest::Client,
+    data_dir: std::path::PathBuf,
+    full_refresh: bool,
+) -> Result<FetchGachaResult, String> {
     // Get a fresh game-specific grant token
-    let grant = auth::get_game_grant(&game_id, &auth_token, &client)
+    let grant = auth::get_game_grant(game_id, auth_token, &client)
         .await
         .map_err(|e| format!("获取游戏授权失败（登录可能已过期）：{e}"))?;
 
-    let gacha_url = auth::build_gacha_url(&game_id, &grant, &uid);
+    let gacha_url = auth::build_gacha_url(game_id, channel, &grant, uid);
 
-    // Fetch all records
-    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
     let mgr = GachaManager::new(data_dir, client);
+    fetch_and_save(&mgr, game_id, &gacha_url, full_refresh).await
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameFetchSummary {
+    pub game_id: String,
+    pub uid: Option<String>,
+    pub total: usize,
+    pub error: Option<String>,
+}
+
+/// Sync every supported game for the logged-in account, one after another.
+/// A failing game is reported in its summary entry and does not stop the rest.
+/// Emits `gacha:fetch-all` with the summary after each game.
+#[tauri::command]
+pub async fn fetch_all_games_gacha(
+    app: AppHandle,
+    config: State<'_, Arc<RwLock<crate::config::AppConfig>>>,
+    state: State<'_, Arc<RwLock<AppState>>>,
+) -> Result<Vec<GameFetchSummary>, String> {
+    let (uid, auth_token, settings) = {
+        let c = config.read().await;
+        let (uid, auth_token) = valid_session(&c)?;
+        (uid, auth_token, c.settings.clone())
+    };
+
+    let client = state.read().await.http_client.clone();
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
 
-    let (fetched_uid, records) = mgr
-        .fetch_all_records(&game_id, &gacha_url)
+    let mut summaries = Vec::with_capacity(game::GAME_IDS.len());
+    for game_id in game::GAME_IDS {
+        let summary = match fetch_with_grant(
+            game_id,
+            settings.game_channel(game_id),
+            &uid,
+            &auth_token,
+            client.clone(),
+            data_dir.clone(),
+            false,
+        )
         .await
-        .map_err(|e| e.to_string())?;
+        {
+            Ok(r) => {
+                warn_clock_skew(&app, game_id, &r);
+                GameFetchSummary {
+                    game_id: game_id.to_string(),
+                    uid: Some(r.uid),
+                    total: r.total,
+                    error: r.error,
+                }
+            }
+            Err(e) => {
+                log::warn!("[gacha] fetch-all: {} failed: {}", game_id, e);
+                GameFetchSummary {
+                    game_id: game_id.to_string(),
+                    uid: None,
+                    total: 0,
+                    error: Some(e),
+                }
+            }
+        };
+        let _ = app.emit("gacha:fetch-all", &summary);
+        summaries.push(summary);
+    }
 
-    let total = records.len();
-    let fetched_at = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64;
+    Ok(summaries)
+}
 
-    mgr.save_data(&crate::gacha::GachaData {
-        uid: fetched_uid.clone(),
-        game_id,
-        records,
-        fetched_at,
-    })
-    .map_err(|e| e.to_string())?;
+/// Pack formats `extract_archive_sync` can unpack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveKind {
+    Zip,
+    SevenZ,
+    TarGz,
+}
 
-    Ok(FetchGachaResult {
-        uid: fetched_uid,
-        total,
-    })
+impl ArchiveKind {
+    /// By extension, or by magic bytes for names without a known one (CDN
+    /// packs are not guaranteed to end in `.zip`).
+    fn detect(path: &str) -> anyhow::Result<Self> {
+        use std::io::Read;
+
+        let lower = path.to_lowercase();
+        if lower.ends_with(".zip") {
+            return Ok(ArchiveKind::Zip);
+        }
+        if lower.ends_with(".7z") {
+            return Ok(ArchiveKind::SevenZ);
+        }
+        if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            return Ok(ArchiveKind::TarGz);
+        }
+
+        let mut magic = [0u8; 6];
+        let n = std::fs::File::open(path)?.read(&mut magic)?;
+        match &magic[..n] {
+            [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] => Ok(ArchiveKind::Zip),
+            [b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C] => Ok(ArchiveKind::SevenZ),
+            [0x1F, 0x8B, ..] => Ok(ArchiveKind::TarGz),
+            _ => Err(anyhow::anyhow!(
+                "不支持的压缩包格式：{}（支持 zip、7z、tar.gz）",
+                std::path::Path::new(path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| path.to_string())
+            )),
+        }
+    }
 }
 
-/// Synchronously extract a zip archive into `dest_dir` and delete the archive on success.
-fn extract_zip_sync(zip_path: &str, dest_dir: &str) -> anyhow::Result<()> {
-    use std::io;
-    use zip::ZipArchive;
+/// Synchronously extract a zip, 7z or tar.gz archive into `dest_dir`, then
+/// delete the archive unless `keep_archive` is set. Deletion only happens
+/// after every entry was written, so a failed or cancelled pack can be
+/// retried from the same archive. Stops between entries once `cancel` is set.
+fn extract_archive_sync(
+    archive_path: &str,
+    dest_dir: &str,
+    keep_archive: bool,
+    cancel: &AtomicBool,
+) -> anyhow::Result<()> {
+    let kind = ArchiveKind::detect(archive_path)?;
+    let dest = extraction_root(dest_dir)?;
+
+    let rejected = match kind {
+        ArchiveKind::Zip => unpack_zip(archive_path, &dest, cancel)?,
+        ArchiveKind::SevenZ => unpack_7z(archive_path, &dest, cancel)?,
+        ArchiveKind::TarGz => unpack_tar_gz(archive_path, &dest, cancel)?,
+    };
+    // Keep the archive so the rejected entries can be inspected.
+    if !rejected.is_empty() {
+        for name in &rejected {
+            log::warn!("[extract] rejected entry {:?} in {}", name, archive_path);
+        }
+        const SHOWN: usize = 10;
+        let mut list = rejected[..rejected.len().min(SHOWN)].join("、");
+        if rejected.len() > SHOWN {
+            list.push_str(&format!(" 等 {} 个", rejected.len()));
+        }
+        anyhow::bail!("压缩包包含不安全的条目，已跳过：{}", list);
+    }
+
+    if keep_archive {
+        log::info!("[extract] keeping {}", archive_path);
+        return Ok(());
+    }
 
+    // Remove the archive to free space after successful extraction.
+    std::fs::remove_file(archive_path)?;
+    log::info!("[extract] removed {}", archive_path);
+    Ok(())
+}
+
+/// Unpack every entry of a zip under `dest`; returns the rejected names.
+fn unpack_zip(
+    zip_path: &str,
+    dest: &std::path::Path,
+    cancel: &AtomicBool,
+) -> anyhow::Result<Vec<String>> {
     let file = std::fs::File::open(zip_path)?;
-    let mut archive = ZipArchive::new(file)?;
+    let mut archive = zip::ZipArchive::new(file)?;
 
+    let mut rejected = Vec::new();
     for i in 0..archive.len() {
+        if cancel.load(Ordering::SeqCst) {
+            anyhow::bail!("解压已取消");
+        }
         let mut entry = archive.by_index(i)?;
-        let out_path = match entry.enclosed_name() {
-            Some(p) => std::path::Path::new(dest_dir).join(p),
-            None => continue, // skip entries with unsafe paths
-        };
+        if !write_zip_entry(&mut entry, dest)? {
+            rejected.push(entry.name().to_string());
+        }
+    }
+    Ok(rejected)
+}
+
+/// 7z attribute flag meaning the high 16 bits hold a Unix mode.
+const SEVENZ_UNIX_EXTENSION: u32 = 0x8000;
+
+/// Unpack every entry of a 7z archive under `dest`; returns the rejected
+/// names. Entries of a solid block decode in order, so rejected ones are
+/// still read through.
+fn unpack_7z(
+    path: &str,
+    dest: &std::path::Path,
+    cancel: &AtomicBool,
+) -> anyhow::Result<Vec<String>> {
+    let mut reader = sevenz_rust::SevenZReader::open(path, sevenz_rust::Password::empty())?;
+
+    let mut rejected = Vec::new();
+    let mut failure: Option<anyhow::Error> = None;
+    reader.for_each_entries(|entry, data| {
+        if cancel.load(Ordering::SeqCst) {
+            failure = Some(anyhow::anyhow!("解压已取消"));
+            return Ok(false);
+        }
+        // Deletion markers for incremental archives; nothing to write.
+        if entry.is_anti_item() {
+            return Ok(true);
+        }
+        match write_7z_entry(entry, dest, data) {
+            Ok(true) => Ok(true),
+            Ok(false) => {
+                rejected.push(entry.name().to_string());
+                std::io::copy(data, &mut std::io::sink())?;
+                Ok(true)
+            }
+            Err(e) => {
+                failure = Some(e);
+                Ok(false)
+            }
+        }
+    })?;
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(rejected),
+    }
+}
+
+/// `write_entry` for a 7z entry, honouring the Unix mode some archivers
+/// store in the high bits of the Windows attributes.
+fn write_7z_entry(
+    entry: &sevenz_rust::SevenZArchiveEntry,
+    dest: &std::path::Path,
+    data: &mut dyn std::io::Read,
+) -> anyhow::Result<bool> {
+    let attrs = entry.windows_attributes();
+    let unix_mode =
+        (entry.has_windows_attributes && attrs & SEVENZ_UNIX_EXTENSION != 0).then_some(attrs >> 16);
+    let is_symlink = unix_mode.is_some_and(|m| m & 0o170000 == 0o120000);
+    let rel = safe_relative_path(entry.name());
+    write_entry(dest, rel, entry.is_directory(), is_symlink, unix_mode, data)
+}
+
+/// Unpack every entry of a gzip-compressed tarball under `dest`; returns the
+/// rejected names. Links and special files are rejected.
+fn unpack_tar_gz(
+    path: &str,
+    dest: &std::path::Path,
+    cancel: &AtomicBool,
+) -> anyhow::Result<Vec<String>> {
+    use tar::EntryType;
 
-        if entry.is_dir() {
-            std::fs::create_dir_all(&out_path)?;
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+
+    let mut rejected = Vec::new();
+    for entry in archive.entries()? {
+        if cancel.load(Ordering::SeqCst) {
+            anyhow::bail!("解压已取消");
+        }
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let kind = entry.header().entry_type();
+        let unix_mode = entry.header().mode().ok();
+        let is_dir = kind == EntryType::Directory;
+        let written = if is_dir || kind == EntryType::Regular || kind == EntryType::Continuous {
+            write_entry(
+                dest,
+                safe_relative_path(&name),
+                is_dir,
+                false,
+                unix_mode,
+                &mut entry,
+            )?
         } else {
-            if let Some(parent) = out_path.parent() {
-                std::fs::create_dir_all(parent)?;
+            false
+        };
+        if !written {
+            rejected.push(name);
+        }
+    }
+    Ok(rejected)
+}
+
+/// `name` from a 7z or tar header as a path relative to the destination, or
+/// None when it is absolute or climbs out with `..` — the rules zip's
+/// `enclosed_name` applies. Backslashes count as separators.
+fn safe_relative_path(name: &str) -> Option<std::path::PathBuf> {
+    if name.contains('\0') || name.starts_with(['/', '\\']) {
+        return None;
+    }
+    let mut out = std::path::PathBuf::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if !out.pop() {
+                    return None;
+                }
             }
-            let mut out_file = std::fs::File::create(&out_path)?;
-            io::copy(&mut entry, &mut out_file)?;
+            // Drive letters and alternate data streams. Rejected on every
+            // platform: the packs target Windows, where `:` is never a plain
+            // name character.
+            p if p.contains(':') => return None,
+            p => out.push(p),
         }
     }
+    Some(out)
+}
 
-    // Remove the zip to free space after successful extraction.
-    std::fs::remove_file(zip_path)?;
-    log::info!("[extract] removed {}", zip_path);
+/// Error for per-entry operations on a tar.gz: it has no index, so one
+/// entry can only be reached by decompressing everything before it.
+fn tar_gz_unsupported(path: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "tar.gz 压缩包没有索引，不支持单独列出或提取条目，请整体解压：{}",
+        std::path::Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string())
+    )
+}
+
+/// Synchronously extract a single named entry from a zip or 7z archive into
+/// `dest_dir`. The archive itself is left untouched.
+fn extract_entry_sync(archive_path: &str, entry_name: &str, dest_dir: &str) -> anyhow::Result<()> {
+    let written = match ArchiveKind::detect(archive_path)? {
+        ArchiveKind::Zip => extract_zip_entry(archive_path, entry_name, dest_dir)?,
+        ArchiveKind::SevenZ => extract_7z_entry(archive_path, entry_name, dest_dir)?,
+        ArchiveKind::TarGz => return Err(tar_gz_unsupported(archive_path)),
+    };
+    if !written {
+        return Err(anyhow::anyhow!("不安全的条目路径：{}", entry_name));
+    }
+    log::info!("[extract] extracted {} from {}", entry_name, archive_path);
     Ok(())
 }
+
+/// `extract_entry_sync` for a zip; false when the entry's path is unsafe.
+fn extract_zip_entry(zip_path: &str, entry_name: &str, dest_dir: &str) -> anyhow::Result<bool> {
+    use zip::result::ZipError;
+    use zip::ZipArchive;
+
+    let file = std::fs::File::open(zip_path)?;
+    let mut archive = ZipArchive::new(file)?;
+    let mut entry = match archive.by_name(entry_name) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => {
+            return Err(anyhow::anyhow!("压缩包中没有 {}", entry_name));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let dest = extraction_root(dest_dir)?;
+    write_zip_entry(&mut entry, &dest)
+}
+
+/// `extract_entry_sync` for a 7z. Entries of a solid block decode in
+/// order, so the ones before the match are read through.
+fn extract_7z_entry(path: &str, entry_name: &str, dest_dir: &str) -> anyhow::Result<bool> {
+    let mut reader = sevenz_rust::SevenZReader::open(path, sevenz_rust::Password::empty())?;
+    if !reader
+        .archive()
+        .files
+        .iter()
+        .any(|f| f.name() == entry_name)
+    {
+        return Err(anyhow::anyhow!("压缩包中没有 {}", entry_name));
+    }
+    let dest = extraction_root(dest_dir)?;
+
+    let mut result: Option<anyhow::Result<bool>> = None;
+    reader.for_each_entries(|entry, data| {
+        if entry.name() != entry_name {
+            std::io::copy(data, &mut std::io::sink())?;
+            return Ok(true);
+        }
+        result = Some(write_7z_entry(entry, &dest, data));
+        Ok(false)
+    })?;
+    result.unwrap_or_else(|| Err(anyhow::anyhow!("压缩包中没有 {}", entry_name)))
+}
+
+/// Read the index of a zip or 7z archive (read-only).
+fn list_archive_entries_sync(archive_path: &str) -> anyhow::Result<Vec<ArchiveEntry>> {
+    match ArchiveKind::detect(archive_path)? {
+        ArchiveKind::Zip => {
+            let file = std::fs::File::open(archive_path)?;
+            let mut archive = zip::ZipArchive::new(file)?;
+
+            let mut entries = Vec::with_capacity(archive.len());
+            for i in 0..archive.len() {
+                let entry = archive.by_index_raw(i)?;
+                entries.push(ArchiveEntry {
+                    name: entry.name().to_string(),
+                    size: entry.size(),
+                    is_dir: entry.is_dir(),
+                });
+            }
+            Ok(entries)
+        }
+        ArchiveKind::SevenZ => {
+            let reader =
+                sevenz_rust::SevenZReader::open(archive_path, sevenz_rust::Password::empty())?;
+            Ok(reader
+                .archive()
+                .files
+                .iter()
+                .map(|f| ArchiveEntry {
+                    name: f.name().to_string(),
+                    size: f.size(),
+                    is_dir: f.is_directory(),
+                })
+                .collect())
+        }
+        ArchiveKind::TarGz => Err(tar_gz_unsupported(archive_path)),
+    }
+}
+
+/// Create `dest_dir` and return its canonical form, which every output path
+/// is checked against.
+fn extraction_root(dest_dir: &str) -> anyhow::Result<std::path::PathBuf> {
+    std::fs::create_dir_all(dest_dir)?;
+    Ok(std::fs::canonicalize(dest_dir)?)
+}
+
+/// Whether `path` resolves inside `root` (canonical). The deepest existing
+/// ancestor is canonicalized, so a symlinked directory already on disk can't
+/// redirect writes; the rest comes from `enclosed_name` and has no `..`.
+fn resolves_inside(root: &std::path::Path, path: &std::path::Path) -> bool {
+    path.ancestors()
+        .find(|a| a.exists())
+        .and_then(|a| std::fs::canonicalize(a).ok())
+        .is_some_and(|a| a.starts_with(root))
+}
+
+/// Relative output path of a zip entry. `enclosed_name` alone doesn't know
+/// about drive prefixes when running off Windows, so the name must pass
+/// `safe_relative_path` too.
+fn zip_entry_path(entry: &zip::read::ZipFile) -> Option<std::path::PathBuf> {
+    entry.enclosed_name()?;
+    safe_relative_path(entry.name())
+}
+
+/// Write one zip entry under `dest`; see `write_entry`.
+fn write_zip_entry(entry: &mut zip::read::ZipFile, dest: &std::path::Path) -> anyhow::Result<bool> {
+    let rel = zip_entry_path(entry);
+    let (is_dir, is_symlink, unix_mode) = (entry.is_dir(), entry.is_symlink(), entry.unix_mode());
+    write_entry(dest, rel, is_dir, is_symlink, unix_mode, entry)
+}
+
+/// Write one archive entry at `rel` under `dest` (canonical, see
+/// `extraction_root`). `rel` is None for names that are absolute or climb out
+/// with `..`. Returns false (and writes nothing) for those, for symlinks, and
+/// for paths that resolve outside `dest`.
+fn write_entry(
+    dest: &std::path::Path,
+    rel: Option<std::path::PathBuf>,
+    is_dir: bool,
+    is_symlink: bool,
+    unix_mode: Option<u32>,
+    data: &mut dyn std::io::Read,
+) -> anyhow::Result<bool> {
+    if is_symlink {
+        return Ok(false);
+    }
+    let out_path = match rel {
+        // A `./` entry for the archive root: nothing to create.
+        Some(p) if p.as_os_str().is_empty() => return Ok(is_dir),
+        Some(p) => dest.join(p),
+        None => return Ok(false),
+    };
+    if !resolves_inside(dest, &out_path) {
+        return Ok(false);
+    }
+
+    if is_dir {
+        std::fs::create_dir_all(&out_path)?;
+    } else {
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut out_file = std::fs::File::create(&out_path)?;
+        std::io::copy(data, &mut out_file)?;
+        // Keep the executable bit on launchers and helper binaries. Special
+        // bits (setuid etc.) are dropped, and the owner always keeps
+        // read/write so updates and repairs can overwrite the file.
+        #[cfg(unix)]
+        if let Some(mode) = unix_mode {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = (mode & 0o777) | 0o600;
+            std::fs::set_permissions(&out_path, std::fs::Permissions::from_mode(mode))?;
+        }
+        #[cfg(not(unix))]
+        let _ = unix_mode;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    /// A zip at `dir/name` with one file per `(name, contents)`.
+    fn make_zip(dir: &std::path::Path, name: &str, files: &[(&str, &str)]) -> String {
+        let path = dir.join(name);
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (entry, contents) in files {
+            zip.start_file(*entry, SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // ─── Pack filenames ──────────────────────────────────────────────────────
+
+    fn manifest_with_pack(filename: &str) -> GameManifest {
+        GameManifest {
+            game_id: "arknights".into(),
+            version: "1.0.0".into(),
+            packs: vec![game::hypergryph::GamePack {
+                url: format!("https://cdn.example.com/{filename}"),
+                md5: String::new(),
+                size: 1,
+                filename: filename.into(),
+                mirrors: Vec::new(),
+            }],
+            total_size: 1,
+            game_files_md5: String::new(),
+            file_path: String::new(),
+        }
+    }
+
+    #[test]
+    fn crafted_pack_filenames_are_rejected() {
+        for name in [
+            "../evil.zip",
+            "a/b.zip",
+            "a\\b.zip",
+            "..",
+            ".",
+            "C:evil.zip",
+            "",
+        ] {
+            let err = check_pack_filenames(&manifest_with_pack(name)).unwrap_err();
+            assert_eq!(err, format!("安装包文件名不安全，已拒绝安装：{name}"));
+        }
+    }
+
+    #[test]
+    fn plain_pack_filenames_are_accepted() {
+        for name in ["game_1.0.0.zip", "明日方舟.zip", "pack.tar.gz"] {
+            assert_eq!(check_pack_filenames(&manifest_with_pack(name)), Ok(()));
+        }
+    }
+
+    // ─── Install paths with spaces and non-ASCII characters ──────────────────
+
+    /// `<tmp>/My Games/明日方舟 测试`
+    fn unusual_install_dir(tmp: &std::path::Path) -> std::path::PathBuf {
+        tmp.join("My Games").join("明日方舟 测试")
+    }
+
+    #[test]
+    fn install_dirs_join_unusual_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = unusual_install_dir(tmp.path());
+        let mut settings = AppSettings::default();
+        settings
+            .nest_install_dir
+            .insert("arknights".to_string(), true);
+
+        let (dest_dir, pack_dir) =
+            install_dirs(&settings, "arknights", base.to_string_lossy().into_owned());
+
+        assert_eq!(std::path::Path::new(&dest_dir), base.join("arknights"));
+        assert_eq!(pack_dir, base.join("arknights"));
+    }
+
+    #[test]
+    fn extraction_into_unusual_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = unusual_install_dir(tmp.path());
+        std::fs::create_dir_all(&dir).unwrap();
+        let zip = make_zip(&dir, "游戏 包.zip", &[("数据 文件/a b.txt", "ok")]);
+
+        extract_archive_sync(&zip, dir.to_str().unwrap(), false, &AtomicBool::new(false)).unwrap();
+
+        let extracted = dir.join("数据 文件").join("a b.txt");
+        assert_eq!(std::fs::read_to_string(extracted).unwrap(), "ok");
+        assert!(!std::path::Path::new(&zip).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launch_from_unusual_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = unusual_install_dir(tmp.path());
+        std::fs::create_dir_all(&dir).unwrap();
+        // Found by the extension scan like a real game exe, but runnable here.
+        let exe = dir.join("明日方舟 Game.exe");
+        std::fs::write(&exe, "#!/bin/sh\nprintf '%s' \"$1\" > launched.txt\n").unwrap();
+        std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let found = game::require_game_exe("unknown", dir.to_str().unwrap(), None).unwrap();
+        assert_eq!(found, exe);
+        let options = crate::config::LaunchOptions {
+            args: vec!["参数 一".to_string()],
+            ..Default::default()
+        };
+        let status = launch_command(&found, &options).status().unwrap();
+
+        assert!(status.success());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("launched.txt")).unwrap(),
+            "参数 一"
+        );
+    }
+
+    // ─── Extraction path safety ──────────────────────────────────────────────
+
+    #[test]
+    fn safe_relative_path_rejects_escapes() {
+        for name in [
+            "../x",
+            "a/../../x",
+            "/etc/passwd",
+            "\\server\\share",
+            "C:/Windows/x.dll",
+            "C:\\x",
+            "a/b:stream",
+            "a\0b",
+        ] {
+            assert_eq!(safe_relative_path(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn safe_relative_path_normalizes_inner_parent() {
+        assert_eq!(
+            safe_relative_path("a/../b"),
+            Some(std::path::PathBuf::from("b"))
+        );
+        assert_eq!(
+            safe_relative_path("./a\\b/"),
+            Some(std::path::Path::new("a").join("b"))
+        );
+    }
+
+    #[test]
+    fn resolves_inside_follows_existing_symlinks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = extraction_root(tmp.path().join("dest").to_str().unwrap()).unwrap();
+        assert!(resolves_inside(&root, &root.join("a").join("b.txt")));
+
+        #[cfg(unix)]
+        {
+            let outside = tmp.path().join("outside");
+            std::fs::create_dir(&outside).unwrap();
+            std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+            assert!(!resolves_inside(&root, &root.join("link").join("x")));
+        }
+    }
+
+    #[test]
+    fn unpack_zip_rejects_unsafe_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let zip = make_zip(
+            tmp.path(),
+            "evil.zip",
+            &[
+                ("../x", "up"),
+                ("/abs.txt", "abs"),
+                ("C:/drive.txt", "drive"),
+                ("ok.txt", "ok"),
+            ],
+        );
+        let dest = extraction_root(tmp.path().join("dest").to_str().unwrap()).unwrap();
+
+        let rejected = unpack_zip(&zip, &dest, &AtomicBool::new(false)).unwrap();
+
+        assert_eq!(rejected, ["../x", "/abs.txt", "C:/drive.txt"]);
+        assert!(dest.join("ok.txt").is_file());
+        assert!(!tmp.path().join("x").exists());
+        assert!(!dest.join("C:").exists());
+    }
+
+    #[test]
+    fn unpack_zip_keeps_inner_parent_inside() {
+        let tmp = tempfile::tempdir().unwrap();
+        let zip = make_zip(tmp.path(), "pack.zip", &[("a/../b.txt", "b")]);
+        let dest = extraction_root(tmp.path().join("dest").to_str().unwrap()).unwrap();
+
+        let rejected = unpack_zip(&zip, &dest, &AtomicBool::new(false)).unwrap();
+
+        assert!(rejected.is_empty());
+        assert_eq!(std::fs::read_to_string(dest.join("b.txt")).unwrap(), "b");
+    }
+
+    #[test]
+    fn unpack_zip_rejects_symlinks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("link.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        zip.add_symlink("link", "/etc/passwd", SimpleFileOptions::default())
+            .unwrap();
+        zip.finish().unwrap();
+        let dest = extraction_root(tmp.path().join("dest").to_str().unwrap()).unwrap();
+
+        let rejected = unpack_zip(path.to_str().unwrap(), &dest, &AtomicBool::new(false)).unwrap();
+
+        assert_eq!(rejected, ["link"]);
+        assert!(std::fs::symlink_metadata(dest.join("link")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unpack_zip_applies_sanitized_unix_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("modes.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (name, mode) in [("run.sh", 0o755), ("suid", 0o4777), ("ro.txt", 0o400)] {
+            let options = SimpleFileOptions::default().unix_permissions(mode);
+            zip.start_file(name, options).unwrap();
+            zip.write_all(b"x").unwrap();
+        }
+        zip.finish().unwrap();
+        let dest = extraction_root(tmp.path().join("dest").to_str().unwrap()).unwrap();
+
+        unpack_zip(path.to_str().unwrap(), &dest, &AtomicBool::new(false)).unwrap();
+
+        let mode = |name| {
+            std::fs::metadata(dest.join(name))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o7777
+        };
+        assert_eq!(mode("run.sh"), 0o755);
+        // Special bits dropped, owner read/write always kept.
+        assert_eq!(mode("suid"), 0o777);
+        assert_eq!(mode("ro.txt"), 0o600);
+    }
+
+    #[test]
+    fn extract_archive_keeps_archive_with_unsafe_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let zip = make_zip(tmp.path(), "evil.zip", &[("../x", "up")]);
+        let dest = tmp.path().join("dest");
+
+        let err =
+            extract_archive_sync(&zip, dest.to_str().unwrap(), false, &AtomicBool::new(false))
+                .unwrap_err();
+
+        assert!(err.to_string().contains("不安全的条目"), "{err}");
+        assert!(std::path::Path::new(&zip).exists());
+    }
+
+    // ─── Single-entry access ─────────────────────────────────────────────────
+
+    #[test]
+    fn zip_entries_list_and_extract() {
+        let tmp = tempfile::tempdir().unwrap();
+        let zip = make_zip(tmp.path(), "pack", &[("data/a.txt", "hello")]);
+        let dest = tmp.path().join("out");
+
+        let entries = list_archive_entries_sync(&zip).unwrap();
+        extract_entry_sync(&zip, "data/a.txt", &dest.to_string_lossy()).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "data/a.txt");
+        assert_eq!(
+            std::fs::read_to_string(dest.join("data").join("a.txt")).unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn tar_gz_entries_are_refused_clearly() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pack.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            std::fs::File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "a.txt", &b"hello"[..])
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let list_err = list_archive_entries_sync(&path).unwrap_err();
+        let extract_err =
+            extract_entry_sync(&path, "a.txt", &tmp.path().to_string_lossy()).unwrap_err();
+
+        assert!(list_err.to_string().contains("tar.gz"), "{list_err}");
+        assert_eq!(list_err.to_string(), extract_err.to_string());
+    }
+
+    // ─── Running games ───────────────────────────────────────────────────────
+
+    fn app_state() -> RwLock<AppState> {
+        let network = crate::net::NetworkOptions::default();
+        RwLock::new(AppState {
+            download_manager: Arc::new(DownloadManager::new(1, &network, None).unwrap()),
+            http_client: reqwest::Client::new(),
+            running_games: HashMap::new(),
+            install_groups: HashMap::new(),
+            extractions: HashMap::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn stale_watcher_leaves_relaunched_game_tracked() {
+        let state = app_state();
+        let (old, new) = (SysPid::from_u32(100), SysPid::from_u32(200));
+        assert!(claim_running_game(&state, "arknights", old).await);
+
+        // Stopped and relaunched before the first watcher saw the exit.
+        assert!(release_running_game(&state, "arknights", old).await);
+        assert!(claim_running_game(&state, "arknights", new).await);
+
+        assert!(!release_running_game(&state, "arknights", old).await);
+        assert_eq!(
+            state.read().await.running_games.get("arknights"),
+            Some(&new)
+        );
+        assert!(release_running_game(&state, "arknights", new).await);
+        assert!(state.read().await.running_games.is_empty());
+    }
+
+    async fn add_install_task(state: &RwLock<AppState>, name: &str) -> String {
+        let dm = Arc::clone(&state.read().await.download_manager);
+        let id = dm
+            .create_task(
+                "arknights".into(),
+                name.into(),
+                format!("https://cdn.example.com/{name}"),
+                name.into(),
+                Some(1024),
+                None,
+                None,
+                HashMap::new(),
+            )
+            .await
+            .unwrap();
+        state
+            .write()
+            .await
+            .install_groups
+            .entry("arknights".into())
+            .or_default()
+            .insert(id.clone());
+        id
+    }
+
+    #[tokio::test]
+    async fn new_install_starts_a_fresh_group() {
+        let state = app_state();
+        add_install_task(&state, "old.zip").await;
+
+        reset_install_group(&state, "arknights").await;
+        let new = add_install_task(&state, "new.zip").await;
+
+        let ids: Vec<String> = install_tasks(&state, "arknights")
+            .await
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(ids, vec![new]);
+    }
+}
@@ -1,9 +1,16 @@
+use crate::download::VerifyMode;
+use crate::game::GameChannel;
+use crate::net::{IpMode, NetworkOptions};
 use anyhow::Result;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 use tauri::Manager;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 
 // ─── Types ────────────────────────────────────────────────────────────────────
 
@@ -14,6 +21,114 @@ pub struct AppSettings {
     pub language: String,
     pub download_path: String,
     pub proxy_url: Option<String>,
+    /// game_id → install into `{dest_dir}/{game_id}` instead of `dest_dir` itself
+    #[serde(default)]
+    pub nest_install_dir: HashMap<String, bool>,
+    /// Installs larger than this (in GB) require explicit confirmation.
+    #[serde(default = "default_max_install_size_gb")]
+    pub max_install_size_gb: u64,
+    /// Force outgoing connections onto IPv4 or IPv6
+    #[serde(default)]
+    pub ip_mode: IpMode,
+    /// Optional DNS-over-HTTPS JSON endpoint used instead of the system resolver
+    #[serde(default)]
+    pub doh_url: Option<String>,
+    /// Files hashed at once by `verify_game_files`. 1 suits HDDs, where
+    /// parallel reads turn into seeking; SSDs benefit from higher values.
+    #[serde(default = "default_verify_concurrency")]
+    pub verify_concurrency: usize,
+    /// Where install packs are downloaded before extraction; None = the
+    /// install directory itself
+    #[serde(default)]
+    pub temp_download_dir: Option<String>,
+    /// Which checksum to check after a download completes
+    #[serde(default)]
+    pub verify_mode: VerifyMode,
+    /// Never delete archives after extraction or partial files on cancel.
+    /// For debugging the install pipeline only.
+    #[serde(default)]
+    pub debug_keep_all_files: bool,
+    /// Keep downloaded packs after they are extracted, e.g. to install on
+    /// another machine. Default for `extract_game_packs`.
+    #[serde(default)]
+    pub keep_archives: bool,
+    /// Refuse (instead of only warning about) a game path already used by
+    /// another game
+    #[serde(default)]
+    pub reject_path_conflicts: bool,
+    /// Files downloaded at the same time
+    #[serde(default = "default_download_concurrency")]
+    pub download_concurrency: usize,
+    /// Ranged connections per large file when the server supports ranges;
+    /// 1 downloads every file over a single connection
+    #[serde(default = "default_download_threads")]
+    pub download_threads: usize,
+    /// Packs extracted at the same time; packs writing the same files are
+    /// still extracted one after another
+    #[serde(default = "default_extract_concurrency")]
+    pub extract_concurrency: usize,
+    /// Combined download speed cap across all tasks in KB/s; 0 = unlimited
+    #[serde(default)]
+    pub max_download_speed_kbps: u64,
+    /// Base URLs (`https://host[/prefix]`) serving the same paths as the
+    /// official CDN; packs fall back to them in order when downloads fail
+    #[serde(default)]
+    pub mirror_bases: Vec<String>,
+    /// Server per game id (官服 / B 服); games not listed use the official one
+    #[serde(default)]
+    pub game_channels: HashMap<String, GameChannel>,
+}
+
+impl AppSettings {
+    /// `verify_concurrency` clamped to 1..=CPU count.
+    pub fn effective_verify_concurrency(&self) -> usize {
+        let cpus = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        self.verify_concurrency.clamp(1, cpus)
+    }
+
+    pub fn network_options(&self) -> NetworkOptions {
+        NetworkOptions {
+            ip_mode: self.ip_mode,
+            doh_url: self.doh_url.clone(),
+            proxy_url: self
+                .proxy_url
+                .as_deref()
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_string),
+        }
+    }
+
+    /// Whether extraction leaves the packs on disk.
+    pub fn keeps_archives(&self) -> bool {
+        self.keep_archives || self.debug_keep_all_files
+    }
+
+    pub fn game_channel(&self, game_id: &str) -> GameChannel {
+        self.game_channels.get(game_id).copied().unwrap_or_default()
+    }
+}
+
+fn default_max_install_size_gb() -> u64 {
+    200
+}
+
+fn default_verify_concurrency() -> usize {
+    1
+}
+
+fn default_download_concurrency() -> usize {
+    3
+}
+
+fn default_download_threads() -> usize {
+    1
+}
+
+fn default_extract_concurrency() -> usize {
+    2
 }
 
 impl Default for AppSettings {
@@ -23,6 +138,22 @@ impl Default for AppSettings {
             language: "zh-CN".to_string(),
             download_path: String::new(),
             proxy_url: None,
+            nest_install_dir: HashMap::new(),
+            max_install_size_gb: default_max_install_size_gb(),
+            ip_mode: IpMode::default(),
+            doh_url: None,
+            verify_concurrency: default_verify_concurrency(),
+            temp_download_dir: None,
+            verify_mode: VerifyMode::default(),
+            debug_keep_all_files: false,
+            keep_archives: false,
+            reject_path_conflicts: false,
+            download_concurrency: default_download_concurrency(),
+            download_threads: default_download_threads(),
+            extract_concurrency: default_extract_concurrency(),
+            max_download_speed_kbps: 0,
+            mirror_bases: Vec::new(),
+            game_channels: HashMap::new(),
         }
     }
 }
@@ -30,18 +161,61 @@ impl Default for AppSettings {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct AppConfig {
+    /// Shape of the file; see `migrate`. Missing in files written before
+    /// versioning, which count as version 1.
+    #[serde(default)]
+    pub schema_version: u32,
     #[serde(default)]
     pub settings: AppSettings,
     /// game_id → install path
     #[serde(default)]
     pub game_paths: HashMap<String, String>,
+    /// game_id → user-selected executable, used instead of auto-detection
+    #[serde(default)]
+    pub exe_overrides: HashMap<String, String>,
     /// Persisted Hypergryph account session (shared across games)
     #[serde(default)]
     pub hypergryph_session: Option<HypergryphSession>,
+    /// Manual pity baselines for players with gaps in their history
+    #[serde(default)]
+    pub pity_anchors: Vec<PityAnchor>,
+    /// game_id → arguments and environment passed by `launch_game`
+    #[serde(default)]
+    pub launch_options: HashMap<String, LaunchOptions>,
+}
+
+/// Extra command line, environment and hook commands for starting a game.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchOptions {
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Shell command run to completion before the game starts; the launch
+    /// is aborted if it fails
+    #[serde(default)]
+    pub pre_launch_cmd: Option<String>,
+    /// Shell command run after the game process exits
+    #[serde(default)]
+    pub post_exit_cmd: Option<String>,
+}
+
+/// "As of `as_of_ts`, my pity in `pool_type` was `known_pity`."
+/// Records after that timestamp are counted from this baseline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PityAnchor {
+    pub game_id: String,
+    pub uid: String,
+    pub pool_type: String,
+    pub known_pity: u32,
+    pub as_of_ts: i64,
 }
 
 /// Account-level session token from as.hypergryph.com.
-/// Only the token (not password) is persisted.
+/// Only the token (not password) is persisted, encrypted on disk (see
+/// `seal_token`); in memory it is always plaintext.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HypergryphSession {
@@ -49,6 +223,29 @@ pub struct HypergryphSession {
     pub phone_masked: String,
     pub uid: String,
     pub token: String,
+    /// Unix seconds after which the token is assumed dead. `None` for
+    /// sessions saved before expiry was tracked.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+/// Re-login this long before the recorded expiry, so a fetch doesn't start
+/// with a token that dies halfway through.
+const SESSION_EXPIRY_MARGIN_SECS: u64 = 10 * 60;
+
+impl HypergryphSession {
+    /// Whether the token can still be used. Sessions without a recorded
+    /// expiry are trusted until the API rejects them.
+    pub fn session_valid(&self) -> bool {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        !self.token.is_empty()
+            && self
+                .expires_at
+                .is_none_or(|t| now + SESSION_EXPIRY_MARGIN_SECS < t)
+    }
 }
 
 // ─── Persistence ──────────────────────────────────────────────────────────────
@@ -61,22 +258,344 @@ fn config_path(app: &tauri::AppHandle) -> Result<PathBuf> {
     Ok(dir.join("config.json"))
 }
 
+// ─── Token encryption ─────────────────────────────────────────────────────────
+
+/// Marks an encrypted token in config.json: `enc1:` + hex(nonce ‖ ciphertext ‖ tag).
+const SEALED_TOKEN_PREFIX: &str = "enc1:";
+
+/// AES-256 key for the session token, kept apart from config.json so that
+/// file alone (shared for troubleshooting, synced, backed up) doesn't expose
+/// the account.
+fn key_path(app: &tauri::AppHandle) -> Result<PathBuf> {
+    Ok(config_path(app)?.with_file_name("session.key"))
+}
+
+async fn read_key(path: &std::path::Path) -> Option<LessSafeKey> {
+    let bytes = fs::read(path).await.ok()?;
+    let key = UnboundKey::new(&AES_256_GCM, &bytes).ok()?;
+    Some(LessSafeKey::new(key))
+}
+
+/// The existing key, or a new random one written with owner-only access.
+async fn load_or_create_key(path: &std::path::Path) -> Result<LessSafeKey> {
+    if let Some(key) = read_key(path).await {
+        return Ok(key);
+    }
+    let mut bytes = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("无法生成加密密钥"))?;
+    // Created with its final mode so the key is never readable by others,
+    // not even briefly. An unreadable old key is removed first rather than
+    // overwritten, so its permissions don't carry over.
+    let _ = fs::remove_file(path).await;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).await?;
+    file.write_all(&bytes).await?;
+    file.flush().await?;
+    log::info!("created session key {}", path.display());
+    let key =
+        UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| anyhow::anyhow!("无法生成加密密钥"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn seal_token(key: &LessSafeKey, token: &str) -> Result<String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow::anyhow!("无法生成随机数"))?;
+    let mut sealed = token.as_bytes().to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut sealed,
+    )
+    .map_err(|_| anyhow::anyhow!("加密登录凭据失败"))?;
+    Ok(format!(
+        "{SEALED_TOKEN_PREFIX}{}{}",
+        hex::encode(nonce),
+        hex::encode(sealed)
+    ))
+}
+
+/// `None` when the data is malformed or was sealed with another key.
+fn open_token(key: &LessSafeKey, sealed: &str) -> Option<String> {
+    let mut bytes = hex::decode(sealed.strip_prefix(SEALED_TOKEN_PREFIX)?).ok()?;
+    if bytes.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = bytes.split_at_mut(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let plain = key.open_in_place(nonce, Aad::empty(), ciphertext).ok()?;
+    String::from_utf8(plain.to_vec()).ok()
+}
+
+/// Decrypt the loaded session token in place. A token that can't be
+/// decrypted (key deleted or replaced) logs the user out instead of failing
+/// the load. Returns true when the file still held a plaintext token and
+/// should be re-saved.
+async fn unseal_session(app: &tauri::AppHandle, config: &mut AppConfig) -> Result<bool> {
+    let Some(session) = config.hypergryph_session.as_mut() else {
+        return Ok(false);
+    };
+    if !session.token.starts_with(SEALED_TOKEN_PREFIX) {
+        return Ok(!session.token.is_empty());
+    }
+    let opened = match read_key(&key_path(app)?).await {
+        Some(key) => open_token(&key, &session.token),
+        None => None,
+    };
+    match opened {
+        Some(token) => session.token = token,
+        None => {
+            log::warn!("stored session token could not be decrypted; logging out");
+            config.hypergryph_session = None;
+        }
+    }
+    Ok(false)
+}
+
+// ─── Schema migration ─────────────────────────────────────────────────────────
+
+/// Version written by this build. Bump it together with a new step in
+/// `migrate` whenever a field is renamed, moved or reinterpreted; plain
+/// additions with `#[serde(default)]` don't need a bump.
+pub const CONFIG_SCHEMA_VERSION: u32 = 2;
+
+/// Why `save_config` must not touch config.json this session, if it must
+/// not: the file came from a newer launcher (saving would drop the fields
+/// this build doesn't know), or it couldn't be loaded (saving would replace
+/// it with defaults).
+static CONFIG_READ_ONLY: OnceLock<&'static str> = OnceLock::new();
+
+/// Fallback for a failed `load_config`: defaults for this session, with
+/// saving disabled so they never overwrite the file on disk.
+pub fn defaults_after_load_error(err: &anyhow::Error) -> AppConfig {
+    log::error!(
+        "config.json could not be loaded ({:#}); using defaults without saving",
+        err
+    );
+    let _ = CONFIG_READ_ONLY.set("配置文件加载失败，为避免覆盖原有设置已拒绝写入，请检查后重启");
+    AppConfig {
+        schema_version: CONFIG_SCHEMA_VERSION,
+        ..AppConfig::default()
+    }
+}
+
+/// Upgrade a raw config object, one version step at a time, to
+/// `CONFIG_SCHEMA_VERSION`. Returns the version it started from.
+fn migrate(value: &mut serde_json::Value) -> u32 {
+    let from = value
+        .get("schemaVersion")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32)
+        .unwrap_or(1);
+    let mut version = from;
+
+    if version == 1 {
+        // v1 stored "no proxy" as either null or an empty string; v2 only
+        // uses null.
+        if let Some(proxy) = value.pointer_mut("/settings/proxyUrl") {
+            if proxy.as_str().is_some_and(|p| p.trim().is_empty()) {
+                *proxy = serde_json::Value::Null;
+            }
+        }
+        version = 2;
+    }
+
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("schemaVersion".into(), version.into());
+    }
+    from
+}
+
 pub async fn load_config(app: &tauri::AppHandle) -> Result<AppConfig> {
     let path = config_path(app)?;
     if !path.exists() {
-        return Ok(AppConfig::default());
+        return Ok(AppConfig {
+            schema_version: CONFIG_SCHEMA_VERSION,
+            ..AppConfig::default()
+        });
     }
     let raw = fs::read_to_string(&path).await?;
-    let config: AppConfig = serde_json::from_str(&raw).unwrap_or_default();
+
+    let mut value: serde_json::Value = match serde_json::from_str(&raw) {
+        Ok(v) => v,
+        Err(e) => return set_aside(&path, e).await,
+    };
+
+    let found = value
+        .get("schemaVersion")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(1);
+    if found > CONFIG_SCHEMA_VERSION as u64 {
+        log::warn!(
+            "config.json has schema version {} but this build only knows {}; \
+             loading what it can and NOT saving changes",
+            found,
+            CONFIG_SCHEMA_VERSION
+        );
+        let _ = CONFIG_READ_ONLY.set("配置文件来自更新版本的启动器，为避免丢失设置已拒绝写入");
+        let mut config: AppConfig = serde_json::from_value(value)?;
+        unseal_session(app, &mut config).await?;
+        return Ok(config);
+    }
+
+    let from = migrate(&mut value);
+    let mut config: AppConfig = match serde_json::from_value(value) {
+        Ok(config) => config,
+        Err(e) => return set_aside(&path, e).await,
+    };
+    let plaintext_token = unseal_session(app, &mut config).await?;
+    // The loaded config is good even if re-saving it fails; the file still
+    // holds the old form and is upgraded again on the next start.
+    if plaintext_token && from == CONFIG_SCHEMA_VERSION {
+        log::info!("encrypting the plaintext session token in config.json");
+        if let Err(e) = save_config(app, &config).await {
+            log::warn!(
+                "re-saving config.json with an encrypted token failed: {}",
+                e
+            );
+        }
+    }
+    if from < CONFIG_SCHEMA_VERSION {
+        log::info!(
+            "config.json migrated from schema {} to {}",
+            from,
+            CONFIG_SCHEMA_VERSION
+        );
+        // Keep the pre-migration file in case the upgrade loses something,
+        // minus a plaintext token that would otherwise outlive encryption.
+        let backup = path.with_extension(format!("json.v{from}"));
+        let copied = if plaintext_token {
+            let mut original: serde_json::Value = serde_json::from_str(&raw)?;
+            if let Some(obj) = original.as_object_mut() {
+                obj.remove("hypergryphSession");
+            }
+            fs::write(&backup, serde_json::to_string_pretty(&original)?).await
+        } else {
+            fs::copy(&path, &backup).await.map(|_| ())
+        };
+        if let Err(e) = copied {
+            log::warn!("config backup to {} failed: {}", backup.display(), e);
+        }
+        if let Err(e) = save_config(app, &config).await {
+            log::warn!("saving the migrated config.json failed: {}", e);
+        }
+    }
     Ok(config)
 }
 
+/// Move an unreadable config.json out of the way, so starting over with
+/// defaults doesn't overwrite it on the next save. If it can't be moved the
+/// load fails, and the caller falls back to `defaults_after_load_error`.
+async fn set_aside(path: &std::path::Path, err: serde_json::Error) -> Result<AppConfig> {
+    let backup = path.with_extension("json.broken");
+    log::error!(
+        "config.json could not be read ({}), moved to {}",
+        err,
+        backup.display()
+    );
+    fs::rename(path, &backup).await?;
+    Ok(AppConfig {
+        schema_version: CONFIG_SCHEMA_VERSION,
+        ..AppConfig::default()
+    })
+}
+
 pub async fn save_config(app: &tauri::AppHandle, config: &AppConfig) -> Result<()> {
+    if let Some(reason) = CONFIG_READ_ONLY.get() {
+        return Err(anyhow::anyhow!(*reason));
+    }
     let path = config_path(app)?;
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent).await?;
     }
-    let raw = serde_json::to_string_pretty(config)?;
+    let mut config = AppConfig {
+        schema_version: CONFIG_SCHEMA_VERSION,
+        ..config.clone()
+    };
+    if let Some(session) = config
+        .hypergryph_session
+        .as_mut()
+        .filter(|s| !s.token.is_empty())
+    {
+        let key = load_or_create_key(&key_path(app)?).await?;
+        session.token = seal_token(&key, &session.token)?;
+    }
+    let raw = serde_json::to_string_pretty(&config)?;
     fs::write(&path, raw).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn migrate_v1_nulls_empty_proxy() {
+        let mut value = json!({ "settings": { "proxyUrl": "  " } });
+
+        assert_eq!(migrate(&mut value), 1);
+
+        assert_eq!(value["settings"]["proxyUrl"], serde_json::Value::Null);
+        assert_eq!(value["schemaVersion"], CONFIG_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn migrate_v1_keeps_real_proxy() {
+        let mut value = json!({
+            "schemaVersion": 1,
+            "settings": { "proxyUrl": "http://127.0.0.1:7890" }
+        });
+
+        migrate(&mut value);
+
+        assert_eq!(value["settings"]["proxyUrl"], "http://127.0.0.1:7890");
+    }
+
+    #[test]
+    fn migrate_stamps_current_version() {
+        let mut value = json!({ "schemaVersion": CONFIG_SCHEMA_VERSION, "settings": {} });
+
+        assert_eq!(migrate(&mut value), CONFIG_SCHEMA_VERSION);
+
+        assert_eq!(value["schemaVersion"], CONFIG_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn migrate_leaves_newer_version_untouched() {
+        let newer = CONFIG_SCHEMA_VERSION + 1;
+        let original = json!({
+            "schemaVersion": newer,
+            "settings": { "proxyUrl": "" },
+            "futureField": true
+        });
+        let mut value = original.clone();
+
+        assert_eq!(migrate(&mut value), newer);
+
+        assert_eq!(value, original);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn new_key_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("session.key");
+        // A corrupt key left with loose permissions is replaced.
+        std::fs::write(&path, b"short").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        load_or_create_key(&path).await.unwrap();
+
+        let meta = std::fs::metadata(&path).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        assert_eq!(meta.len(), 32);
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+// ─── Types ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PackStatus {
+    Pending,
+    Extracting,
+    Done,
+}
+
+/// One pack of an extraction run, with enough information to re-download it
+/// if the archive was already deleted when the app died.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalPack {
+    pub name: String,
+    pub zip_path: String,
+    pub dest_dir: String,
+    pub url: String,
+    pub md5: Option<String>,
+    pub size: u64,
+    pub status: PackStatus,
+}
+
+/// game_id → packs of the extraction run in progress for that game.
+/// An entry is removed once every pack has been extracted, so anything left
+/// in the journal at startup is an interrupted extraction.
+pub type ExtractionJournal = HashMap<String, Vec<JournalPack>>;
+
+// ─── Persistence ───────────────────────────────────────────────────────────
+
+/// Serializes read-modify-write cycles across concurrent extractions.
+static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+pub fn load_journal(path: &Path) -> ExtractionJournal {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// Apply `f` to the on-disk journal and write it back.
+pub fn update_journal(path: &Path, f: impl FnOnce(&mut ExtractionJournal)) -> Result<()> {
+    let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut journal = load_journal(path);
+    f(&mut journal);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&journal)?)?;
+    Ok(())
+}
+
+/// Set the status of one pack of a game's extraction run.
+pub fn mark_pack(path: &Path, game_id: &str, zip_path: &str, status: PackStatus) -> Result<()> {
+    update_journal(path, |journal| {
+        if let Some(pack) = journal
+            .get_mut(game_id)
+            .and_then(|packs| packs.iter_mut().find(|p| p.zip_path == zip_path))
+        {
+            pack.status = status;
+        }
+    })
+}
@@ -0,0 +1,32 @@
+use log::{LevelFilter, Metadata};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Runtime verbosity for log records coming from the download module.
+/// Other modules are unaffected.
+static LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+
+const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+pub fn set_level(level: LevelFilter) {
+    LEVEL.store(level as usize, Ordering::Relaxed);
+    log::info!("[dl] log level set to {level}");
+}
+
+pub fn level() -> LevelFilter {
+    LEVELS[LEVEL.load(Ordering::Relaxed).min(LEVELS.len() - 1)]
+}
+
+/// Filter for the log plugin: download records must pass the runtime level.
+pub fn allows(metadata: &Metadata) -> bool {
+    !metadata
+        .target()
+        .starts_with(module_path!().trim_end_matches("::logging"))
+        || metadata.level() <= level()
+}
@@ -1,13 +1,16 @@
+use super::throttle::RateLimiter;
+use crate::net::{apply_network_options, NetworkOptions};
 use anyhow::{anyhow, Context, Result};
 use futures_util::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::fs::{self, OpenOptions};
-use tokio::io::{AsyncSeekExt, AsyncWriteExt, SeekFrom};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
 use tokio::sync::{Mutex, RwLock};
 use tokio::task::JoinHandle;
 use uuid::Uuid;
@@ -34,6 +37,12 @@ pub struct DownloadChunk {
     pub end: u64,
     pub downloaded: u64,
     pub completed: bool,
+    /// Alternative URLs serving the same bytes; the fastest probed one is used.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// URL actually being downloaded from in this session (runtime only).
+    #[serde(skip)]
+    pub active_url: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +62,39 @@ pub struct DownloadTask {
     pub chunks: Vec<DownloadChunk>,
     pub sha256: Option<String>,
     pub md5: Option<String>,
+    /// BLAKE3 of the finished file, computed locally for fast re-checks.
+    #[serde(default)]
+    pub blake3: Option<String>,
+    /// ETag (or Last-Modified) of the remote file, sent as `If-Range` on
+    /// resume so a changed upstream file restarts instead of corrupting.
+    #[serde(default)]
+    pub validator: Option<String>,
+    /// Extra request headers (auth, referer) for gated mirrors; validated
+    /// when the task is created.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Unix seconds when the task reached Completed.
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+    /// The last failure found the downloaded bytes bad (size or checksum),
+    /// so a retry must start over instead of resuming.
+    #[serde(default)]
+    pub corrupt: bool,
+}
+
+/// Which checksum `run_download` checks after the last byte arrives.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum VerifyMode {
+    /// SHA256 when provided, else MD5, else nothing.
+    #[default]
+    Auto,
+    /// Require SHA256; fail if the task has none.
+    Sha256Only,
+    /// Require MD5; fail if the task has none.
+    Md5Only,
+    /// Trust the source and skip verification.
+    Skip,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,59 +104,387 @@ pub struct DownloadProgress {
     pub downloaded_size: u64,
     pub total_size: u64,
     pub progress: f64,
+    /// Bytes/s over the last few seconds
     pub speed: u64,
+    /// Seconds left at the current speed; None while stalled or size unknown
+    #[serde(default)]
+    pub eta_seconds: Option<u64>,
     pub status: DownloadStatus,
     pub error: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkDetail {
+    pub id: usize,
+    pub start: u64,
+    pub end: u64,
+    pub downloaded: u64,
+    pub progress: f64,
+    pub completed: bool,
+    /// Mirror in use this session, or the stored URL when not running.
+    pub url: String,
+}
+
+/// A task plus the derived numbers the task-detail panel shows.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskDetail {
+    pub task: DownloadTask,
+    pub remaining_bytes: u64,
+    /// Seconds left at the current speed; None when stalled or unknown.
+    pub eta_secs: Option<u64>,
+    pub chunks: Vec<ChunkDetail>,
+}
+
+/// Outcome of a small ranged GET against one candidate URL.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MirrorResult {
+    pub url: String,
+    pub ok: bool,
+    /// Time to first byte in milliseconds.
+    pub ttfb_ms: Option<u64>,
+    /// Bytes per second over the probe slice.
+    pub throughput: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Measured throughput against a pack URL, e.g. "预计下载速度约 20 MB/s".
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedBenchmark {
+    pub url: String,
+    /// Bytes actually received (may be less than requested for small files).
+    pub bytes: u64,
+    /// Time to first byte in milliseconds.
+    pub latency_ms: u64,
+    pub mb_per_sec: f64,
+}
+
+/// Snapshot of download slot usage, e.g. "正在下载 3/3，排队 5".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConcurrencyStatus {
+    pub max: usize,
+    /// Permits currently held by running downloads.
+    pub active: usize,
+    /// Started tasks still waiting for a permit.
+    pub queued: usize,
+}
+
 // ─── Download Manager ───────────────────────────────────────────────────────
 
 pub struct DownloadManager {
-    client: Client,
+    /// Replaced by `set_network`; running downloads keep the client they
+    /// started with.
+    client: std::sync::RwLock<Client>,
     tasks: Arc<RwLock<HashMap<String, DownloadTask>>>,
     handles: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    /// Per-task cancellation flags, checked between buffers while verifying.
+    cancel_flags: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
     /// Limits how many files can be actively downloading at once.
     semaphore: Arc<tokio::sync::Semaphore>,
-    persist_path: Option<Arc<PathBuf>>,
+    /// Current limit; the semaphore's permit count follows it.
+    max_concurrent: AtomicUsize,
+    /// Number of tasks holding a semaphore permit.
+    active: Arc<AtomicUsize>,
+    /// Number of spawned tasks waiting on the semaphore.
+    queued: Arc<AtomicUsize>,
+    store: Option<Arc<TaskStore>>,
+    /// URL (without query) → filename resolved from Content-Disposition.
+    filename_cache: Arc<RwLock<HashMap<String, String>>>,
+    /// URL → (probed at, result); entries expire after MIRROR_PROBE_TTL.
+    probe_cache: Arc<RwLock<HashMap<String, (std::time::Instant, MirrorResult)>>>,
+    /// Read when a task starts, so changes apply to the next download.
+    verify_mode: std::sync::RwLock<VerifyMode>,
+    /// `debug_keep_all_files`: leave partial files behind on cancel.
+    keep_files: AtomicBool,
+    /// Hosts where a segmented download came out corrupt; files from them are
+    /// fetched over a single connection for the rest of the session.
+    unsegmented_hosts: Arc<std::sync::Mutex<HashSet<String>>>,
+    /// `download_threads`: ranged chunks per file for new tasks.
+    download_threads: AtomicUsize,
+    /// `max_download_speed_kbps`, shared by every running chunk.
+    limiter: Arc<RateLimiter>,
 }
 
+/// The downloads.json file. Every write goes through `save`, which holds
+/// `write_lock` while snapshotting and writing, so concurrent saves can't
+/// interleave or let an older snapshot land after a newer one.
+/// Completed tasks are appended to downloads_history.json next to it, under
+/// the same lock.
+struct TaskStore {
+    path: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+/// Most entries kept in downloads_history.json; the oldest are dropped.
+const HISTORY_LIMIT: usize = 500;
+
+impl TaskStore {
+    /// Write all non-completed tasks. The snapshot is written to a temp file
+    /// and renamed over the old one, so a crash mid-write leaves either the
+    /// previous or the new file, never a truncated one.
+    async fn save(&self, tasks: &RwLock<HashMap<String, DownloadTask>>) {
+        // Lock order: write_lock before the tasks lock.
+        let _guard = self.write_lock.lock().await;
+        let raw = {
+            let tasks = tasks.read().await;
+            let to_save: HashMap<&String, &DownloadTask> = tasks
+                .iter()
+                .filter(|(_, t)| {
+                    !matches!(t.status, DownloadStatus::Completed | DownloadStatus::Error)
+                })
+                .collect();
+            match serde_json::to_string_pretty(&to_save) {
+                Ok(raw) => raw,
+                Err(e) => {
+                    log::error!("[dl] persist serialize failed: {}", e);
+                    return;
+                }
+            }
+        };
+        let tmp = self.path.with_extension("json.tmp");
+        let result = async {
+            fs::write(&tmp, raw).await?;
+            fs::rename(&tmp, &self.path).await
+        }
+        .await;
+        if let Err(e) = result {
+            log::error!("[dl] persist write failed: {}", e);
+        }
+    }
+
+    fn history_path(&self) -> PathBuf {
+        self.path.with_file_name("downloads_history.json")
+    }
+
+    /// Completed tasks, newest first.
+    async fn load_history(&self) -> Vec<DownloadTask> {
+        match fs::read_to_string(self.history_path()).await {
+            Ok(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+                log::warn!("[dl] history file unreadable, starting over: {}", e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    async fn record_history(&self, task: DownloadTask) {
+        let _guard = self.write_lock.lock().await;
+        let mut history = self.load_history().await;
+        history.retain(|t| t.id != task.id);
+        history.insert(0, task);
+        history.truncate(HISTORY_LIMIT);
+        self.write_history(&history).await;
+    }
+
+    /// Apply `update` to the history entry `task_id`, keeping its position.
+    async fn update_history(&self, task_id: &str, update: impl FnOnce(&mut DownloadTask)) {
+        let _guard = self.write_lock.lock().await;
+        let mut history = self.load_history().await;
+        let Some(task) = history.iter_mut().find(|t| t.id == task_id) else {
+            return;
+        };
+        update(task);
+        self.write_history(&history).await;
+    }
+
+    /// Callers hold `write_lock`.
+    async fn write_history(&self, history: &[DownloadTask]) {
+        let raw = match serde_json::to_string(history) {
+            Ok(raw) => raw,
+            Err(e) => {
+                log::error!("[dl] history serialize failed: {}", e);
+                return;
+            }
+        };
+        let path = self.history_path();
+        let tmp = path.with_extension("json.tmp");
+        let result = async {
+            fs::write(&tmp, raw).await?;
+            fs::rename(&tmp, &path).await
+        }
+        .await;
+        if let Err(e) = result {
+            log::error!("[dl] history write failed: {}", e);
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// How often `download_chunk` copies live progress into the stored task.
+const LIVE_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+/// Span the reported speed is averaged over.
+const SPEED_WINDOW: std::time::Duration = std::time::Duration::from_secs(3);
+/// Minimum gap between speed samples, to bound the window's size.
+const SPEED_SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// How long a mirror probe result is trusted before re-probing.
+const MIRROR_PROBE_TTL: std::time::Duration = std::time::Duration::from_secs(300);
+/// Size of the ranged slice fetched when probing a mirror.
+const MIRROR_PROBE_BYTES: u64 = 256 * 1024;
+/// Slice fetched by `benchmark`; large enough to get past TCP slow start.
+const BENCHMARK_BYTES: u64 = 16 * 1024 * 1024;
+/// Attempts per URL before a chunk moves on to its next mirror.
+const ATTEMPTS_PER_URL: usize = 2;
+/// Pause before retrying a chunk, multiplied by the attempt number.
+const CHUNK_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+/// Files are only split when every chunk gets at least this much.
+const MIN_CHUNK_SIZE: u64 = 32 * 1024 * 1024;
+/// Upper bound for `download_threads`.
+const MAX_DOWNLOAD_THREADS: usize = 16;
+/// Upper bound for `download_concurrency`.
+const MAX_CONCURRENT_DOWNLOADS: usize = 16;
+
 impl DownloadManager {
     /// `max_concurrent` — how many files download simultaneously (e.g. 3).
+    /// The proxy, if any, comes with `network`.
     pub fn new(
         max_concurrent: usize,
-        proxy_url: Option<&str>,
+        network: &NetworkOptions,
         persist_path: Option<PathBuf>,
     ) -> Result<Self> {
-        let mut builder = Client::builder()
+        let max_concurrent = max_concurrent.clamp(1, MAX_CONCURRENT_DOWNLOADS);
+        Ok(Self {
+            client: std::sync::RwLock::new(Self::build_client(network)?),
+            tasks: Arc::new(RwLock::new(HashMap::new())),
+            handles: Arc::new(Mutex::new(HashMap::new())),
+            cancel_flags: Arc::new(Mutex::new(HashMap::new())),
+            semaphore: Arc::new(tokio::sync::Semaphore::new(max_concurrent)),
+            max_concurrent: AtomicUsize::new(max_concurrent),
+            active: Arc::new(AtomicUsize::new(0)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            store: persist_path.map(|path| {
+                Arc::new(TaskStore {
+                    path,
+                    write_lock: Mutex::new(()),
+                })
+            }),
+            filename_cache: Arc::new(RwLock::new(HashMap::new())),
+            probe_cache: Arc::new(RwLock::new(HashMap::new())),
+            verify_mode: std::sync::RwLock::new(VerifyMode::default()),
+            keep_files: AtomicBool::new(false),
+            unsegmented_hosts: Arc::new(std::sync::Mutex::new(HashSet::new())),
+            download_threads: AtomicUsize::new(1),
+            limiter: Arc::new(RateLimiter::new(0)),
+        })
+    }
+
+    fn build_client(network: &NetworkOptions) -> Result<Client> {
+        let builder = Client::builder()
             .user_agent("Mozilla/5.0 Highgarden/0.1.0")
             .tcp_keepalive(std::time::Duration::from_secs(30))
             // Only limit the TCP connect phase; do NOT set a total request
             // timeout — that would kill body streaming for large files.
             .connect_timeout(std::time::Duration::from_secs(30));
+        Ok(apply_network_options(builder, network).build()?)
+    }
 
-        if let Some(proxy) = proxy_url {
-            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    fn client(&self) -> Client {
+        self.client
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Rebuild the HTTP client after proxy/DNS/IP settings change. Tasks
+    /// started afterwards use it; running ones finish on the old client.
+    pub fn set_network(&self, network: &NetworkOptions) -> Result<()> {
+        let client = Self::build_client(network)?;
+        *self.client.write().unwrap_or_else(|e| e.into_inner()) = client;
+        log::info!(
+            "[dl] network settings applied (proxy={})",
+            network.proxy_url.as_deref().unwrap_or("none")
+        );
+        Ok(())
+    }
+
+    /// Whether a file from `url` may be split into several ranged requests.
+    pub fn segmentation_allowed(&self, url: &str) -> bool {
+        let Some(host) = url_host(url) else {
+            return true;
+        };
+        !self
+            .unsegmented_hosts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&host)
+    }
+
+    pub fn set_verify_mode(&self, mode: VerifyMode) {
+        *self.verify_mode.write().unwrap_or_else(|e| e.into_inner()) = mode;
+    }
+
+    fn verify_mode(&self) -> VerifyMode {
+        *self.verify_mode.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Change how many files download at once. Raising the limit lets queued
+    /// tasks start right away. Lowering it never interrupts a running task:
+    /// free permits are withdrawn immediately, and the rest as running tasks
+    /// finish, so no new task starts until the active count is under the new
+    /// limit.
+    pub fn set_concurrency(&self, max: usize) {
+        let max = max.clamp(1, MAX_CONCURRENT_DOWNLOADS);
+        let old = self.max_concurrent.swap(max, Ordering::SeqCst);
+        if max == old {
+            return;
         }
+        log::info!("[dl] concurrency {} → {}", old, max);
+        if max > old {
+            self.semaphore.add_permits(max - old);
+            return;
+        }
+        let excess = old - max;
+        let rest = excess - self.semaphore.forget_permits(excess);
+        if rest > 0 {
+            // The semaphore is FIFO, so this waiter also holds back tasks
+            // queued after it until enough running ones have released.
+            let semaphore = self.semaphore.clone();
+            tokio::spawn(async move {
+                if let Ok(permits) = semaphore.acquire_many_owned(rest as u32).await {
+                    permits.forget();
+                }
+            });
+        }
+    }
 
-        Ok(Self {
-            client: builder.build()?,
-            tasks: Arc::new(RwLock::new(HashMap::new())),
-            handles: Arc::new(Mutex::new(HashMap::new())),
-            semaphore: Arc::new(tokio::sync::Semaphore::new(max_concurrent)),
-            persist_path: persist_path.map(Arc::new),
-        })
+    /// Applies to tasks created afterwards; existing tasks keep their chunks.
+    pub fn set_download_threads(&self, threads: usize) {
+        let threads = threads.clamp(1, MAX_DOWNLOAD_THREADS);
+        self.download_threads.store(threads, Ordering::Relaxed);
+    }
+
+    /// Global cap in KB/s (0 = unlimited); applies to running downloads too.
+    pub fn set_speed_limit(&self, kbps: u64) {
+        self.limiter.set_limit_kbps(kbps);
+    }
+
+    pub fn set_keep_files(&self, keep: bool) {
+        if keep {
+            log::warn!("[dl] DEBUG: debug_keep_all_files is ON — partial files are kept");
+        }
+        self.keep_files.store(keep, Ordering::Relaxed);
     }
 
     /// Load tasks saved from the previous session. Called once at startup.
     /// Tasks that were actively "downloading" are reset to "paused".
     pub async fn load_persisted(&self) -> Result<()> {
-        let Some(path) = &self.persist_path else {
+        let Some(store) = &self.store else {
             return Ok(());
         };
-        if !path.exists() {
+        if !store.path.exists() {
             return Ok(());
         }
-        let raw = fs::read_to_string(path.as_ref()).await?;
+        let raw = fs::read_to_string(&store.path).await?;
         let saved: HashMap<String, DownloadTask> = serde_json::from_str(&raw).unwrap_or_default();
         let mut tasks = self.tasks.write().await;
         for (id, mut task) in saved {
@@ -133,29 +503,18 @@ impl DownloadManager {
 
     /// Persist all non-completed tasks to disk.
     async fn persist(&self) {
-        let Some(path) = &self.persist_path else {
-            return;
-        };
-        let tasks = self.tasks.read().await;
-        let to_save: HashMap<&String, &DownloadTask> = tasks
-            .iter()
-            .filter(|(_, t)| !matches!(t.status, DownloadStatus::Completed | DownloadStatus::Error))
-            .collect();
-        match serde_json::to_string_pretty(&to_save) {
-            Ok(raw) => {
-                if let Err(e) = fs::write(path.as_ref(), raw).await {
-                    log::error!("[dl] persist write failed: {}", e);
-                }
-            }
-            Err(e) => log::error!("[dl] persist serialize failed: {}", e),
+        if let Some(store) = &self.store {
+            store.save(&self.tasks).await;
         }
     }
 
     /// Create a new download task (single file, multi-chunk).
     ///
-    /// If `known_size` is provided (e.g. from the API manifest) the HEAD probe
-    /// is skipped entirely, which avoids issues with signed CDN URLs that do not
-    /// support HEAD.
+    /// If `known_size` is provided (e.g. from the API manifest) it is used as
+    /// the file size. The HEAD probe then only runs when the file would be
+    /// split, to confirm range support. Signed CDN URLs may not support HEAD,
+    /// so a failed probe falls back to a single connection.
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_task(
         &self,
         game_id: String,
@@ -165,60 +524,82 @@ impl DownloadManager {
         known_size: Option<u64>,
         sha256: Option<String>,
         md5: Option<String>,
+        headers: HashMap<String, String>,
     ) -> Result<String> {
-        let (total_size, _supports_range) = if let Some(size) = known_size {
-            log::info!("[dl] create_task name={name} size={size}");
-            (size, true)
+        let header_map = custom_headers(&headers)?;
+
+        // Ad-hoc downloads may come without a name, or with a meaningless
+        // hash derived from the URL; ask the server for the real one.
+        let name = if name.is_empty() || looks_like_hash(&name) {
+            self.resolve_filename(&url, &header_map).await
         } else {
-            log::info!("[dl] create_task name={name} — probing HEAD {url}");
-            let resp = self
-                .client
-                .head(&url)
-                .send()
-                .await
-                .context("HEAD request failed")?;
+            name
+        };
 
-            log::debug!(
-                "[dl] HEAD status={} headers={:?}",
-                resp.status(),
-                resp.headers()
-            );
+        // Splitting needs confirmed range support, so a known size alone is
+        // not enough when more than one thread is configured.
+        let threads = self.download_threads.load(Ordering::Relaxed);
+        let want_segments = threads > 1
+            && known_size.is_none_or(|s| s >= MIN_CHUNK_SIZE * 2)
+            && self.segmentation_allowed(&url);
 
-            let size = resp
-                .headers()
-                .get(reqwest::header::CONTENT_LENGTH)
-                .and_then(|v| v.to_str().ok())
-                .and_then(|s| s.parse::<u64>().ok())
-                .unwrap_or(0);
-
-            let range = resp
-                .headers()
-                .get(reqwest::header::ACCEPT_RANGES)
-                .map(|v| v != "none")
-                .unwrap_or(false);
+        let trusted_size = if want_segments { None } else { known_size };
+        let (total_size, supports_range, validator) = if let Some(size) = trusted_size {
+            log::info!("[dl] create_task name={name} size={size}");
+            (size, true, None)
+        } else {
+            log::info!("[dl] create_task name={name} — probing HEAD {url}");
+            match self.client().head(&url).headers(header_map).send().await {
+                Ok(resp) if resp.status().is_success() => {
+                    log::debug!(
+                        "[dl] HEAD status={} headers={:?}",
+                        resp.status(),
+                        resp.headers()
+                    );
 
-            log::info!("[dl] HEAD result: size={size} supports_range={range}");
-            (size, range)
+                    let size = known_size
+                        .or_else(|| {
+                            resp.headers()
+                                .get(reqwest::header::CONTENT_LENGTH)
+                                .and_then(|v| v.to_str().ok())
+                                .and_then(|s| s.parse::<u64>().ok())
+                        })
+                        .unwrap_or(0);
+
+                    let range = resp
+                        .headers()
+                        .get(reqwest::header::ACCEPT_RANGES)
+                        .map(|v| v != "none")
+                        .unwrap_or(false);
+
+                    log::info!("[dl] HEAD result: size={size} supports_range={range}");
+                    (size, range, response_validator(resp.headers()))
+                }
+                result => {
+                    let reason = match result {
+                        Ok(resp) => resp.status().to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    log::warn!("[dl] HEAD failed ({reason}), using a single connection");
+                    (known_size.unwrap_or(0), false, None)
+                }
+            }
         };
 
-        // Always a single chunk — concurrency across files is controlled by
-        // the semaphore in start_task, not by splitting the file.
-        let chunks = vec![DownloadChunk {
-            id: 0,
-            url: url.clone(),
-            start: 0,
-            end: total_size.saturating_sub(1),
-            downloaded: 0,
-            completed: false,
-        }];
+        // Concurrency across files is controlled by the semaphore in
+        // start_task; on top of that a large file may be split into ranged
+        // chunks that download in parallel into the same file.
+        let segments = if want_segments && supports_range {
+            threads.min((total_size / MIN_CHUNK_SIZE) as usize).max(1)
+        } else {
+            1
+        };
+        let chunks = split_chunks(&url, total_size, segments);
 
         log::info!("[dl] create_task name={name} chunks={}", chunks.len());
 
         let task_id = Uuid::new_v4().to_string();
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        let now = unix_now();
 
         let task = DownloadTask {
             id: task_id.clone(),
@@ -235,6 +616,11 @@ impl DownloadManager {
             chunks,
             sha256,
             md5,
+            blake3: None,
+            validator,
+            headers,
+            completed_at: None,
+            corrupt: false,
         };
 
         self.tasks.write().await.insert(task_id.clone(), task);
@@ -247,7 +633,7 @@ impl DownloadManager {
     where
         F: Fn(DownloadProgress) + Send + Sync + 'static,
     {
-        let task = {
+        let mut task = {
             let mut tasks = self.tasks.write().await;
             let task = tasks
                 .get_mut(&task_id)
@@ -256,7 +642,14 @@ impl DownloadManager {
 
             // Resume support: use the actual file size on disk as the resume offset.
             // This is crash-safe — file bytes written are the ground truth.
-            if let Ok(meta) = tokio::fs::metadata(&task.dest_path).await {
+            // Segmented files are pre-sized, so their length says nothing;
+            // they resume from the per-chunk progress saved with the task.
+            let single_chunk = task.chunks.len() == 1;
+            if let Some(meta) = tokio::fs::metadata(&task.dest_path)
+                .await
+                .ok()
+                .filter(|_| single_chunk)
+            {
                 let on_disk = meta.len();
                 if on_disk > 0 && on_disk < task.total_size {
                     log::info!(
@@ -275,6 +668,7 @@ impl DownloadManager {
                     log::info!("[dl] file already complete for {}", task.name);
                     task.status = DownloadStatus::Completed;
                     task.progress = 100.0;
+                    task.completed_at = Some(unix_now());
                 }
             }
 
@@ -283,6 +677,9 @@ impl DownloadManager {
 
         // File was already present — nothing to download.
         if task.status == DownloadStatus::Completed {
+            if let Some(store) = &self.store {
+                store.record_history(task.clone()).await;
+            }
             self.persist().await;
             return Ok(());
         }
@@ -296,20 +693,66 @@ impl DownloadManager {
             task.chunks.first().map(|c| c.downloaded).unwrap_or(0)
         );
 
+        // Start each chunk on its fastest reachable mirror; the stored task
+        // keeps the manifest URL so a later run re-evaluates. Chunks of one
+        // file normally share their candidates, so each set is probed once.
+        let header_map = custom_headers(&task.headers)?;
+        let mut fastest: HashMap<Vec<String>, Option<String>> = HashMap::new();
+        for chunk in task.chunks.iter_mut().filter(|c| !c.mirrors.is_empty()) {
+            let mut candidates = vec![chunk.url.clone()];
+            candidates.extend(chunk.mirrors.iter().cloned());
+            let best = match fastest.get(&candidates) {
+                Some(best) => best.clone(),
+                None => {
+                    let results = self.probe_mirrors(&candidates, &header_map).await;
+                    let best = fastest_mirror(&results).map(|r| r.url.clone());
+                    fastest.insert(candidates, best.clone());
+                    best
+                }
+            };
+            if let Some(best) = best {
+                if best != chunk.url {
+                    log::info!("[dl] chunk {} using mirror {}", chunk.id, best);
+                    // The manifest URL becomes the first fallback.
+                    chunk.mirrors.retain(|m| *m != best);
+                    let original = std::mem::replace(&mut chunk.url, best);
+                    chunk.mirrors.insert(0, original);
+                }
+            }
+        }
+
+        if let Some(t) = self.tasks.write().await.get_mut(&task_id) {
+            for (stored, chosen) in t.chunks.iter_mut().zip(&task.chunks) {
+                stored.active_url = Some(chosen.url.clone());
+            }
+        }
+
         // Ensure destination directory exists
         if let Some(parent) = Path::new(&task.dest_path).parent() {
             fs::create_dir_all(parent).await?;
         }
 
-        let client = self.client.clone();
+        let client = self.client();
         let tasks = self.tasks.clone();
         let task_id_clone = task_id.clone();
         let semaphore = self.semaphore.clone();
-        let persist_path = self.persist_path.clone();
+        let active = self.active.clone();
+        let queued = self.queued.clone();
+        let store = self.store.clone();
+        let verify_mode = self.verify_mode();
+        let unsegmented_hosts = self.unsegmented_hosts.clone();
+        let limiter = self.limiter.clone();
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.cancel_flags
+            .lock()
+            .await
+            .insert(task_id.clone(), cancel.clone());
 
         let handle = tokio::spawn(async move {
             // Wait for a download slot.  The permit is held for the entire
             // download and dropped automatically when this block ends.
+            // The counter guards also decrement if the task is aborted.
+            let queued_guard = CounterGuard::new(queued);
             let _permit = match semaphore.acquire().await {
                 Ok(p) => p,
                 Err(_) => {
@@ -317,10 +760,69 @@ impl DownloadManager {
                     return;
                 }
             };
+            drop(queued_guard);
+            let _active_guard = CounterGuard::new(active);
             log::info!("[dl] semaphore acquired → starting {}", task_id_clone);
 
-            let result = Self::run_download(client, tasks.clone(), task.clone(), on_progress).await;
+            let on_progress = Arc::new(on_progress);
+            let progress = |p: &Arc<F>| {
+                let p = p.clone();
+                move |progress: DownloadProgress| p(progress)
+            };
+            let mut result = Self::run_download(
+                client.clone(),
+                tasks.clone(),
+                task.clone(),
+                verify_mode,
+                cancel.clone(),
+                limiter.clone(),
+                progress(&on_progress),
+            )
+            .await;
+
+            // Some proxies/CDNs mangle ranged requests. Retry a corrupt
+            // segmented download once over a single connection.
+            let corrupt = result
+                .as_ref()
+                .err()
+                .and_then(|e| e.downcast_ref::<Corrupt>())
+                .map(|e| e.to_string());
+            if let Some(reason) = corrupt.filter(|_| task.chunks.len() > 1) {
+                log::warn!(
+                    "[dl] task {} corrupt after segmented download ({}), retrying over a single connection",
+                    task_id_clone,
+                    reason
+                );
+                if let Some(host) = task.chunks.first().and_then(|c| url_host(&c.url)) {
+                    log::warn!(
+                        "[dl] disabling segmented downloads from {} for this session",
+                        host
+                    );
+                    unsegmented_hosts
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(host);
+                }
+                let single = single_connection(&task);
+                if let Some(t) = tasks.write().await.get_mut(&task_id_clone) {
+                    t.chunks = single.chunks.clone();
+                    t.downloaded_size = 0;
+                    t.progress = 0.0;
+                }
+                let _ = fs::remove_file(&single.dest_path).await;
+                result = Self::run_download(
+                    client,
+                    tasks.clone(),
+                    single,
+                    verify_mode,
+                    cancel,
+                    limiter,
+                    progress(&on_progress),
+                )
+                .await;
+            }
 
+            let mut finished = None;
             let mut tasks_w = tasks.write().await;
             if let Some(t) = tasks_w.get_mut(&task_id_clone) {
                 match result {
@@ -328,26 +830,33 @@ impl DownloadManager {
                         log::info!("[dl] task {} completed: {}", task_id_clone, t.name);
                         t.status = DownloadStatus::Completed;
                         t.progress = 100.0;
+                        t.speed = 0;
+                        t.completed_at = Some(unix_now());
+                        finished = Some(t.clone());
+                    }
+                    // Interrupted verification is a pause, not a failure.
+                    Err(e) if e.is::<Cancelled>() => {
+                        log::info!("[dl] task {} verification cancelled", task_id_clone);
+                        t.status = DownloadStatus::Paused;
+                        t.speed = 0;
                     }
                     Err(e) => {
                         log::error!("[dl] task {} FAILED: {}", task_id_clone, e);
                         t.status = DownloadStatus::Error;
+                        t.corrupt = e.downcast_ref::<Corrupt>().is_some();
                         t.error = Some(e.to_string());
                     }
                 }
             }
+            drop(tasks_w);
 
-            // Persist after completion (completed/errored tasks are dropped from the file).
-            if let Some(path) = &persist_path {
-                let to_save: HashMap<&String, &DownloadTask> = tasks_w
-                    .iter()
-                    .filter(|(_, t)| {
-                        !matches!(t.status, DownloadStatus::Completed | DownloadStatus::Error)
-                    })
-                    .collect();
-                if let Ok(raw) = serde_json::to_string_pretty(&to_save) {
-                    let _ = fs::write(path.as_ref(), raw).await;
+            // Persist after completion (completed/errored tasks are dropped
+            // from the file; completed ones move to the history).
+            if let Some(store) = &store {
+                if let Some(task) = finished {
+                    store.record_history(task).await;
                 }
+                store.save(&tasks).await;
             }
         });
 
@@ -359,6 +868,9 @@ impl DownloadManager {
         client: Client,
         tasks: Arc<RwLock<HashMap<String, DownloadTask>>>,
         task: DownloadTask,
+        verify_mode: VerifyMode,
+        cancel: Arc<AtomicBool>,
+        limiter: Arc<RateLimiter>,
         on_progress: F,
     ) -> Result<()>
     where
@@ -378,12 +890,58 @@ impl DownloadManager {
             task.chunks.len()
         );
 
+        // Pick the checksum according to the verify mode. The same pass also
+        // yields the local BLAKE3 fingerprint used by later re-checks.
+        let (sha256, md5) = match verify_mode {
+            VerifyMode::Auto => (task.sha256.as_deref(), task.md5.as_deref()),
+            VerifyMode::Sha256Only => {
+                let sha256 = task
+                    .sha256
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("校验模式要求 SHA256，但该任务没有提供"))?;
+                (Some(sha256), None)
+            }
+            VerifyMode::Md5Only => {
+                let md5 = task
+                    .md5
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("校验模式要求 MD5，但该任务没有提供"))?;
+                (None, Some(md5))
+            }
+            VerifyMode::Skip => {
+                log::warn!(
+                    "[dl] verification skipped for {} (verify_mode=skip)",
+                    task.dest_path
+                );
+                (None, None)
+            }
+        };
+
         let on_progress = Arc::new(on_progress);
         // Initialize counter from already-downloaded bytes so progress is correct on resume.
         let downloaded_counter = Arc::new(tokio::sync::Mutex::new(task.downloaded_size));
         let resume_offset = task.downloaded_size;
         let start_time = std::time::Instant::now();
 
+        // A single-chunk file is written front to back, so it can be hashed
+        // as the bytes arrive. Segmented files are re-read after the download.
+        let stream_hash = task.chunks.len() == 1;
+
+        // Segments write at their own offsets; size the file up front so a
+        // stale, longer file from an earlier download can't leave a tail.
+        if !stream_hash && task.total_size > 0 {
+            let file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&task.dest_path)
+                .await
+                .with_context(|| format!("open file {} failed", task.dest_path))?;
+            file.set_len(task.total_size)
+                .await
+                .with_context(|| format!("allocate {} failed", task.dest_path))?;
+        }
+
         let mut join_set = tokio::task::JoinSet::new();
 
         for chunk in pending {
@@ -395,46 +953,133 @@ impl DownloadManager {
             let total_size = task.total_size;
             let on_progress = on_progress.clone();
             let start = start_time;
+            let digests =
+                stream_hash.then(|| (sha256.map(str::to_string), md5.map(str::to_string)));
+            let limiter = limiter.clone();
 
             join_set.spawn(async move {
-                Self::download_chunk(
-                    client,
-                    chunk,
-                    dest_path,
-                    task_id,
-                    tasks,
-                    counter,
-                    resume_offset,
-                    total_size,
-                    on_progress,
-                    start,
-                )
-                .await
+                // The chunk's URL first, then its mirrors, each tried a couple
+                // of times before moving on. Every attempt resumes from the
+                // bytes the previous one left on disk.
+                let mut urls = vec![chunk.url.clone()];
+                urls.extend(chunk.mirrors.iter().filter(|m| **m != chunk.url).cloned());
+                let mut chunk = chunk;
+                let mut attempt = 0;
+                loop {
+                    let hasher = digests
+                        .as_ref()
+                        .map(|(sha256, md5)| FileHasher::new(sha256.as_deref(), md5.as_deref()));
+                    let err = match Self::download_chunk(
+                        client.clone(),
+                        chunk.clone(),
+                        dest_path.clone(),
+                        task_id.clone(),
+                        tasks.clone(),
+                        counter.clone(),
+                        resume_offset,
+                        total_size,
+                        on_progress.clone(),
+                        start,
+                        hasher,
+                        limiter.clone(),
+                    )
+                    .await
+                    {
+                        Ok(hasher) => return Ok(hasher),
+                        Err(e) => e,
+                    };
+
+                    attempt += 1;
+                    if !is_network_error(&err) || attempt >= urls.len() * ATTEMPTS_PER_URL {
+                        return Err(err);
+                    }
+                    let next = &urls[attempt / ATTEMPTS_PER_URL];
+                    {
+                        let mut tasks_w = tasks.write().await;
+                        let stored = tasks_w
+                            .get_mut(&task_id)
+                            .and_then(|t| t.chunks.iter_mut().find(|c| c.id == chunk.id));
+                        if let Some(c) = stored {
+                            chunk.downloaded = c.downloaded;
+                            c.active_url = Some(next.clone());
+                        }
+                    }
+                    if *next != chunk.url {
+                        log::warn!(
+                            "[dl] chunk {} failed on {} ({:#}), switching to {} at offset {}",
+                            chunk.id,
+                            chunk.url,
+                            err,
+                            next,
+                            chunk.downloaded
+                        );
+                        chunk.url = next.clone();
+                    } else {
+                        log::warn!(
+                            "[dl] chunk {} failed ({:#}), retrying at offset {}",
+                            chunk.id,
+                            err,
+                            chunk.downloaded
+                        );
+                    }
+                    tokio::time::sleep(CHUNK_RETRY_DELAY * attempt as u32).await;
+                }
             });
         }
 
+        let mut streamed: Option<FileHasher> = None;
         while let Some(result) = join_set.join_next().await {
-            result.map_err(|e| anyhow!("Task join error: {}", e))??;
+            if let Some(h) = result.map_err(|e| anyhow!("Task join error: {}", e))?? {
+                streamed = Some(h);
+            }
         }
 
         log::info!("[dl] run_download task={} all chunks done", task.id);
 
-        // Verify checksum if provided
-        if task.sha256.is_some() || task.md5.is_some() {
-            {
-                let mut tasks_w = tasks.write().await;
-                if let Some(t) = tasks_w.get_mut(&task.id) {
-                    t.status = DownloadStatus::Verifying;
+        // Cheap truncation check before the expensive checksum pass.
+        if task.total_size > 0 {
+            let on_disk = fs::metadata(&task.dest_path).await?.len();
+            if on_disk != task.total_size {
+                log::error!(
+                    "[dl] size mismatch path={} expected={} got={}",
+                    task.dest_path,
+                    task.total_size,
+                    on_disk
+                );
+                return Err(Corrupt(format!(
+                    "文件大小不符：expected {} bytes, got {}",
+                    task.total_size, on_disk
+                ))
+                .into());
+            }
+        }
+
+        let fingerprint = match streamed {
+            Some(hasher) => {
+                log::info!(
+                    "[dl] checksum computed while downloading {}",
+                    task.dest_path
+                );
+                Some(hasher.finish(&task.dest_path, sha256, md5)?)
+            }
+            None if sha256.is_some() || md5.is_some() => {
+                {
+                    let mut tasks_w = tasks.write().await;
+                    if let Some(t) = tasks_w.get_mut(&task.id) {
+                        t.status = DownloadStatus::Verifying;
+                    }
                 }
+                log::info!("[dl] verifying {} (re-reading file)", task.dest_path);
+                Some(verify_file(&task.dest_path, sha256, md5, &cancel).await?)
+            }
+            None => None,
+        };
+        if let Some(fingerprint) = fingerprint {
+            if sha256.is_some() || md5.is_some() {
+                log::info!("[dl] checksum OK for {}", task.dest_path);
             }
-            if let Some(expected_sha256) = &task.sha256 {
-                log::info!("[dl] verifying sha256 for {}", task.dest_path);
-                verify_sha256(&task.dest_path, expected_sha256).await?;
-                log::info!("[dl] sha256 OK for {}", task.dest_path);
-            } else if let Some(expected_md5) = &task.md5 {
-                log::info!("[dl] verifying md5 for {}", task.dest_path);
-                verify_md5(&task.dest_path, expected_md5).await?;
-                log::info!("[dl] md5 OK for {}", task.dest_path);
+            if let Some(t) = tasks.write().await.get_mut(&task.id) {
+                t.blake3 = Some(fingerprint);
             }
         }
 
@@ -453,7 +1098,9 @@ impl DownloadManager {
         total_size: u64,
         on_progress: Arc<F>,
         start_time: std::time::Instant,
-    ) -> Result<()>
+        mut hasher: Option<FileHasher>,
+        limiter: Arc<RateLimiter>,
+    ) -> Result<Option<FileHasher>>
     where
         F: Fn(DownloadProgress) + Send + Sync + 'static,
     {
@@ -463,16 +1110,30 @@ impl DownloadManager {
 
         if range_start > range_end {
             log::debug!("[dl] chunk {} already complete, skipping", chunk.id);
-            return Ok(());
+            // Nothing streamed; the caller falls back to re-reading the file.
+            return Ok(None);
         }
 
-        let use_range = range_start > 0;
-        let mut request = client.get(&chunk.url);
+        // A segment of a split file always needs a range, even from offset 0.
+        let partial_chunk = range_end + 1 < total_size;
+        let use_range = range_start > 0 || partial_chunk;
+        let (validator, headers) = tasks
+            .read()
+            .await
+            .get(&task_id)
+            .map(|t| (t.validator.clone(), t.headers.clone()))
+            .unwrap_or_default();
+        let mut request = client.get(&chunk.url).headers(custom_headers(&headers)?);
         if use_range {
             request = request.header(
                 reqwest::header::RANGE,
                 format!("bytes={}-{}", range_start, range_end),
             );
+            // The server only honours the range if the file is unchanged;
+            // otherwise it sends the whole new file with 200.
+            if let Some(v) = &validator {
+                request = request.header(reqwest::header::IF_RANGE, v.as_str());
+            }
         }
 
         log::info!(
@@ -508,12 +1169,62 @@ impl DownloadManager {
             content_length
         );
 
+        // A 200 to a ranged request means the content changed (If-Range
+        // mismatch) or the server ignores ranges: the body is the full file.
+        let mut already_downloaded = already_downloaded;
+        if use_range && resp_status == reqwest::StatusCode::OK {
+            if chunk.start > 0 || partial_chunk {
+                return Err(anyhow!(
+                    "chunk {} 收到完整文件而非分段，服务器可能不支持断点续传",
+                    chunk.id
+                ));
+            }
+            log::warn!(
+                "[dl] chunk {} task={} remote file changed or range ignored; restarting from 0",
+                chunk.id,
+                task_id
+            );
+            *downloaded_counter.lock().await -= already_downloaded;
+            already_downloaded = 0;
+            if let Some(h) = hasher.as_mut() {
+                h.reset();
+            }
+        }
+
+        // Resuming: bring the hasher up to date with the bytes a previous
+        // session wrote, so the streamed digest still covers the whole file.
+        if let Some(h) = hasher.as_mut() {
+            if already_downloaded > 0 {
+                log::info!(
+                    "[dl] chunk {} hashing {}MB already on disk",
+                    chunk.id,
+                    already_downloaded / 1024 / 1024
+                );
+                hash_prefix(&dest_path, already_downloaded, h)
+                    .await
+                    .with_context(|| format!("hash existing part of {} failed", dest_path))?;
+            }
+        }
+
+        // Remember the validator of the content we are now writing.
+        if already_downloaded == 0 {
+            if let Some(v) = response_validator(response.headers()) {
+                if let Some(t) = tasks.write().await.get_mut(&task_id) {
+                    t.validator = Some(v);
+                }
+            }
+        }
+
         let mut stream = response.bytes_stream();
 
+        // Only a fresh download of the whole file may discard what's on disk;
+        // a resume (or another chunk's bytes) must survive the open.
+        let fresh_whole_file =
+            already_downloaded == 0 && chunk.start == 0 && range_end + 1 >= total_size;
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
-            .truncate(true)
+            .truncate(fresh_whole_file)
             .open(&dest_path)
             .await
             .with_context(|| format!("open file {} failed", dest_path))?;
@@ -522,27 +1233,85 @@ impl DownloadManager {
             .await
             .with_context(|| format!("seek in {} failed", dest_path))?;
 
+        let chunk_len = range_end - chunk.start + 1;
         let mut chunk_downloaded = already_downloaded;
         let mut last_log_bytes = 0u64;
+        let mut last_sync = std::time::Instant::now();
+        // (time, task-wide downloaded bytes). The counter is shared by all
+        // chunks, so any chunk's samples measure the whole task's speed.
+        let mut samples: std::collections::VecDeque<(std::time::Instant, u64)> =
+            std::collections::VecDeque::new();
 
         while let Some(item) = stream.next().await {
-            let data = item.with_context(|| format!("chunk {} stream read error", chunk.id))?;
+            let data = match item {
+                Ok(data) => data,
+                Err(e) => {
+                    // Record exactly what reached the file, so a retry
+                    // resumes from here rather than the last live sync.
+                    let _ = file.flush().await;
+                    if let Some(t) = tasks.write().await.get_mut(&task_id) {
+                        if let Some(c) = t.chunks.iter_mut().find(|c| c.id == chunk.id) {
+                            c.downloaded = chunk_downloaded;
+                        }
+                    }
+                    return Err(anyhow::Error::new(e)
+                        .context(format!("chunk {} stream read error", chunk.id)));
+                }
+            };
+            let bytes = data.len() as u64;
+            // Writing past the segment would clobber the next chunk's bytes,
+            // so an overshooting piece is rejected before it reaches the file.
+            if partial_chunk && bytes > chunk_len.saturating_sub(chunk_downloaded) {
+                let _ = file.flush().await;
+                return Err(Corrupt(format!(
+                    "chunk {} 超出分段范围：{} > {} bytes",
+                    chunk.id,
+                    chunk_downloaded + bytes,
+                    chunk_len
+                ))
+                .into());
+            }
             file.write_all(&data)
                 .await
                 .with_context(|| format!("chunk {} write error", chunk.id))?;
+            if let Some(h) = hasher.as_mut() {
+                h.update(&data);
+            }
 
-            let bytes = data.len() as u64;
             chunk_downloaded += bytes;
+            limiter.consume(bytes).await;
 
             let mut counter = downloaded_counter.lock().await;
             *counter += bytes;
             let total_downloaded = *counter;
             drop(counter);
 
-            let elapsed = start_time.elapsed().as_secs_f64().max(0.001);
-            // Speed reflects only bytes downloaded in this session, not the resume offset.
-            let session_bytes = total_downloaded.saturating_sub(resume_offset);
-            let speed = (session_bytes as f64 / elapsed) as u64;
+            let now = std::time::Instant::now();
+            if samples
+                .back()
+                .is_none_or(|(t, _)| now.duration_since(*t) >= SPEED_SAMPLE_INTERVAL)
+            {
+                samples.push_back((now, total_downloaded));
+            }
+            // Keep one sample at or beyond the window edge so the span covers it.
+            while samples.len() > 1 && now.duration_since(samples[1].0) >= SPEED_WINDOW {
+                samples.pop_front();
+            }
+            let speed = match samples.front() {
+                Some((t, bytes)) if now.duration_since(*t) >= SPEED_SAMPLE_INTERVAL => {
+                    (total_downloaded.saturating_sub(*bytes) as f64
+                        / now.duration_since(*t).as_secs_f64()) as u64
+                }
+                // Too early for a window: average over this session so far,
+                // excluding the resume offset.
+                _ => {
+                    let elapsed = start_time.elapsed().as_secs_f64().max(0.001);
+                    let session_bytes = total_downloaded.saturating_sub(resume_offset);
+                    (session_bytes as f64 / elapsed) as u64
+                }
+            };
+            let eta_seconds = (speed > 0 && total_size > 0)
+                .then(|| total_size.saturating_sub(total_downloaded).div_ceil(speed));
 
             let progress = if total_size > 0 {
                 (total_downloaded as f64 / total_size as f64 * 100.0).min(100.0)
@@ -562,23 +1331,55 @@ impl DownloadManager {
                 last_log_bytes = chunk_downloaded;
             }
 
+            // Mirror live numbers into the stored task a few times a second
+            // so get_task / get_task_detail see current progress.
+            if last_sync.elapsed() >= LIVE_SYNC_INTERVAL {
+                last_sync = std::time::Instant::now();
+                if let Some(t) = tasks.write().await.get_mut(&task_id) {
+                    t.downloaded_size = total_downloaded;
+                    t.progress = progress;
+                    t.speed = speed;
+                    if let Some(c) = t.chunks.iter_mut().find(|c| c.id == chunk.id) {
+                        c.downloaded = chunk_downloaded;
+                    }
+                }
+            }
+
             on_progress(DownloadProgress {
                 task_id: task_id.clone(),
                 downloaded_size: total_downloaded,
                 total_size,
                 progress,
                 speed,
+                eta_seconds,
                 status: DownloadStatus::Downloading,
                 error: None,
             });
         }
 
+        // tokio hands writes to a blocking thread; without a flush the last
+        // buffer may land after the caller already checks the file.
+        file.flush()
+            .await
+            .with_context(|| format!("chunk {} flush error", chunk.id))?;
+
         log::info!(
             "[dl] chunk {} done downloaded={}MB",
             chunk.id,
             chunk_downloaded / 1024 / 1024
         );
 
+        // The file was pre-sized, so a short segment would pass the size
+        // check in run_download; catch it here.
+        if partial_chunk && chunk_downloaded != chunk_len {
+            return Err(anyhow!(
+                "chunk {} 未下载完整：{} / {} bytes",
+                chunk.id,
+                chunk_downloaded,
+                chunk_len
+            ));
+        }
+
         let mut tasks_w = tasks.write().await;
         if let Some(t) = tasks_w.get_mut(&task_id) {
             if let Some(c) = t.chunks.iter_mut().find(|c| c.id == chunk.id) {
@@ -588,11 +1389,59 @@ impl DownloadManager {
             t.downloaded_size = t.chunks.iter().map(|c| c.downloaded).sum();
         }
 
+        Ok(hasher)
+    }
+
+    /// Re-check a completed download on disk. Uses the stored BLAKE3 when
+    /// available; otherwise falls back to the manifest hash and records the
+    /// BLAKE3 for next time. Tasks from earlier sessions are looked up in
+    /// the history.
+    pub async fn reverify_task(&self, task_id: &str) -> Result<()> {
+        let task = match self.get_task(task_id).await {
+            Some(task) => task,
+            None => self
+                .get_history()
+                .await
+                .into_iter()
+                .find(|t| t.id == task_id)
+                .ok_or_else(|| anyhow!("Task not found: {}", task_id))?,
+        };
+        if task.status != DownloadStatus::Completed {
+            return Err(anyhow!("任务尚未完成，无法校验"));
+        }
+
+        let cancel = AtomicBool::new(false);
+        if let Some(expected) = &task.blake3 {
+            log::info!("[dl] re-verifying {} with blake3", task.dest_path);
+            return verify_blake3(&task.dest_path, expected, &cancel).await;
+        }
+        if task.sha256.is_none() && task.md5.is_none() {
+            return Err(anyhow!("该任务没有可用的校验值"));
+        }
+
+        log::info!("[dl] re-verifying {} with manifest hash", task.dest_path);
+        let fingerprint = verify_file(
+            &task.dest_path,
+            task.sha256.as_deref(),
+            task.md5.as_deref(),
+            &cancel,
+        )
+        .await?;
+        if let Some(t) = self.tasks.write().await.get_mut(task_id) {
+            t.blake3 = Some(fingerprint.clone());
+        }
+        // Completed tasks live on only in the history.
+        if let Some(store) = &self.store {
+            store
+                .update_history(task_id, |t| t.blake3 = Some(fingerprint))
+                .await;
+        }
         Ok(())
     }
 
     pub async fn pause_task(&self, task_id: &str) -> Result<()> {
         log::info!("[dl] pause_task id={}", task_id);
+        self.signal_cancel(task_id).await;
         if let Some(handle) = self.handles.lock().await.get(task_id) {
             handle.abort();
         }
@@ -606,25 +1455,305 @@ impl DownloadManager {
         Ok(())
     }
 
+    /// Put a failed task back to Pending so `start_task` can run it again.
+    /// It resumes from the bytes on disk unless the failure was a size or
+    /// checksum mismatch, in which case the file is discarded first.
+    pub async fn reset_failed_task(&self, task_id: &str) -> Result<DownloadProgress> {
+        let mut tasks = self.tasks.write().await;
+        let task = tasks
+            .get_mut(task_id)
+            .ok_or_else(|| anyhow!("Task not found: {}", task_id))?;
+        if task.status != DownloadStatus::Error {
+            return Err(anyhow!("只能重试失败的任务"));
+        }
+
+        if task.corrupt {
+            log::info!(
+                "[dl] retry {}: previous file was bad, starting over",
+                task.name
+            );
+            if let Err(e) = fs::remove_file(&task.dest_path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(anyhow!("无法删除损坏的文件 {}：{}", task.dest_path, e));
+                }
+            }
+            for c in &mut task.chunks {
+                c.downloaded = 0;
+                c.completed = false;
+            }
+            task.downloaded_size = 0;
+            task.progress = 0.0;
+            task.validator = None;
+            task.blake3 = None;
+        } else {
+            log::info!("[dl] retry {}: resuming from disk", task.name);
+        }
+        task.status = DownloadStatus::Pending;
+        task.error = None;
+        task.corrupt = false;
+        task.speed = 0;
+
+        let progress = DownloadProgress {
+            task_id: task.id.clone(),
+            downloaded_size: task.downloaded_size,
+            total_size: task.total_size,
+            progress: task.progress,
+            speed: 0,
+            eta_seconds: None,
+            status: DownloadStatus::Pending,
+            error: None,
+        };
+        drop(tasks);
+        self.persist().await;
+        Ok(progress)
+    }
+
     pub async fn cancel_task(&self, task_id: &str) -> Result<()> {
         log::info!("[dl] cancel_task id={}", task_id);
+        self.signal_cancel(task_id).await;
         if let Some(handle) = self.handles.lock().await.remove(task_id) {
             handle.abort();
         }
         let mut tasks = self.tasks.write().await;
-        tasks.remove(task_id);
+        let removed = tasks.remove(task_id);
         drop(tasks);
         self.persist().await;
+
+        if let Some(task) = removed.filter(|t| t.status != DownloadStatus::Completed) {
+            if self.keep_files.load(Ordering::Relaxed) {
+                log::warn!(
+                    "[dl] DEBUG: debug_keep_all_files is ON — keeping partial file {}",
+                    task.dest_path
+                );
+            } else if let Err(e) = tokio::fs::remove_file(&task.dest_path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("[dl] could not remove {}: {}", task.dest_path, e);
+                }
+            }
+        }
         Ok(())
     }
 
+    /// Ask an in-progress verification to stop at the next buffer boundary.
+    async fn signal_cancel(&self, task_id: &str) {
+        if let Some(flag) = self.cancel_flags.lock().await.remove(task_id) {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+
     pub async fn get_tasks(&self) -> Vec<DownloadTask> {
         self.tasks.read().await.values().cloned().collect()
     }
 
+    /// Fallback URLs for every chunk of a task, tried when the primary keeps
+    /// failing.
+    pub async fn set_task_mirrors(&self, task_id: &str, mirrors: Vec<String>) -> Result<()> {
+        {
+            let mut tasks = self.tasks.write().await;
+            let task = tasks
+                .get_mut(task_id)
+                .ok_or_else(|| anyhow!("Task not found: {}", task_id))?;
+            for c in &mut task.chunks {
+                c.mirrors = mirrors.clone();
+            }
+        }
+        self.persist().await;
+        Ok(())
+    }
+
+    /// Completed downloads from this and earlier sessions, newest first.
+    pub async fn get_history(&self) -> Vec<DownloadTask> {
+        match &self.store {
+            Some(store) => store.load_history().await,
+            None => Vec::new(),
+        }
+    }
+
     pub async fn get_task(&self, task_id: &str) -> Option<DownloadTask> {
         self.tasks.read().await.get(task_id).cloned()
     }
+
+    pub async fn get_task_detail(&self, task_id: &str) -> Option<TaskDetail> {
+        let task = self.get_task(task_id).await?;
+        let remaining_bytes = task.total_size.saturating_sub(task.downloaded_size);
+        let eta_secs = (task.status == DownloadStatus::Downloading && task.speed > 0)
+            .then(|| remaining_bytes.div_ceil(task.speed));
+        let chunks = task
+            .chunks
+            .iter()
+            .map(|c| {
+                let len = c.end.saturating_sub(c.start) + 1;
+                ChunkDetail {
+                    id: c.id,
+                    start: c.start,
+                    end: c.end,
+                    downloaded: c.downloaded,
+                    progress: if c.completed {
+                        100.0
+                    } else {
+                        (c.downloaded as f64 / len as f64 * 100.0).min(100.0)
+                    },
+                    completed: c.completed,
+                    url: c.active_url.clone().unwrap_or_else(|| c.url.clone()),
+                }
+            })
+            .collect();
+        Some(TaskDetail {
+            task,
+            remaining_bytes,
+            eta_secs,
+            chunks,
+        })
+    }
+
+    /// Resolve the real filename for a URL from the `Content-Disposition`
+    /// header of a HEAD response, falling back to the URL's last path segment.
+    /// Results are cached per URL (query string ignored).
+    pub async fn resolve_filename(
+        &self,
+        url: &str,
+        headers: &reqwest::header::HeaderMap,
+    ) -> String {
+        let key = url.split_once('?').map(|(base, _)| base).unwrap_or(url);
+        if let Some(name) = self.filename_cache.read().await.get(key) {
+            return name.clone();
+        }
+
+        let from_header = match self
+            .client()
+            .head(url)
+            .headers(headers.clone())
+            .send()
+            .await
+        {
+            Ok(resp) => resp
+                .headers()
+                .get(reqwest::header::CONTENT_DISPOSITION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_content_disposition),
+            Err(e) => {
+                log::warn!("[dl] HEAD for filename failed: {e}");
+                None
+            }
+        };
+
+        let name = from_header.unwrap_or_else(|| filename_from_url(url));
+        log::info!("[dl] resolved filename {name} for {key}");
+        self.filename_cache
+            .write()
+            .await
+            .insert(key.to_string(), name.clone());
+        name
+    }
+
+    /// Probe candidate URLs concurrently with a small ranged GET, measuring
+    /// time-to-first-byte and throughput. Results are cached briefly and
+    /// returned in input order. `headers` are sent with every probe.
+    pub async fn probe_mirrors(
+        &self,
+        urls: &[String],
+        headers: &reqwest::header::HeaderMap,
+    ) -> Vec<MirrorResult> {
+        let mut fresh: HashMap<String, MirrorResult> = HashMap::new();
+        {
+            let cache = self.probe_cache.read().await;
+            for url in urls {
+                if let Some((at, result)) = cache.get(url) {
+                    if at.elapsed() < MIRROR_PROBE_TTL {
+                        fresh.insert(url.clone(), result.clone());
+                    }
+                }
+            }
+        }
+
+        let to_probe: Vec<&String> = urls.iter().filter(|u| !fresh.contains_key(*u)).collect();
+        let client = self.client();
+        let probed = futures_util::future::join_all(
+            to_probe
+                .iter()
+                .map(|url| probe_mirror(&client, url, headers)),
+        )
+        .await;
+
+        {
+            let mut cache = self.probe_cache.write().await;
+            let now = std::time::Instant::now();
+            for result in probed {
+                cache.insert(result.url.clone(), (now, result.clone()));
+                fresh.insert(result.url.clone(), result);
+            }
+        }
+
+        urls.iter().filter_map(|u| fresh.get(u).cloned()).collect()
+    }
+
+    /// Download the first BENCHMARK_BYTES of `url`, discarding the data, and
+    /// report the throughput.
+    pub async fn benchmark(&self, url: &str) -> Result<SpeedBenchmark> {
+        let start = std::time::Instant::now();
+        let resp = self
+            .client()
+            .get(url)
+            .header(
+                reqwest::header::RANGE,
+                format!("bytes=0-{}", BENCHMARK_BYTES - 1),
+            )
+            .timeout(std::time::Duration::from_secs(60))
+            .send()
+            .await?
+            .error_for_status()?;
+        let latency = start.elapsed();
+
+        let mut stream = resp.bytes_stream();
+        let mut received = 0u64;
+        while let Some(item) = stream.next().await {
+            received += item?.len() as u64;
+            if received >= BENCHMARK_BYTES {
+                break;
+            }
+        }
+
+        let elapsed = start.elapsed().as_secs_f64().max(0.001);
+        let mb_per_sec = received as f64 / elapsed / (1024.0 * 1024.0);
+        log::info!(
+            "[dl] benchmark {} bytes in {:.2}s ({:.1} MB/s, ttfb {}ms)",
+            received,
+            elapsed,
+            mb_per_sec,
+            latency.as_millis()
+        );
+        Ok(SpeedBenchmark {
+            url: url.to_string(),
+            bytes: received,
+            latency_ms: latency.as_millis() as u64,
+            mb_per_sec,
+        })
+    }
+
+    pub fn concurrency_status(&self) -> ConcurrencyStatus {
+        ConcurrencyStatus {
+            max: self.max_concurrent.load(Ordering::SeqCst),
+            active: self.active.load(Ordering::SeqCst),
+            queued: self.queued.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Increments a shared counter on creation and decrements it on drop,
+/// so aborted download tasks never leave stale slot counts behind.
+struct CounterGuard(Arc<AtomicUsize>);
+
+impl CounterGuard {
+    fn new(counter: Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for CounterGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 // ─── Helpers ────────────────────────────────────────────────────────────────
@@ -641,37 +1770,662 @@ fn format_bytes(b: u64) -> String {
     }
 }
 
-// ─── Checksum verification ──────────────────────────────────────────────────
+/// Fetch the first MIRROR_PROBE_BYTES of `url` and time it.
+async fn probe_mirror(
+    client: &Client,
+    url: &str,
+    headers: &reqwest::header::HeaderMap,
+) -> MirrorResult {
+    let failed = |e: String| MirrorResult {
+        url: url.to_string(),
+        ok: false,
+        ttfb_ms: None,
+        throughput: None,
+        error: Some(e),
+    };
+
+    let start = std::time::Instant::now();
+    let resp = match client
+        .get(url)
+        .headers(headers.clone())
+        .header(
+            reqwest::header::RANGE,
+            format!("bytes=0-{}", MIRROR_PROBE_BYTES - 1),
+        )
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+    {
+        Ok(r) => r,
+        Err(e) => return failed(e.to_string()),
+    };
+    let ttfb = start.elapsed();
+
+    let mut stream = resp.bytes_stream();
+    let mut received = 0u64;
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(data) => received += data.len() as u64,
+            Err(e) => return failed(e.to_string()),
+        }
+        // Servers ignoring Range would otherwise stream the whole file.
+        if received >= MIRROR_PROBE_BYTES {
+            break;
+        }
+    }
 
-async fn verify_sha256(path: &str, expected: &str) -> Result<()> {
-    let data = fs::read(path).await?;
-    let mut hasher = Sha256::new();
-    hasher.update(&data);
-    let actual = hex::encode(hasher.finalize());
-    if actual.eq_ignore_ascii_case(expected) {
-        Ok(())
+    let elapsed = start.elapsed().as_secs_f64().max(0.001);
+    MirrorResult {
+        url: url.to_string(),
+        ok: true,
+        ttfb_ms: Some(ttfb.as_millis() as u64),
+        throughput: Some((received as f64 / elapsed) as u64),
+        error: None,
+    }
+}
+
+/// Host part of a URL, used to key per-host download behaviour.
+fn url_host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(str::to_string)
+}
+
+/// `count` contiguous chunks covering `0..total_size`; the last one takes the
+/// remainder.
+fn split_chunks(url: &str, total_size: u64, count: usize) -> Vec<DownloadChunk> {
+    let count = count.max(1) as u64;
+    let step = total_size / count;
+    (0..count)
+        .map(|i| DownloadChunk {
+            id: i as usize,
+            url: url.to_string(),
+            start: i * step,
+            end: if i + 1 == count {
+                total_size.saturating_sub(1)
+            } else {
+                (i + 1) * step - 1
+            },
+            downloaded: 0,
+            completed: false,
+            mirrors: Vec::new(),
+            active_url: None,
+        })
+        .collect()
+}
+
+/// `task` reset to one fresh chunk covering the whole file.
+fn single_connection(task: &DownloadTask) -> DownloadTask {
+    let first = task.chunks.first();
+    let mut single = task.clone();
+    single.chunks = vec![DownloadChunk {
+        id: 0,
+        url: first.map(|c| c.url.clone()).unwrap_or_default(),
+        start: 0,
+        end: task.total_size.saturating_sub(1),
+        downloaded: 0,
+        completed: false,
+        mirrors: first.map(|c| c.mirrors.clone()).unwrap_or_default(),
+        active_url: None,
+    }];
+    single.downloaded_size = 0;
+    single.progress = 0.0;
+    single
+}
+
+/// Whether a chunk failed in transport (connect, timeout, HTTP status, broken
+/// body) rather than on disk or in verification; only those are retried.
+fn is_network_error(e: &anyhow::Error) -> bool {
+    e.chain().any(|c| c.is::<reqwest::Error>())
+}
+
+/// Highest-throughput successful probe, if any.
+fn fastest_mirror(results: &[MirrorResult]) -> Option<&MirrorResult> {
+    results
+        .iter()
+        .filter(|r| r.ok)
+        .max_by_key(|r| r.throughput.unwrap_or(0))
+}
+
+/// Parse user-supplied headers, rejecting names or values that aren't valid
+/// HTTP (e.g. containing CR/LF).
+fn custom_headers(headers: &HashMap<String, String>) -> Result<reqwest::header::HeaderMap> {
+    use reqwest::header::{HeaderName, HeaderValue};
+    let mut map = reqwest::header::HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| anyhow!("无效的请求头名称：{}", name))?;
+        let value =
+            HeaderValue::from_str(value.trim()).map_err(|_| anyhow!("请求头 {} 的值无效", name))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+/// Strong validator for `If-Range`: ETag when present (weak ETags are not
+/// allowed there), else Last-Modified.
+fn response_validator(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    let etag = headers
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.starts_with("W/"));
+    etag.or_else(|| {
+        headers
+            .get(reqwest::header::LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+    })
+    .map(str::to_string)
+}
+
+/// Free bytes on the disk holding `path` (or its nearest existing ancestor).
+pub fn available_space(path: &Path) -> Option<u64> {
+    disk_space(path).map(|(_, free)| free)
+}
+
+/// Mount point and free bytes of the disk holding `path` (or its nearest
+/// existing ancestor).
+pub fn disk_space(path: &Path) -> Option<(PathBuf, u64)> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let path = existing.canonicalize().ok()?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|d| path.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .map(|d| (d.mount_point().to_path_buf(), d.available_space()))
+}
+
+/// Last path segment of a URL with any query string stripped.
+pub fn filename_from_url(url: &str) -> String {
+    let path = url.split_once('?').map(|(base, _)| base).unwrap_or(url);
+    let name = path.rsplit('/').next().unwrap_or_default();
+    if name.is_empty() {
+        "download".to_string()
     } else {
-        log::error!("[dl] sha256 mismatch path={path} expected={expected} got={actual}");
-        Err(anyhow!(
-            "SHA256 mismatch: expected {}, got {}",
-            expected,
-            actual
-        ))
+        name.to_string()
+    }
+}
+
+/// True when a filename's stem is a long hex string (a content hash rather
+/// than a human-readable name).
+fn looks_like_hash(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name);
+    stem.len() >= 16 && stem.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Extract the filename from a `Content-Disposition` header value.
+/// Prefers the RFC 5987 `filename*=UTF-8''...` form over plain `filename=`.
+fn parse_content_disposition(value: &str) -> Option<String> {
+    let mut plain = None;
+    let mut extended = None;
+    for part in value.split(';').map(str::trim) {
+        if let Some(v) = part.strip_prefix("filename*=") {
+            let encoded = v.split_once("''").map(|(_, rest)| rest).unwrap_or(v);
+            extended = Some(percent_decode(encoded.trim_matches('"')));
+        } else if let Some(v) = part.strip_prefix("filename=") {
+            plain = Some(v.trim_matches('"').to_string());
+        }
+    }
+    // Never trust path components from the server.
+    extended
+        .or(plain)
+        .map(|n| n.rsplit(['/', '\\']).next().unwrap_or_default().to_string())
+        .filter(|n| !n.is_empty() && n != "." && n != "..")
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 3 <= bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// ─── Checksum verification ──────────────────────────────────────────────────
+
+/// Returned when verification is interrupted by a pause/cancel request.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("verification cancelled")
     }
 }
 
-async fn verify_md5(path: &str, expected: &str) -> Result<()> {
-    let data = fs::read(path).await?;
-    let digest = md5::compute(&data);
-    let actual = format!("{:x}", digest);
+impl std::error::Error for Cancelled {}
+
+/// The finished file doesn't match its expected size or checksum.
+#[derive(Debug)]
+pub struct Corrupt(String);
+
+impl std::fmt::Display for Corrupt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Corrupt {}
+
+const VERIFY_BUF_SIZE: usize = 1024 * 1024;
+
+/// Stream a file through `update` in fixed-size buffers, checking `cancel`
+/// between buffers so multi-gigabyte verifications stay interruptible.
+async fn hash_file(path: &str, cancel: &AtomicBool, mut update: impl FnMut(&[u8])) -> Result<()> {
+    let mut file = fs::File::open(path).await?;
+    let mut buf = vec![0u8; VERIFY_BUF_SIZE];
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            return Err(Cancelled.into());
+        }
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        update(&buf[..n]);
+    }
+}
+
+/// The server-provided SHA256 (preferred) or MD5, plus the local BLAKE3,
+/// fed in one pass — either while downloading or by re-reading the file.
+struct FileHasher {
+    sha: Option<Sha256>,
+    md5: Option<md5::Context>,
+    b3: blake3::Hasher,
+}
+
+impl FileHasher {
+    fn new(sha256: Option<&str>, md5: Option<&str>) -> Self {
+        Self {
+            sha: sha256.map(|_| Sha256::new()),
+            md5: (sha256.is_none() && md5.is_some()).then(md5::Context::new),
+            b3: blake3::Hasher::new(),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        if let Some(h) = self.sha.as_mut() {
+            h.update(data);
+        }
+        if let Some(c) = self.md5.as_mut() {
+            c.consume(data);
+        }
+        self.b3.update(data);
+    }
+
+    /// Start over, e.g. when the server restarts a resumed download from 0.
+    fn reset(&mut self) {
+        self.sha = self.sha.as_ref().map(|_| Sha256::new());
+        self.md5 = self.md5.as_ref().map(|_| md5::Context::new());
+        self.b3.reset();
+    }
+
+    /// Compare against the expected digest and return the BLAKE3.
+    fn finish(self, path: &str, sha256: Option<&str>, md5: Option<&str>) -> Result<String> {
+        if let (Some(h), Some(expected)) = (self.sha, sha256) {
+            check_digest("SHA256", path, expected, &hex::encode(h.finalize()))?;
+        } else if let (Some(c), Some(expected)) = (self.md5, md5) {
+            check_digest("MD5", path, expected, &format!("{:x}", c.compute()))?;
+        }
+        Ok(self.b3.finalize().to_hex().to_string())
+    }
+}
+
+/// Feed the first `len` bytes of `path` into `hasher`.
+async fn hash_prefix(path: &str, len: u64, hasher: &mut FileHasher) -> Result<()> {
+    let mut file = fs::File::open(path).await?.take(len);
+    let mut buf = vec![0u8; VERIFY_BUF_SIZE];
+    let mut read = 0u64;
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        read += n as u64;
+    }
+    if read != len {
+        return Err(anyhow!("文件比记录的进度短：{} < {}", read, len));
+    }
+    Ok(())
+}
+
+/// Check `path` against the server-provided SHA256 (preferred) or MD5 and
+/// return its BLAKE3, all from a single read of the file.
+async fn verify_file(
+    path: &str,
+    sha256: Option<&str>,
+    md5: Option<&str>,
+    cancel: &AtomicBool,
+) -> Result<String> {
+    let mut hasher = FileHasher::new(sha256, md5);
+    hash_file(path, cancel, |data| hasher.update(data)).await?;
+    hasher.finish(path, sha256, md5)
+}
+
+/// Re-check a file against a fingerprint previously stored by `verify_file`.
+async fn verify_blake3(path: &str, expected: &str, cancel: &AtomicBool) -> Result<()> {
+    let mut b3 = blake3::Hasher::new();
+    hash_file(path, cancel, |data| {
+        b3.update(data);
+    })
+    .await?;
+    check_digest("BLAKE3", path, expected, b3.finalize().to_hex().as_str())
+}
+
+fn check_digest(algo: &str, path: &str, expected: &str, actual: &str) -> Result<()> {
     if actual.eq_ignore_ascii_case(expected) {
         Ok(())
     } else {
-        log::error!("[dl] md5 mismatch path={path} expected={expected} got={actual}");
-        Err(anyhow!(
-            "MD5 mismatch: expected {}, got {}",
-            expected,
-            actual
+        log::error!(
+            "[dl] {} mismatch path={path} expected={expected} got={actual}",
+            algo.to_lowercase()
+        );
+        Err(Corrupt(format!(
+            "{} mismatch: expected {}, got {}",
+            algo, expected, actual
         ))
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+
+    /// Serve byte ranges of `body` over HTTP on a local port, for `requests`
+    /// requests. Returns the base URL.
+    fn serve_ranges(body: &'static [u8], requests: usize) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(r) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = r.split_once('-').unwrap();
+                        range = Some((
+                            start.parse::<usize>().unwrap(),
+                            end.parse::<usize>().unwrap(),
+                        ));
+                    }
+                }
+                let (start, end) = range.expect("ranged request");
+                let head = format!(
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    start,
+                    end,
+                    body.len(),
+                    end - start + 1
+                );
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(&body[start..=end]).unwrap();
+            }
+        });
+        url
+    }
+
+    fn chunk(id: usize, url: &str, start: u64, end: u64, downloaded: u64) -> DownloadChunk {
+        DownloadChunk {
+            id,
+            url: url.to_string(),
+            start,
+            end,
+            downloaded,
+            completed: false,
+            mirrors: Vec::new(),
+            active_url: None,
+        }
+    }
+
+    async fn run_chunk(
+        chunk: DownloadChunk,
+        dest: &Path,
+        already: u64,
+        total_size: u64,
+    ) -> Result<()> {
+        DownloadManager::download_chunk(
+            Client::new(),
+            chunk,
+            dest.to_string_lossy().into_owned(),
+            "task".to_string(),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(Mutex::new(already)),
+            already,
+            total_size,
+            Arc::new(|_: DownloadProgress| {}),
+            std::time::Instant::now(),
+            None,
+            Arc::new(RateLimiter::new(0)),
+        )
+        .await
+        .map(drop)
+    }
+
+    const BODY: &[u8] = b"0123456789abcdefghij";
+
+    /// Answer one request with the raw HTTP `response`. Returns the URL.
+    fn serve_once(response: &'static str) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for line in BufReader::new(&stream).lines() {
+                if line.unwrap().is_empty() {
+                    break;
+                }
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    async fn segmented_task(url: String, known_size: u64) -> DownloadTask {
+        let manager = DownloadManager::new(1, &NetworkOptions::default(), None).unwrap();
+        manager.set_download_threads(4);
+        let id = manager
+            .create_task(
+                "arknights".into(),
+                "game.zip".into(),
+                url,
+                "game.zip".into(),
+                Some(known_size),
+                None,
+                None,
+                HashMap::new(),
+            )
+            .await
+            .unwrap();
+        manager.get_task(&id).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn reverify_finds_tasks_from_history() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("game.zip");
+        std::fs::write(&dest, BODY).unwrap();
+        let manager = DownloadManager::new(
+            1,
+            &NetworkOptions::default(),
+            Some(tmp.path().join("downloads.json")),
+        )
+        .unwrap();
+        let task = DownloadTask {
+            id: "done".into(),
+            game_id: "arknights".into(),
+            name: "game.zip".into(),
+            dest_path: dest.to_string_lossy().into_owned(),
+            total_size: BODY.len() as u64,
+            downloaded_size: BODY.len() as u64,
+            progress: 100.0,
+            speed: 0,
+            status: DownloadStatus::Completed,
+            error: None,
+            created_at: 0,
+            chunks: Vec::new(),
+            sha256: None,
+            md5: Some(format!("{:x}", md5::compute(BODY))),
+            blake3: None,
+            validator: None,
+            headers: HashMap::new(),
+            completed_at: Some(0),
+            corrupt: false,
+        };
+        manager.store.as_ref().unwrap().record_history(task).await;
+
+        manager.reverify_task("done").await.unwrap();
+
+        let history = manager.get_history().await;
+        assert!(history[0].blake3.is_some());
+        std::fs::write(&dest, b"tampered").unwrap();
+        assert!(manager.reverify_task("done").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn mirror_probes_send_task_headers() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut authorized = false;
+            for line in BufReader::new(&stream).lines() {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                authorized |= line.eq_ignore_ascii_case("x-token: secret");
+            }
+            let response = if authorized {
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndata"
+            } else {
+                "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        let manager = DownloadManager::new(1, &NetworkOptions::default(), None).unwrap();
+        let headers =
+            custom_headers(&HashMap::from([("X-Token".into(), "secret".into())])).unwrap();
+
+        let results = manager.probe_mirrors(&[url], &headers).await;
+
+        assert!(results[0].ok, "{:?}", results[0].error);
+    }
+
+    #[test]
+    fn percent_decode_handles_trailing_escape() {
+        assert_eq!(percent_decode("%E6%B8%B8%E6%88%8F.zip"), "游戏.zip");
+        assert_eq!(percent_decode("game%20v2"), "game v2");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("a%2"), "a%2");
+    }
+
+    #[tokio::test]
+    async fn failed_head_falls_back_to_one_connection() {
+        let url = serve_once(
+            "HTTP/1.1 403 Forbidden\r\nAccept-Ranges: bytes\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+
+        let task = segmented_task(url, 4 * MIN_CHUNK_SIZE).await;
+
+        assert_eq!(task.total_size, 4 * MIN_CHUNK_SIZE);
+        assert_eq!(task.chunks.len(), 1);
+        assert_eq!(task.chunks[0].end, 4 * MIN_CHUNK_SIZE - 1);
+    }
+
+    #[tokio::test]
+    async fn known_size_wins_over_head_length() {
+        let url = serve_once(
+            "HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nContent-Length: 1234\r\nConnection: close\r\n\r\n",
+        );
+
+        let task = segmented_task(url, 4 * MIN_CHUNK_SIZE).await;
+
+        assert_eq!(task.total_size, 4 * MIN_CHUNK_SIZE);
+        assert_eq!(task.chunks.len(), 4);
+        assert_eq!(task.chunks[3].end, 4 * MIN_CHUNK_SIZE - 1);
+    }
+
+    #[tokio::test]
+    async fn resumed_chunk_keeps_bytes_on_disk() {
+        let url = serve_ranges(BODY, 1);
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("file.bin");
+        std::fs::write(&dest, &BODY[..8]).unwrap();
+
+        run_chunk(chunk(0, &url, 0, 19, 8), &dest, 8, 20)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), BODY);
+    }
+
+    #[tokio::test]
+    async fn download_into_unusual_path() {
+        let url = serve_ranges(BODY, 1);
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("My Games").join("明日方舟 测试");
+        std::fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("游戏 包.zip");
+
+        run_chunk(chunk(0, &url, 0, 9, 0), &dest, 0, 20)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), &BODY[..10]);
+    }
+
+    #[tokio::test]
+    async fn segment_keeps_other_chunks_bytes() {
+        let url = serve_ranges(BODY, 1);
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("file.bin");
+        // First segment finished, second not started.
+        let mut prefilled = BODY[..10].to_vec();
+        prefilled.resize(20, 0);
+        std::fs::write(&dest, &prefilled).unwrap();
+
+        run_chunk(chunk(1, &url, 10, 19, 0), &dest, 10, 20)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), BODY);
+    }
+
+    #[tokio::test]
+    async fn overshooting_segment_leaves_next_chunk_alone() {
+        // Answers the range for the first half with the whole body.
+        let url = serve_once(
+            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-9/20\r\nContent-Length: 20\r\nConnection: close\r\n\r\n0123456789abcdefghij",
+        );
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("file.bin");
+        std::fs::write(&dest, [b'.'; 20]).unwrap();
+
+        let err = run_chunk(chunk(0, &url, 0, 9, 0), &dest, 0, 20)
+            .await
+            .unwrap_err();
+
+        assert!(err.downcast_ref::<Corrupt>().is_some(), "{err}");
+        assert_eq!(&std::fs::read(&dest).unwrap()[10..], &[b'.'; 10]);
     }
 }
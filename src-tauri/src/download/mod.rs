@@ -1,2 +1,8 @@
+pub mod journal;
+pub mod logging;
 pub mod manager;
-pub use manager::{DownloadManager, DownloadProgress, DownloadStatus, DownloadTask};
+pub mod throttle;
+pub use manager::{
+    ConcurrencyStatus, DownloadManager, DownloadProgress, DownloadStatus, DownloadTask,
+    MirrorResult, SpeedBenchmark, TaskDetail, VerifyMode,
+};
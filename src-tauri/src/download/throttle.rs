@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// ─── Rate Limiter ──────────────────────────────────────────────────────────
+
+/// Token bucket shared by every chunk of every task, so the limit applies to
+/// the launcher as a whole. Holds at most one second of budget, which keeps
+/// bursts after an idle period short.
+pub struct RateLimiter {
+    /// Bytes per second; 0 = unlimited.
+    limit: AtomicU64,
+    /// (last refill, available tokens). Tokens go negative when a buffer is
+    /// larger than the remaining budget; the caller then sleeps off the debt.
+    bucket: Mutex<(Instant, f64)>,
+}
+
+impl RateLimiter {
+    pub fn new(limit_kbps: u64) -> Self {
+        let limiter = Self {
+            limit: AtomicU64::new(0),
+            bucket: Mutex::new((Instant::now(), 0.0)),
+        };
+        limiter.set_limit_kbps(limit_kbps);
+        limiter
+    }
+
+    /// Takes effect on the next buffer of every running download.
+    pub fn set_limit_kbps(&self, kbps: u64) {
+        let bytes = kbps.saturating_mul(1024);
+        let old = self.limit.swap(bytes, Ordering::Relaxed);
+        if old != bytes {
+            log::info!(
+                "[dl] speed limit {}",
+                if kbps == 0 {
+                    "off".to_string()
+                } else {
+                    format!("{kbps} KB/s")
+                }
+            );
+            *self.bucket.lock().unwrap_or_else(|e| e.into_inner()) = (Instant::now(), 0.0);
+        }
+    }
+
+    /// Account for `bytes` just received, sleeping if the budget is spent.
+    pub async fn consume(&self, bytes: u64) {
+        let limit = self.limit.load(Ordering::Relaxed);
+        if limit == 0 {
+            return;
+        }
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.0).as_secs_f64() * limit as f64;
+            bucket.0 = now;
+            bucket.1 = (bucket.1 + refill).min(limit as f64) - bytes as f64;
+            if bucket.1 < 0.0 {
+                Duration::from_secs_f64(-bucket.1 / limit as f64)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -1,4 +1,6 @@
+use crate::game::GameChannel;
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 
 // ─── App codes & endpoints ────────────────────────────────────────────────────
 
@@ -19,23 +21,130 @@ pub fn gacha_inquiry_base(game_id: &str) -> &'static str {
     }
 }
 
-pub fn build_gacha_url(game_id: &str, grant_token: &str, uid: &str) -> String {
+pub fn build_gacha_url(
+    game_id: &str,
+    channel: GameChannel,
+    grant_token: &str,
+    uid: &str,
+) -> String {
     let base = gacha_inquiry_base(game_id);
-    format!("{base}?channelId=1&token={grant_token}&uid={uid}")
+    let channel_id = channel.channel_id();
+    format!("{base}?channelId={channel_id}&token={grant_token}&uid={uid}")
+}
+
+// ─── Captcha ──────────────────────────────────────────────────────────────────
+
+/// Geetest (v3) parameters the frontend needs to render the widget.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptchaChallenge {
+    pub gt: String,
+    pub challenge: String,
+    pub new_captcha: bool,
+}
+
+/// The widget's `getValidate()` result, sent back with the retried request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptchaSolution {
+    pub geetest_challenge: String,
+    pub geetest_validate: String,
+    pub geetest_seccode: String,
+}
+
+/// The API wants a captcha solved before it will send a code or log in.
+/// Retry the same call with the `CaptchaSolution`.
+#[derive(Debug)]
+pub struct CaptchaRequired(pub CaptchaChallenge);
+
+impl std::fmt::Display for CaptchaRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("需要完成人机验证")
+    }
+}
+
+impl std::error::Error for CaptchaRequired {}
+
+/// A challenge arrives as a failed status with `data.captcha = {gt, challenge}`.
+fn captcha_challenge(resp: &serde_json::Value) -> Option<CaptchaChallenge> {
+    let captcha = resp.get("data")?.get("captcha")?;
+    Some(CaptchaChallenge {
+        gt: captcha.get("gt")?.as_str()?.to_string(),
+        challenge: captcha.get("challenge")?.as_str()?.to_string(),
+        new_captcha: captcha
+            .get("new_captcha")
+            .and_then(|v| v.as_bool().or_else(|| v.as_i64().map(|n| n != 0)))
+            .unwrap_or(true),
+    })
+}
+
+/// `body` plus the solved captcha, when there is one.
+fn with_captcha(
+    mut body: serde_json::Value,
+    captcha: Option<&CaptchaSolution>,
+) -> Result<serde_json::Value> {
+    if let Some(captcha) = captcha {
+        body["captcha"] = serde_json::to_value(captcha)?;
+    }
+    Ok(body)
 }
 
 // ─── Auth API helpers ─────────────────────────────────────────────────────────
 
-/// Login with phone number and password.
-/// Returns (uid, token, token_type).
+/// Assumed token lifetime when the login response doesn't state one.
+const DEFAULT_TOKEN_LIFETIME_SECS: u64 = 7 * 24 * 3600;
+
+/// Account token returned by a successful login.
+#[derive(Debug, Clone)]
+pub struct LoginToken {
+    pub uid: String,
+    pub token: String,
+    pub token_type: String,
+    /// Unix seconds; from the response when present, otherwise issue time
+    /// plus DEFAULT_TOKEN_LIFETIME_SECS.
+    pub expires_at: u64,
+}
+
+impl LoginToken {
+    fn from_response(resp: &serde_json::Value) -> Self {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        // Fields may sit at the top level or under `data`.
+        let field = |key: &str| {
+            resp.get(key)
+                .or_else(|| resp.get("data").and_then(|d| d.get(key)))
+                .and_then(|v| v.as_u64())
+        };
+        let expires_at = field("expiresAt")
+            .or_else(|| field("expireAt"))
+            .or_else(|| field("expiresIn").map(|secs| now + secs))
+            .filter(|t| *t > now)
+            .unwrap_or(now + DEFAULT_TOKEN_LIFETIME_SECS);
+        LoginToken {
+            uid: resp["uid"].as_str().unwrap_or("").to_string(),
+            token: resp["token"].as_str().unwrap_or("").to_string(),
+            token_type: resp["type"].as_str().unwrap_or("A").to_string(),
+            expires_at,
+        }
+    }
+}
+
+/// Login with phone number and password. Fails with `CaptchaRequired` when
+/// the account is challenged; retry with the solved `captcha`.
 pub async fn login_by_password(
     phone: &str,
     password: &str,
+    captcha: Option<&CaptchaSolution>,
     client: &reqwest::Client,
-) -> Result<(String, String, String)> {
+) -> Result<LoginToken> {
+    let body = with_captcha(
+        serde_json::json!({ "phone": phone, "password": password }),
+        captcha,
+    )?;
     let resp: serde_json::Value = client
         .post("https://as.hypergryph.com/user/auth/v1/token_by_phone_password")
-        .json(&serde_json::json!({ "phone": phone, "password": password }))
+        .json(&body)
         .send()
         .await?
         .json()
@@ -43,18 +152,20 @@ pub async fn login_by_password(
 
     check_status(&resp, "密码登录失败")?;
 
-    Ok((
-        resp["uid"].as_str().unwrap_or("").to_string(),
-        resp["token"].as_str().unwrap_or("").to_string(),
-        resp["type"].as_str().unwrap_or("A").to_string(),
-    ))
+    Ok(LoginToken::from_response(&resp))
 }
 
-/// Send an SMS verification code to the phone number.
-pub async fn send_sms_code(phone: &str, client: &reqwest::Client) -> Result<()> {
+/// Send an SMS verification code to the phone number. Fails with
+/// `CaptchaRequired` when challenged; retry with the solved `captcha`.
+pub async fn send_sms_code(
+    phone: &str,
+    captcha: Option<&CaptchaSolution>,
+    client: &reqwest::Client,
+) -> Result<()> {
+    let body = with_captcha(serde_json::json!({ "phone": phone, "type": 2 }), captcha)?;
     let resp: serde_json::Value = client
         .post("https://as.hypergryph.com/general/v1/send_phone_code")
-        .json(&serde_json::json!({ "phone": phone, "type": 2 }))
+        .json(&body)
         .send()
         .await?
         .json()
@@ -64,12 +175,11 @@ pub async fn send_sms_code(phone: &str, client: &reqwest::Client) -> Result<()>
 }
 
 /// Login with phone number and SMS verification code.
-/// Returns (uid, token, token_type).
 pub async fn login_by_code(
     phone: &str,
     code: &str,
     client: &reqwest::Client,
-) -> Result<(String, String, String)> {
+) -> Result<LoginToken> {
     let resp: serde_json::Value = client
         .post("https://as.hypergryph.com/user/auth/v2/token_by_phone_code")
         .json(&serde_json::json!({ "phone": phone, "code": code }))
@@ -80,11 +190,7 @@ pub async fn login_by_code(
 
     check_status(&resp, "验证码登录失败")?;
 
-    Ok((
-        resp["uid"].as_str().unwrap_or("").to_string(),
-        resp["token"].as_str().unwrap_or("").to_string(),
-        resp["type"].as_str().unwrap_or("A").to_string(),
-    ))
+    Ok(LoginToken::from_response(&resp))
 }
 
 /// Exchange a user auth token for a game-specific grant token.
@@ -116,6 +222,9 @@ pub async fn get_game_grant(
 fn check_status(resp: &serde_json::Value, default_msg: &str) -> Result<()> {
     let status = resp["status"].as_i64().unwrap_or(-1);
     if status != 0 {
+        if let Some(challenge) = captcha_challenge(resp) {
+            return Err(CaptchaRequired(challenge).into());
+        }
         let msg = resp["msg"]
             .as_str()
             .or_else(|| resp["message"].as_str())
@@ -0,0 +1,235 @@
+// Pull estimates ("还需约 X 抽") and simulations ("如果我再抽 N 次") from a
+// game's pity model.
+//
+// Model: each pull is an independent trial whose 6★ chance depends only on
+// the pulls since the last 6★ in the same pool type (`pity`). The chance is
+// `base_rate` up to `soft_pity_start`, then rises by `soft_pity_step` per
+// pull until it reaches 1 (the hard pity). Pulls to the next 6★ therefore
+// follow a finite discrete distribution; the first copy starts from the
+// current pity and every later copy from 0, and the total is the sum of those
+// independent draws. Rate-up splits within a banner are not modelled — each
+// 6★ counts as one copy.
+
+use super::manager::PoolStats;
+use serde::Serialize;
+
+/// 6★ probability curve for one game.
+#[derive(Debug, Clone, Copy)]
+struct PityModel {
+    base_rate: f64,
+    /// Last pull number at `base_rate`; the next one is the first boosted pull.
+    soft_pity_start: u32,
+    soft_pity_step: f64,
+}
+
+/// Approximations of the published rates.
+fn pity_model(game_id: &str) -> PityModel {
+    match game_id {
+        "endfield" => PityModel {
+            base_rate: 0.008,
+            soft_pity_start: 65,
+            soft_pity_step: 0.05,
+        },
+        // Arknights: 2%, +2% per pull from the 51st, guaranteed by the 99th.
+        _ => PityModel {
+            base_rate: 0.02,
+            soft_pity_start: 50,
+            soft_pity_step: 0.02,
+        },
+    }
+}
+
+/// Quantile used for the pessimistic bound.
+const PESSIMISTIC_QUANTILE: f64 = 0.9;
+
+/// Above this many copies the distribution is extrapolated linearly instead of
+/// convolved, to bound the work.
+const MAX_EXACT_COPIES: u32 = 30;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullEstimate {
+    pub pool_type: String,
+    pub copies_wanted: u32,
+    pub current_pity: u32,
+    /// Mean pulls needed under the pity model
+    pub expected_pulls: f64,
+    /// Pulls that suffice with 90% probability
+    pub pessimistic_pulls: u32,
+    /// Pulls needed at the player's own historical average pity, if any 6★
+    /// have been pulled in this pool type
+    pub observed_pulls: Option<f64>,
+}
+
+/// Upper bounds for `simulate_pulls`, keeping a run well under a second.
+const MAX_SIM_PULLS: u32 = 10_000;
+const MAX_SIM_TRIALS: u32 = 100_000;
+
+/// Distribution of 6★ counts over simulated runs.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationResult {
+    pub pool_type: String,
+    pub count: u32,
+    pub trials: u32,
+    pub start_pity: u32,
+    pub mean: f64,
+    pub p10: u32,
+    pub p50: u32,
+    pub p90: u32,
+    /// Share of runs with no 6★ at all
+    pub zero_rate: f64,
+    /// `histogram[k]` = runs that got exactly k 6★
+    pub histogram: Vec<u32>,
+}
+
+impl PityModel {
+    /// 6★ chance on the `n`th pull since the last 6★ (1-based).
+    fn rate_at(&self, n: u32) -> f64 {
+        let boost = n.saturating_sub(self.soft_pity_start) as f64 * self.soft_pity_step;
+        (self.base_rate + boost).min(1.0)
+    }
+
+    /// P(next 6★ arrives after exactly `i + 1` more pulls), starting at `pity`.
+    fn next_six_star(&self, pity: u32) -> Vec<f64> {
+        let mut dist = Vec::new();
+        let mut survive = 1.0;
+        let mut n = pity + 1;
+        while survive > 1e-12 {
+            let p = self.rate_at(n);
+            dist.push(survive * p);
+            survive *= 1.0 - p;
+            n += 1;
+        }
+        dist
+    }
+}
+
+fn mean(dist: &[f64]) -> f64 {
+    dist.iter()
+        .enumerate()
+        .map(|(i, p)| (i + 1) as f64 * p)
+        .sum()
+}
+
+/// Distribution of the sum of two independent pull counts (index i = i+1 pulls).
+fn convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut out = vec![0.0; a.len() + b.len()];
+    for (i, pa) in a.iter().enumerate() {
+        for (j, pb) in b.iter().enumerate() {
+            // (i + 1) + (j + 1) pulls → index i + j + 1
+            out[i + j + 1] += pa * pb;
+        }
+    }
+    out
+}
+
+fn quantile(dist: &[f64], q: f64) -> u32 {
+    let mut acc = 0.0;
+    for (i, p) in dist.iter().enumerate() {
+        acc += p;
+        if acc >= q {
+            return (i + 1) as u32;
+        }
+    }
+    dist.len() as u32
+}
+
+/// Estimate pulls for `copies_wanted` more 6★ in a pool type, starting from the
+/// pool's current pity (0 when there is no history).
+pub fn estimate_pulls(
+    game_id: &str,
+    pool_type: &str,
+    pool: Option<&PoolStats>,
+    copies_wanted: u32,
+) -> PullEstimate {
+    let model = pity_model(game_id);
+    let current_pity = pool.map(|p| p.current_pity).unwrap_or(0);
+
+    let (expected_pulls, pessimistic_pulls) = if copies_wanted == 0 {
+        (0.0, 0)
+    } else {
+        let first = model.next_six_star(current_pity);
+        let fresh = model.next_six_star(0);
+        let exact = copies_wanted.min(MAX_EXACT_COPIES);
+        let mut total = first.clone();
+        for _ in 1..exact {
+            total = convolve(&total, &fresh);
+        }
+        let extra = (copies_wanted - exact) as f64;
+        let expected = mean(&first) + (copies_wanted - 1) as f64 * mean(&fresh);
+        let pessimistic = quantile(&total, PESSIMISTIC_QUANTILE) as f64
+            + extra * quantile(&fresh, PESSIMISTIC_QUANTILE) as f64;
+        ((expected * 10.0).round() / 10.0, pessimistic as u32)
+    };
+
+    let observed_pulls = pool
+        .filter(|p| p.six_star_count > 0 && p.avg_pity > 0.0)
+        .map(|p| (p.avg_pity * copies_wanted as f64 - current_pity as f64).max(0.0));
+
+    PullEstimate {
+        pool_type: pool_type.to_string(),
+        copies_wanted,
+        current_pity,
+        expected_pulls,
+        pessimistic_pulls,
+        observed_pulls,
+    }
+}
+
+/// Monte Carlo: run `trials` sequences of `count` pulls from `start_pity` and
+/// collect how many 6★ each produced. Inputs are clamped to MAX_SIM_PULLS and
+/// MAX_SIM_TRIALS.
+pub fn simulate_pulls(
+    game_id: &str,
+    pool_type: &str,
+    start_pity: u32,
+    count: u32,
+    trials: u32,
+) -> SimulationResult {
+    let model = pity_model(game_id);
+    let count = count.min(MAX_SIM_PULLS);
+    let trials = trials.clamp(1, MAX_SIM_TRIALS);
+    let mut rng = fastrand::Rng::new();
+
+    let mut histogram: Vec<u32> = Vec::new();
+    for _ in 0..trials {
+        let mut pity = start_pity;
+        let mut six_stars = 0usize;
+        for _ in 0..count {
+            pity += 1;
+            if rng.f64() < model.rate_at(pity) {
+                six_stars += 1;
+                pity = 0;
+            }
+        }
+        if histogram.len() <= six_stars {
+            histogram.resize(six_stars + 1, 0);
+        }
+        histogram[six_stars] += 1;
+    }
+
+    let total = trials as f64;
+    let mean = histogram
+        .iter()
+        .enumerate()
+        .map(|(k, n)| k as f64 * *n as f64)
+        .sum::<f64>()
+        / total;
+    // quantile() works on "index i = i + 1" distributions; shift by one.
+    let dist: Vec<f64> = histogram.iter().map(|n| *n as f64 / total).collect();
+    let percentile = |q: f64| quantile(&dist, q).saturating_sub(1);
+
+    SimulationResult {
+        pool_type: pool_type.to_string(),
+        count,
+        trials,
+        start_pity,
+        mean: (mean * 100.0).round() / 100.0,
+        p10: percentile(0.1),
+        p50: percentile(0.5),
+        p90: percentile(0.9),
+        zero_rate: histogram.first().map(|n| *n as f64 / total).unwrap_or(0.0),
+        histogram,
+    }
+}
@@ -1,6 +1,9 @@
+use super::auth;
+use crate::config::PityAnchor;
 use anyhow::{anyhow, Result};
+use chrono::{Datelike, Days, FixedOffset, Local, NaiveDateTime, TimeZone};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
 // ─── Public types ─────────────────────────────────────────────────────────────
@@ -19,6 +22,10 @@ pub struct GachaRecord {
     pub timestamp: i64,
     pub is_new: bool,
     pub pity: u32, // pulls since last 6★ in this pool_type
+    /// Index of the API entry (one single pull or one ten-pull) this record came
+    /// from. Absent in data fetched before batches were tracked.
+    #[serde(default)]
+    pub batch_id: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +49,23 @@ pub struct PoolStats {
     pub six_star_rate: f64,
     pub current_pity: u32,
     pub avg_pity: f64,
+    pub first_six_star: Option<SixStarMilestone>,
+    pub last_six_star: Option<SixStarMilestone>,
+    /// Pulls since the last 5★. Counted on its own: a 6★ doesn't reset it.
+    pub five_star_current_pity: u32,
+    /// Mean pulls per 5★
+    pub five_star_avg_pity: f64,
+    pub five_star_rate: f64,
+}
+
+/// A notable 6★ pull, e.g. "你的第一个六星：2023-05-03，XX，第 42 抽".
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SixStarMilestone {
+    pub item_name: String,
+    pub timestamp: i64,
+    /// Pulls it took to obtain.
+    pub pity: u32,
 }
 
 #[derive(Debug, Serialize)]
@@ -50,11 +74,189 @@ pub struct GachaStatsResult {
     pub uid: String,
     pub total_pulls: u32,
     pub by_pool: HashMap<String, PoolStats>,
+    /// Keyed "YYYY-MM" (local time), across all pools.
+    pub by_month: HashMap<String, MonthStats>,
+    pub fetched_at: i64,
+}
+
+/// One calendar month of pulls, for yearly summaries.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthStats {
+    pub total_pulls: u32,
+    pub six_star_count: u32,
+    /// In pull order; repeats are listed each time.
+    pub six_star_items: Vec<String>,
+}
+
+/// Per-game headline numbers for the home-screen overview.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameGachaSummary {
+    pub game_id: String,
+    pub uid: String,
+    pub total_pulls: u32,
+    pub six_star_count: u32,
+    pub six_star_rate: f64,
     pub fetched_at: i64,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeBucket {
+    Week,
+    Month,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateBucket {
+    /// "YYYY-MM" for monthly buckets, week-start date "YYYY-MM-DD" for weekly ones.
+    pub label: String,
+    /// Unix timestamp of the bucket's first second (local midnight).
+    pub start_ts: i64,
+    pub total_pulls: u32,
+    pub six_star_count: u32,
+    pub six_star_rate: f64,
+}
+
+/// One distinct 6★ in the player's collection.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SixStarEntry {
+    pub item_name: String,
+    /// Copies pulled (1 + duplicates)
+    pub count: u32,
+    pub first_pulled_at: i64,
+    pub last_pulled_at: i64,
+}
+
+/// One distinct item of any rarity, e.g. "Exusiai ×3".
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemSummary {
+    pub item_name: String,
+    pub item_type: String,
+    pub rarity: u8,
+    /// Copies pulled (1 + duplicates)
+    pub count: u32,
+    pub first_obtained_ts: i64,
+    /// Whether the first copy was flagged new by the game
+    pub is_new_first: bool,
+}
+
+/// What `validate_gacha_url` found wrong with a pasted URL.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GachaUrlCheck {
+    pub valid: bool,
+    /// e.g. "ak.hypergryph.com"
+    pub expected_host: String,
+    /// Scheme, host and path match the game's inquiry endpoint
+    pub host_ok: bool,
+    /// Query parameters the fetch needs but the URL lacks
+    pub missing_params: Vec<String>,
+    /// The token looks cut off (too short or ends mid-escape)
+    pub token_truncated: bool,
+    /// One line per problem, for display
+    pub problems: Vec<String>,
+}
+
+/// Result of a paginated fetch. `error` is set when the fetch stopped early
+/// and `records` only covers the pages read before that.
+#[derive(Debug)]
+pub struct FetchedRecords {
+    pub uid: String,
+    /// The full history: fetched records, merged after `known` ones when
+    /// fetching incrementally.
+    pub records: Vec<GachaRecord>,
+    /// Records that were not in `known`.
+    pub new_records: usize,
+    pub error: Option<String>,
+    /// How far the newest server record lies ahead of the local clock, when
+    /// beyond `CLOCK_SKEW_THRESHOLD_SECS`.
+    pub clock_skew_secs: Option<i64>,
+}
+
+/// How a pool's history splits into ten-pulls and single pulls.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolPullBreakdown {
+    pub pool_type: String,
+    pub ten_pulls: u32,
+    pub single_pulls: u32,
+    /// Pulls from batches that are neither 1 nor 10 (e.g. truncated history).
+    pub other_pulls: u32,
+    pub total_pulls: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullBreakdown {
+    pub uid: String,
+    pub ten_pulls: u32,
+    pub single_pulls: u32,
+    pub other_pulls: u32,
+    pub by_pool: HashMap<String, PoolPullBreakdown>,
+}
+
+/// What `compact_storage` cleaned up.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactReport {
+    /// Snapshots removed because an identical one was kept
+    pub duplicates_removed: u32,
+    /// Snapshots removed for being older than the retention window
+    pub expired_removed: u32,
+    /// Data files rewritten as minimal JSON
+    pub files_rewritten: u32,
+    pub bytes_reclaimed: u64,
+}
+
+/// Which records an export includes. `None` (or an empty `pool_type`) means
+/// no restriction; the time bounds are inclusive.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFilter {
+    pub from_ts: Option<i64>,
+    pub to_ts: Option<i64>,
+    pub min_rarity: Option<u8>,
+    pub pool_type: Option<String>,
+}
+
+impl ExportFilter {
+    pub fn matches(&self, r: &GachaRecord) -> bool {
+        self.from_ts.is_none_or(|t| r.timestamp >= t)
+            && self.to_ts.is_none_or(|t| r.timestamp <= t)
+            && self.min_rarity.is_none_or(|m| r.rarity >= m)
+            && self
+                .pool_type
+                .as_deref()
+                .is_none_or(|p| p.is_empty() || r.pool_type == p)
+    }
+}
+
+/// Outcome of `import_json`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    /// Records not previously stored
+    pub added: u32,
+    /// Records already present, skipped
+    pub duplicates: u32,
+    /// Records stored after the merge
+    pub total: u32,
+}
+
 // ─── Manager ─────────────────────────────────────────────────────────────────
 
+/// Subdirectory of the data dir holding gacha snapshots, named
+/// `{game_id}_{uid}_{timestamp}.json`.
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// Snapshots older than this are removed by `compact_storage`.
+const SNAPSHOT_RETENTION: std::time::Duration = std::time::Duration::from_secs(90 * 24 * 3600);
+
 pub struct GachaManager {
     data_dir: PathBuf,
     client: reqwest::Client,
@@ -150,9 +352,17 @@ impl GachaManager {
 
     fn find_url_in_bytes(bytes: &[u8], url_pattern: &str) -> Option<String> {
         let text = String::from_utf8_lossy(bytes);
-        let pattern_pos = text.find(url_pattern)?;
+        // Binary caches may hold several copies, some truncated; try each in turn.
+        text.match_indices(url_pattern)
+            .find_map(|(pattern_pos, _)| Self::extract_url_at(&text, pattern_pos, url_pattern))
+    }
+
+    fn extract_url_at(text: &str, pattern_pos: usize, url_pattern: &str) -> Option<String> {
         // Walk back to find https://
-        let search_start = pattern_pos.saturating_sub(512);
+        let mut search_start = pattern_pos.saturating_sub(512);
+        while !text.is_char_boundary(search_start) {
+            search_start += 1;
+        }
         let https_offset = text[search_start..pattern_pos].rfind("https://")?;
         let url_start = search_start + https_offset;
         // Walk forward to find end of URL
@@ -162,76 +372,159 @@ impl GachaManager {
             })
             .map(|i| url_start + i)
             .unwrap_or_else(|| text.len().min(url_start + 8192));
-        let url = text[url_start..url_end].trim().to_string();
-        if url.starts_with("https://") && url.contains('?') {
-            Some(url)
-        } else {
-            None
+        let url = text.get(url_start..url_end)?.trim();
+        is_valid_gacha_url(url, url_pattern).then(|| url.to_string())
+    }
+
+    /// Check a pasted gacha URL against the game's inquiry endpoint before
+    /// fetching, so the UI can say what is wrong instead of relaying an API
+    /// error. The fetch needs `token`, plus `uid` or `channelId`.
+    pub fn validate_gacha_url(game_id: &str, url: &str) -> GachaUrlCheck {
+        let url = url.trim();
+        let expected = reqwest::Url::parse(auth::gacha_inquiry_base(game_id))
+            .expect("inquiry base is a valid URL");
+        let expected_host = expected.host_str().unwrap_or_default().to_string();
+        let mut problems = Vec::new();
+
+        let host_ok = reqwest::Url::parse(url).is_ok_and(|u| {
+            u.scheme() == "https"
+                && u.host_str() == Some(expected_host.as_str())
+                && u.path().trim_end_matches('/') == expected.path()
+        });
+        if !host_ok {
+            problems.push(format!(
+                "链接不是{}的寻访记录地址（应以 {} 开头）",
+                if game_id == "endfield" {
+                    "终末地"
+                } else {
+                    "明日方舟"
+                },
+                auth::gacha_inquiry_base(game_id)
+            ));
+        }
+
+        let mut missing_params = Vec::new();
+        let token = extract_query_param(url, "token");
+        if token.is_none() {
+            missing_params.push("token".to_string());
+        }
+        if extract_query_param(url, "uid").is_none()
+            && extract_query_param(url, "channelId").is_none()
+        {
+            missing_params.push("uid".to_string());
+            missing_params.push("channelId".to_string());
+        }
+        if !missing_params.is_empty() {
+            problems.push(format!(
+                "链接缺少参数：{}，请复制完整链接",
+                missing_params.join("、")
+            ));
+        }
+
+        let token_truncated = token.as_deref().is_some_and(is_truncated_token);
+        if token_truncated {
+            problems.push("token 似乎不完整，请重新复制链接".to_string());
+        }
+
+        GachaUrlCheck {
+            valid: problems.is_empty(),
+            expected_host,
+            host_ok,
+            missing_params,
+            token_truncated,
+            problems,
         }
     }
 
     // ── API fetch (paginated) ─────────────────────────────────────────────────
 
-    /// Fetch all gacha records from the given authenticated URL.
-    /// Returns (uid, records_in_chronological_order).
+    /// Fetch gacha records from the given authenticated URL.
+    ///
+    /// With `known` (the stored data for this game), paging stops at the
+    /// first entry already stored — matched on timestamp, pool and item — and
+    /// the new records are merged after the known ones, so a routine refresh
+    /// costs a page or two. Without it the whole history is fetched.
+    ///
+    /// A failure after some pages were read still returns those records (in
+    /// chronological order, pity computed over the partial set) with
+    /// `error` set; only a failure before any record arrives is an `Err`.
+    /// An incremental fetch that fails before reaching known records keeps
+    /// only the known ones, since merging would leave a silent gap that later
+    /// refreshes could never fill.
     pub async fn fetch_all_records(
         &self,
         game_id: &str,
         base_url: &str,
-    ) -> Result<(String, Vec<GachaRecord>)> {
+        known: Option<&GachaData>,
+    ) -> Result<FetchedRecords> {
         // Extract uid from the URL query params
         let uid = extract_query_param(base_url, "uid")
             .or_else(|| extract_query_param(base_url, "channelId").map(|_| String::new()))
             .unwrap_or_default();
 
-        let mut all_entries: Vec<RawEntry> = Vec::new();
+        // Stored data for another account can't be extended.
+        let mut known = known.filter(|k| k.uid.is_empty() || uid.is_empty() || k.uid == uid);
+        let mut known_keys: HashSet<(i64, &str, &str)> = known
+            .map(|k| {
+                k.records
+                    .iter()
+                    .map(|r| (r.timestamp, r.pool_name.as_str(), r.item_name.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut reached_known = false;
+        let mut page_delay = PAGE_DELAY;
+
+        // Records are built page by page so each page's raw JSON is dropped as
+        // soon as it has been converted. Pages arrive newest-first while pity,
+        // ids and batch ids depend on chronological order, so those fields are
+        // filled in by a second pass once the list is complete. That costs one
+        // extra walk over the records but keeps only a single copy of the
+        // history in memory, capped at MAX_FETCH_RECORDS.
+        let mut records: Vec<GachaRecord> = Vec::new();
+        let mut batches: u32 = 0;
+        let mut final_uid = uid;
         let mut seq_num: i64 = 0; // 0 = start from newest
+        let mut error: Option<anyhow::Error> = None;
 
         loop {
             let url = build_page_url(base_url, seq_num, 10);
-            let resp: serde_json::Value = self
-                .client
-                .get(&url)
-                .send()
-                .await?
-                .json()
-                .await?;
-
-            let code = resp
-                .get("code")
-                .and_then(|c| c.as_i64())
-                .unwrap_or(-1);
-            if code != 0 {
-                let msg = resp
-                    .get("msg")
-                    .and_then(|m| m.as_str())
-                    .unwrap_or("unknown error");
-                return Err(anyhow!("API 返回错误 {code}: {msg}"));
-            }
-
-            let data = resp
-                .get("data")
-                .ok_or_else(|| anyhow!("响应缺少 data 字段"))?;
+            let resp = match self.fetch_page_with_backoff(&url, &mut page_delay).await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            };
+            let data = &resp["data"];
 
             // Try to get uid from response if not in URL
-            let resp_uid = data
-                .get("uid")
-                .and_then(|u| u.as_str())
-                .map(|s| s.to_string());
-
-            let list = data
-                .get("list")
-                .and_then(|l| l.as_array())
-                .cloned()
-                .unwrap_or_default();
-
-            if list.is_empty() {
-                break;
+            if final_uid.is_empty() {
+                if let Some(u) = data.get("uid").and_then(|u| u.as_str()) {
+                    final_uid = u.to_string();
+                    // The URL didn't say whose history this is; if it isn't
+                    // the stored account's, fetch it in full instead.
+                    if let Some(k) = known.filter(|k| k.uid != final_uid) {
+                        log::info!(
+                            "[gacha] uid {} differs from stored uid {}, doing a full fetch",
+                            final_uid,
+                            k.uid
+                        );
+                        known = None;
+                        known_keys.clear();
+                    }
+                }
             }
 
-            let pagination = data
-                .get("pagination")
-                .ok_or_else(|| anyhow!("响应缺少 pagination 字段"))?;
+            let list = match data.get("list").and_then(|l| l.as_array()) {
+                Some(list) if !list.is_empty() => list,
+                _ => break,
+            };
+
+            let Some(pagination) = data.get("pagination") else {
+                error = Some(anyhow!("响应缺少 pagination 字段"));
+                break;
+            };
             let count = pagination
                 .get("count")
                 .and_then(|c| c.as_i64())
@@ -241,60 +534,560 @@ impl GachaManager {
                 .and_then(|c| c.as_i64())
                 .unwrap_or(0);
 
-            for entry in &list {
-                let ts = entry.get("ts").and_then(|t| t.as_i64()).unwrap_or(0);
-                let pool = entry
-                    .get("pool")
-                    .and_then(|p| p.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                let chars: Vec<RawChar> = entry
-                    .get("chars")
-                    .and_then(|c| serde_json::from_value(c.clone()).ok())
-                    .unwrap_or_default();
-                all_entries.push(RawEntry { ts, pool, chars });
-            }
-
-            // Try to extract uid from first response
-            let final_uid = if uid.is_empty() {
-                resp_uid.clone().unwrap_or_default()
-            } else {
-                uid.clone()
-            };
+            for entry in list {
+                let entry = RawEntry::from_json(entry);
+                if entry
+                    .chars
+                    .iter()
+                    .any(|c| known_keys.contains(&(entry.ts, entry.pool.as_str(), c.name.as_str())))
+                {
+                    reached_known = true;
+                    break;
+                }
+                push_entry_records(&mut records, game_id, entry, batches);
+                batches += 1;
+            }
+            if reached_known {
+                break;
+            }
+
+            if records.len() > MAX_FETCH_RECORDS {
+                error = Some(anyhow!(
+                    "抽卡记录超过 {MAX_FETCH_RECORDS} 条上限，已停止获取"
+                ));
+                break;
+            }
 
             // Stop when fewer than requested (last page) or no more seqNum
             if count < 10 || current == 0 {
-                let records = build_records_with_pity(&final_uid, game_id, all_entries);
-                return Ok((final_uid, records));
+                break;
             }
 
             seq_num = current;
 
             // Small delay to avoid rate limiting
-            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            tokio::time::sleep(page_delay).await;
+        }
+
+        if let Some(e) = error.take() {
+            if records.is_empty() {
+                return Err(e);
+            }
+            if let Some(k) = known.filter(|_| !reached_known) {
+                log::warn!(
+                    "[gacha] incremental fetch failed before reaching stored records, \
+                     discarding {} new record(s): {}",
+                    records.len(),
+                    e
+                );
+                return Ok(FetchedRecords {
+                    uid: if final_uid.is_empty() {
+                        k.uid.clone()
+                    } else {
+                        final_uid
+                    },
+                    records: k.records.clone(),
+                    new_records: 0,
+                    error: Some(e.to_string()),
+                    clock_skew_secs: None,
+                });
+            }
+            log::warn!(
+                "[gacha] fetch stopped after {} record(s): {}",
+                records.len(),
+                e
+            );
+            error = Some(e);
+        }
+
+        // Still newest-first here, so the first record is the latest pull.
+        let clock_skew_secs = records.first().and_then(|r| clock_skew(r.timestamp));
+
+        let new_records = records.len();
+        if let Some(k) = known {
+            if final_uid.is_empty() {
+                final_uid = k.uid.clone();
+            }
+            log::info!(
+                "[gacha] {} incremental fetch: {} new record(s), reached stored: {}",
+                game_id,
+                new_records,
+                reached_known
+            );
+            records.reverse();
+            let mut merged = Vec::with_capacity(k.records.len() + new_records);
+            merged.extend_from_slice(&k.records);
+            merged.append(&mut records);
+            records = merged;
+            renumber_records(&final_uid, &mut records);
+        } else {
+            finalize_records(&final_uid, &mut records, batches);
+        }
+        Ok(FetchedRecords {
+            uid: final_uid,
+            records,
+            new_records,
+            error: error.map(|e| e.to_string()),
+            clock_skew_secs,
+        })
+    }
+
+    /// `fetch_page`, retrying a rate-limited page with growing delays. Once
+    /// the API has pushed back, `page_delay` is raised for the rest of the
+    /// fetch so the following pages don't trip the limit again.
+    async fn fetch_page_with_backoff(
+        &self,
+        url: &str,
+        page_delay: &mut std::time::Duration,
+    ) -> Result<serde_json::Value> {
+        let mut backoff = RATE_LIMIT_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let err = match self.fetch_page(url).await {
+                Ok(resp) => return Ok(resp),
+                Err(e) => e,
+            };
+            let Some(limited) = err.downcast_ref::<RateLimited>() else {
+                return Err(err);
+            };
+            if attempt >= RATE_LIMIT_RETRIES {
+                return Err(anyhow!(
+                    "请求过于频繁，重试 {RATE_LIMIT_RETRIES} 次后仍被限流：{limited}"
+                ));
+            }
+            attempt += 1;
+            let wait = limited
+                .retry_after
+                .unwrap_or(backoff)
+                .min(RATE_LIMIT_MAX_WAIT);
+            log::warn!(
+                "[gacha] rate limited ({}), retry {}/{} in {:?}",
+                limited,
+                attempt,
+                RATE_LIMIT_RETRIES,
+                wait
+            );
+            *page_delay = (*page_delay).max(RATE_LIMITED_PAGE_DELAY);
+            tokio::time::sleep(wait).await;
+            backoff = (backoff * 2).min(RATE_LIMIT_MAX_WAIT);
+        }
+    }
+
+    /// GET one page and check the API envelope; the returned value has `data`.
+    /// Rate-limit replies come back as a `RateLimited` error.
+    async fn fetch_page(&self, url: &str) -> Result<serde_json::Value> {
+        let resp = self.client.get(url).send().await?;
+        if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = resp
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(std::time::Duration::from_secs);
+            return Err(RateLimited {
+                message: "HTTP 429".to_string(),
+                retry_after,
+            }
+            .into());
+        }
+        let resp: serde_json::Value = resp.json().await?;
+
+        let code = resp.get("code").and_then(|c| c.as_i64()).unwrap_or(-1);
+        if code != 0 {
+            let msg = resp
+                .get("msg")
+                .and_then(|m| m.as_str())
+                .unwrap_or("unknown error");
+            if is_rate_limit_reply(code, msg) {
+                return Err(RateLimited {
+                    message: format!("{code}: {msg}"),
+                    retry_after: None,
+                }
+                .into());
+            }
+            return Err(anyhow!("API 返回错误 {code}: {msg}"));
+        }
+        if resp.get("data").is_none() {
+            return Err(anyhow!("响应缺少 data 字段"));
+        }
+        Ok(resp)
+    }
+
+    /// Every 6★ a UID has pulled with copy counts, most copies first and,
+    /// among equal counts, most recently pulled first.
+    pub fn six_star_inventory(data: &GachaData, uid: &str) -> Vec<SixStarEntry> {
+        let mut by_name: HashMap<&str, SixStarEntry> = HashMap::new();
+        for record in data
+            .records
+            .iter()
+            .filter(|r| r.uid == uid && r.rarity == 6)
+        {
+            let entry = by_name
+                .entry(record.item_name.as_str())
+                .or_insert_with(|| SixStarEntry {
+                    item_name: record.item_name.clone(),
+                    count: 0,
+                    first_pulled_at: record.timestamp,
+                    last_pulled_at: record.timestamp,
+                });
+            entry.count += 1;
+            entry.first_pulled_at = entry.first_pulled_at.min(record.timestamp);
+            entry.last_pulled_at = entry.last_pulled_at.max(record.timestamp);
+        }
+
+        let mut entries: Vec<SixStarEntry> = by_name.into_values().collect();
+        entries.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then(b.last_pulled_at.cmp(&a.last_pulled_at))
+        });
+        entries
+    }
+
+    /// Copy counts for every item pulled, highest rarity first and, within a
+    /// rarity, most copies first.
+    pub fn compute_item_summary(data: &GachaData) -> Vec<ItemSummary> {
+        let mut by_name: HashMap<&str, ItemSummary> = HashMap::new();
+        for record in &data.records {
+            let entry = by_name
+                .entry(record.item_name.as_str())
+                .or_insert_with(|| ItemSummary {
+                    item_name: record.item_name.clone(),
+                    item_type: record.item_type.clone(),
+                    rarity: record.rarity,
+                    count: 0,
+                    first_obtained_ts: record.timestamp,
+                    is_new_first: record.is_new,
+                });
+            entry.count += 1;
+            // Records are chronological, but don't rely on it for the first copy.
+            if record.timestamp < entry.first_obtained_ts {
+                entry.first_obtained_ts = record.timestamp;
+                entry.is_new_first = record.is_new;
+            }
+        }
+
+        let mut items: Vec<ItemSummary> = by_name.into_values().collect();
+        items.sort_by(|a, b| {
+            b.rarity
+                .cmp(&a.rarity)
+                .then(b.count.cmp(&a.count))
+                .then_with(|| a.item_name.cmp(&b.item_name))
+        });
+        items
+    }
+
+    // ── Import ────────────────────────────────────────────────────────────────
+
+    /// Parse an export file produced by the official tooling. Nothing is
+    /// stored; `import_json` merges a file into the saved history.
+    ///
+    /// The format is detected from the top-level keys: `list` (or `data.list`,
+    /// the raw API envelope) holds official entries of `{ts, pool, chars}`,
+    /// while `records` is our own `GachaData` shape and is loaded as-is. A bare
+    /// array is the record list written by `export_json`.
+    pub fn import_official_export(game_id: &str, path: &str) -> Result<GachaData> {
+        let raw = std::fs::read_to_string(path)?;
+        let root: serde_json::Value =
+            serde_json::from_str(&raw).map_err(|e| anyhow!("无法解析导入文件：{e}"))?;
+
+        if root.is_array() {
+            let records: Vec<GachaRecord> = serde_json::from_value(root)?;
+            if let Some(other) = records.iter().find(|r| r.game_id != game_id) {
+                return Err(anyhow!(
+                    "导入文件属于 {}，与当前游戏 {} 不符",
+                    other.game_id,
+                    game_id
+                ));
+            }
+            let mut data = GachaData {
+                uid: String::new(),
+                game_id: game_id.to_string(),
+                records,
+                fetched_at: 0,
+            };
+            backfill_uid(&mut data);
+            data.fetched_at = data.records.iter().map(|r| r.timestamp).max().unwrap_or(0);
+            return Ok(data);
+        }
+
+        if root.get("records").is_some() {
+            let data: GachaData = serde_json::from_value(root)?;
+            if data.game_id != game_id {
+                return Err(anyhow!(
+                    "导入文件属于 {}，与当前游戏 {} 不符",
+                    data.game_id,
+                    game_id
+                ));
+            }
+            return Ok(data);
         }
 
-        let final_uid = uid;
-        let records = build_records_with_pity(&final_uid, game_id, all_entries);
-        Ok((final_uid, records))
+        let body = root.get("data").unwrap_or(&root);
+        let list = body
+            .get("list")
+            .and_then(|l| l.as_array())
+            .ok_or_else(|| anyhow!("无法识别的导出格式"))?;
+        // Any other object with a `list` (UIGF, another tool's format) would
+        // otherwise parse as records with no time and no items.
+        let is_official_entry = |e: &serde_json::Value| {
+            e.get("ts").is_some_and(|t| t.is_i64()) && e.get("chars").is_some_and(|c| c.is_array())
+        };
+        if !list.iter().any(is_official_entry) {
+            return Err(anyhow!("无法识别的导出格式"));
+        }
+        let uid = body
+            .get("uid")
+            .or_else(|| root.get("uid"))
+            .and_then(|u| u.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        // Exports are normally newest-first like the API, but don't rely on it.
+        let mut entries: Vec<RawEntry> = list.iter().map(RawEntry::from_json).collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.ts));
+
+        let batches = entries.len() as u32;
+        let mut records = Vec::new();
+        for (batch, entry) in entries.into_iter().enumerate() {
+            push_entry_records(&mut records, game_id, entry, batch as u32);
+        }
+        finalize_records(&uid, &mut records, batches);
+
+        log::info!(
+            "[gacha] imported {} record(s) for uid {} from {}",
+            records.len(),
+            uid,
+            path
+        );
+        Ok(GachaData {
+            uid,
+            game_id: game_id.to_string(),
+            records,
+            fetched_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64,
+        })
+    }
+
+    /// Parse records exported by another tool (or by us) without storing
+    /// them.
+    ///
+    /// Accepts everything `import_official_export` does, plus a UIGF-like
+    /// interchange file:
+    ///
+    /// ```json
+    /// { "info": { "uid": "123", "region_time_zone": 8 },
+    ///   "list": [ { "pool_name": "…", "item_name": "…", "item_type": "character",
+    ///               "rank": 6, "time": "2024-01-01 12:00:00" } ] }
+    /// ```
+    ///
+    /// `name`, `gacha_type`/`pool`, `rank_type`/`rarity` and `timestamp`/`ts`
+    /// (Unix seconds) are accepted as aliases; `time` is read in
+    /// `region_time_zone` (UTC+8 when absent).
+    pub fn parse_import_file(game_id: &str, path: &str) -> Result<GachaData> {
+        let raw = std::fs::read_to_string(path)?;
+        let root: serde_json::Value = serde_json::from_str(raw.trim_start_matches('\u{FEFF}'))
+            .map_err(|e| anyhow!("无法解析导入文件：{e}"))?;
+        if root.get("info").is_some() && root.get("list").is_some() {
+            parse_interchange(game_id, &root)
+        } else {
+            Self::import_official_export(game_id, path)
+        }
+    }
+
+    /// Merge records exported by another tool (or by us) into the stored data
+    /// for `game_id`. Any file `parse_import_file` accepts can be merged.
+    ///
+    /// Ids are positional (`{uid}_{index}`), so the same pull can carry
+    /// different ids in different files. Duplicates are therefore matched on
+    /// (time, pool, item), counting repeats so two identical pulls in one
+    /// ten-pull both survive. The merged list is re-sorted chronologically and
+    /// ids, batch ids and pity are recomputed.
+    pub fn import_json(&self, game_id: &str, path: &str) -> Result<ImportReport> {
+        let imported = Self::parse_import_file(game_id, path)?;
+
+        let mut data = self.load_data(game_id).unwrap_or_else(|| GachaData {
+            uid: String::new(),
+            game_id: game_id.to_string(),
+            records: Vec::new(),
+            fetched_at: 0,
+        });
+        if !data.uid.is_empty() && !imported.uid.is_empty() && data.uid != imported.uid {
+            return Err(anyhow!(
+                "导入记录属于 UID {}，与已保存的 UID {} 不符",
+                imported.uid,
+                data.uid
+            ));
+        }
+
+        let key = |r: &GachaRecord| (r.timestamp, r.pool_name.clone(), r.item_name.clone());
+        let mut existing: HashMap<(i64, String, String), u32> = HashMap::new();
+        for r in &data.records {
+            *existing.entry(key(r)).or_insert(0) += 1;
+        }
+        let mut added = 0u32;
+        let mut duplicates = 0u32;
+        for r in imported.records {
+            match existing.get_mut(&key(&r)) {
+                Some(n) if *n > 0 => {
+                    *n -= 1;
+                    duplicates += 1;
+                }
+                _ => {
+                    data.records.push(r);
+                    added += 1;
+                }
+            }
+        }
+
+        if data.uid.is_empty() {
+            data.uid = imported.uid;
+        }
+        data.fetched_at = data.fetched_at.max(imported.fetched_at);
+        if added > 0 {
+            let uid = data.uid.clone();
+            renumber_records(&uid, &mut data.records);
+            self.save_data(&data)?;
+        }
+        log::info!(
+            "[gacha] import {} into {}: added={} duplicates={}",
+            path,
+            game_id,
+            added,
+            duplicates
+        );
+        Ok(ImportReport {
+            added,
+            duplicates,
+            total: data.records.len() as u32,
+        })
     }
 
     // ── Storage ───────────────────────────────────────────────────────────────
 
     pub fn load_data(&self, game_id: &str) -> Option<GachaData> {
-        let raw = std::fs::read_to_string(self.data_path(game_id)).ok()?;
+        let path = self.data_path(game_id);
+        let raw = retry_locked("read", &path, || std::fs::read_to_string(&path)).ok()?;
         serde_json::from_str(&raw).ok()
     }
 
+    /// Data with an empty `uid` gets one derived from its records first.
     pub fn save_data(&self, data: &GachaData) -> Result<()> {
+        if data.uid.is_empty() {
+            let mut fixed = data.clone();
+            if backfill_uid(&mut fixed) {
+                log::warn!("[gacha] empty uid, derived {} from records", fixed.uid);
+                return self.write_data(&fixed);
+            }
+        }
+        self.write_data(data)
+    }
+
+    /// Repair saved data whose `uid` is empty by deriving it from the records.
+    /// Returns the uid now stored.
+    pub fn fix_uid(&self, game_id: &str) -> Result<String> {
+        let mut data = self
+            .load_data(game_id)
+            .ok_or_else(|| anyhow!("没有 {} 的抽卡记录", game_id))?;
+        if !data.uid.is_empty() {
+            return Ok(data.uid);
+        }
+        if !backfill_uid(&mut data) {
+            return Err(anyhow!("记录中没有可用的 UID，请重新获取抽卡记录"));
+        }
+        self.write_data(&data)?;
+        log::info!("[gacha] fixed empty uid for {} → {}", game_id, data.uid);
+        Ok(data.uid)
+    }
+
+    /// Write to a temp file and rename it over the data file, so a failed
+    /// write never leaves a truncated dataset behind.
+    fn write_data(&self, data: &GachaData) -> Result<()> {
         let path = self.data_path(&data.game_id);
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
-        std::fs::write(&path, serde_json::to_string_pretty(data)?)?;
+        let tmp = path.with_extension("json.tmp");
+        let raw = serde_json::to_vec(data)?;
+        retry_locked("write", &tmp, || std::fs::write(&tmp, &raw))?;
+        retry_locked("replace", &path, || std::fs::rename(&tmp, &path))?;
         Ok(())
     }
 
+    /// Drop duplicate and expired snapshots and rewrite each game's data file
+    /// as minimal JSON. The newest snapshot per game/UID is always kept.
+    pub fn compact_storage(&self, game_ids: &[&str]) -> Result<CompactReport> {
+        let mut report = CompactReport::default();
+
+        let snapshot_dir = self.data_dir.join(SNAPSHOTS_DIR);
+        if snapshot_dir.is_dir() {
+            // "{game_id}_{uid}" → [(modified, path, size)]
+            let mut groups: HashMap<String, Vec<(std::time::SystemTime, PathBuf, u64)>> =
+                HashMap::new();
+            for entry in std::fs::read_dir(&snapshot_dir)? {
+                let path = entry?.path();
+                let Some(key) = snapshot_key(&path) else {
+                    continue;
+                };
+                let meta = std::fs::metadata(&path)?;
+                let modified = meta.modified().unwrap_or(std::time::UNIX_EPOCH);
+                groups
+                    .entry(key)
+                    .or_default()
+                    .push((modified, path, meta.len()));
+            }
+
+            let now = std::time::SystemTime::now();
+            for mut snapshots in groups.into_values() {
+                snapshots.sort_by_key(|s| std::cmp::Reverse(s.0));
+                let mut seen = std::collections::HashSet::new();
+                for (i, (modified, path, size)) in snapshots.into_iter().enumerate() {
+                    let digest = blake3::hash(&std::fs::read(&path)?);
+                    let duplicate = !seen.insert(digest);
+                    let expired = now
+                        .duration_since(modified)
+                        .is_ok_and(|age| age > SNAPSHOT_RETENTION);
+                    if i == 0 || !(duplicate || expired) {
+                        continue;
+                    }
+                    std::fs::remove_file(&path)?;
+                    report.bytes_reclaimed += size;
+                    if duplicate {
+                        report.duplicates_removed += 1;
+                    } else {
+                        report.expired_removed += 1;
+                    }
+                }
+            }
+        }
+
+        for game_id in game_ids {
+            let path = self.data_path(game_id);
+            let Ok(before) = std::fs::metadata(&path).map(|m| m.len()) else {
+                continue;
+            };
+            let Some(data) = self.load_data(game_id) else {
+                log::warn!("[gacha] skipping unreadable {}", path.display());
+                continue;
+            };
+            self.write_data(&data)?;
+            let after = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(before);
+            report.bytes_reclaimed += before.saturating_sub(after);
+            report.files_rewritten += 1;
+        }
+
+        log::info!(
+            "[gacha] compacted storage: {} duplicate + {} expired snapshot(s), {} file(s) rewritten, {} bytes reclaimed",
+            report.duplicates_removed,
+            report.expired_removed,
+            report.files_rewritten,
+            report.bytes_reclaimed
+        );
+        Ok(report)
+    }
+
     // ── Statistics ────────────────────────────────────────────────────────────
 
     pub fn compute_stats(data: &GachaData) -> GachaStatsResult {
@@ -313,9 +1106,25 @@ impl GachaManager {
                     six_star_rate: 0.0,
                     current_pity: 0,
                     avg_pity: 0.0,
+                    first_six_star: None,
+                    last_six_star: None,
+                    five_star_current_pity: 0,
+                    five_star_avg_pity: 0.0,
+                    five_star_rate: 0.0,
                 });
 
             pool.total_pulls += 1;
+            if record.rarity >= 6 {
+                let milestone = SixStarMilestone {
+                    item_name: record.item_name.clone(),
+                    timestamp: record.timestamp,
+                    pity: record.pity,
+                };
+                if pool.first_six_star.is_none() {
+                    pool.first_six_star = Some(milestone.clone());
+                }
+                pool.last_six_star = Some(milestone);
+            }
             match record.rarity {
                 6 => pool.six_star_count += 1,
                 5 => pool.five_star_count += 1,
@@ -332,23 +1141,19 @@ impl GachaManager {
                 .filter(|r| r.pool_type == pool_type)
                 .collect();
 
-            // Current pity: how many non-6★ pulls from the end
-            let current_pity = pool_records
-                .iter()
-                .rev()
-                .take_while(|r| r.rarity < 6)
-                .count() as u32;
+            // Current pity: the last record's running counter, unless it was a 6★.
+            // Driven by the stored `pity` so manual anchors are respected.
+            let current_pity = match pool_records.last() {
+                Some(r) if r.rarity < 6 => r.pity,
+                _ => 0,
+            };
 
             // Average pity: mean pulls per 6★
-            let mut six_star_pities: Vec<u32> = Vec::new();
-            let mut since_last: u32 = 0;
-            for r in &pool_records {
-                since_last += 1;
-                if r.rarity >= 6 {
-                    six_star_pities.push(since_last);
-                    since_last = 0;
-                }
-            }
+            let six_star_pities: Vec<u32> = pool_records
+                .iter()
+                .filter(|r| r.rarity >= 6)
+                .map(|r| r.pity)
+                .collect();
             let avg_pity = if six_star_pities.is_empty() {
                 0.0
             } else {
@@ -356,12 +1161,45 @@ impl GachaManager {
                 (sum as f64 / six_star_pities.len() as f64 * 10.0).round() / 10.0
             };
 
+            // 5★ dry streaks aren't stored on records; count them here.
+            let mut five_star_pity = 0u32;
+            let mut five_star_pities: Vec<u32> = Vec::new();
+            for r in &pool_records {
+                five_star_pity += 1;
+                if r.rarity == 5 {
+                    five_star_pities.push(five_star_pity);
+                    five_star_pity = 0;
+                }
+            }
+            let five_star_avg_pity = if five_star_pities.is_empty() {
+                0.0
+            } else {
+                let sum: u32 = five_star_pities.iter().sum();
+                (sum as f64 / five_star_pities.len() as f64 * 10.0).round() / 10.0
+            };
+
             if let Some(pool) = by_pool.get_mut(&pool_type) {
                 pool.six_star_rate =
-                    (pool.six_star_count as f64 / pool.total_pulls as f64 * 1000.0).round()
-                        / 10.0;
+                    (pool.six_star_count as f64 / pool.total_pulls as f64 * 1000.0).round() / 10.0;
                 pool.current_pity = current_pity;
                 pool.avg_pity = avg_pity;
+                pool.five_star_rate =
+                    (pool.five_star_count as f64 / pool.total_pulls as f64 * 1000.0).round() / 10.0;
+                pool.five_star_current_pity = five_star_pity;
+                pool.five_star_avg_pity = five_star_avg_pity;
+            }
+        }
+
+        let mut by_month: HashMap<String, MonthStats> = HashMap::new();
+        for record in &data.records {
+            let start = bucket_start(record.timestamp, TimeBucket::Month);
+            let month = by_month
+                .entry(bucket_label(start, TimeBucket::Month))
+                .or_default();
+            month.total_pulls += 1;
+            if record.rarity >= 6 {
+                month.six_star_count += 1;
+                month.six_star_items.push(record.item_name.clone());
             }
         }
 
@@ -369,35 +1207,251 @@ impl GachaManager {
             uid: data.uid.clone(),
             total_pulls: data.records.len() as u32,
             by_pool,
+            by_month,
             fetched_at: data.fetched_at,
         }
     }
 
+    /// Like `compute_stats`, but with user-supplied pity anchors applied first.
+    /// A pool whose anchor has no later records reports the anchor's pity.
+    pub fn compute_stats_with_anchors(
+        data: &GachaData,
+        anchors: &[PityAnchor],
+    ) -> GachaStatsResult {
+        let mut data = data.clone();
+        Self::apply_pity_anchors(&mut data, anchors);
+        let mut stats = Self::compute_stats(&data);
+
+        for anchor in matching_anchors(&data, anchors) {
+            let has_later = data
+                .records
+                .iter()
+                .any(|r| r.pool_type == anchor.pool_type && r.timestamp > anchor.as_of_ts);
+            if !has_later {
+                if let Some(pool) = stats.by_pool.get_mut(&anchor.pool_type) {
+                    pool.current_pity = anchor.known_pity;
+                }
+            }
+        }
+        stats
+    }
+
+    /// Recount `pity` for records after each anchor's `as_of_ts`, starting
+    /// from the user's known pity instead of the (possibly incomplete) history.
+    pub fn apply_pity_anchors(data: &mut GachaData, anchors: &[PityAnchor]) {
+        let anchors: Vec<PityAnchor> = matching_anchors(data, anchors).cloned().collect();
+        for anchor in anchors {
+            let mut counter = anchor.known_pity;
+            for r in data
+                .records
+                .iter_mut()
+                .filter(|r| r.pool_type == anchor.pool_type && r.timestamp > anchor.as_of_ts)
+            {
+                counter += 1;
+                r.pity = counter;
+                if r.rarity >= 6 {
+                    counter = 0;
+                }
+            }
+        }
+    }
+
+    /// Summarise stored data for each of `game_ids`, skipping games with none.
+    pub fn summarize_games(&self, game_ids: &[&str]) -> Vec<GameGachaSummary> {
+        game_ids
+            .iter()
+            .filter_map(|game_id| self.load_data(game_id))
+            .map(|data| {
+                let stats = Self::compute_stats(&data);
+                let six_star_count: u32 = stats.by_pool.values().map(|p| p.six_star_count).sum();
+                let six_star_rate = if stats.total_pulls == 0 {
+                    0.0
+                } else {
+                    (six_star_count as f64 / stats.total_pulls as f64 * 1000.0).round() / 10.0
+                };
+                GameGachaSummary {
+                    game_id: data.game_id,
+                    uid: stats.uid,
+                    total_pulls: stats.total_pulls,
+                    six_star_count,
+                    six_star_rate,
+                    fetched_at: stats.fetched_at,
+                }
+            })
+            .collect()
+    }
+
+    /// Group a UID's records into weekly/monthly buckets with per-bucket 6★ rate,
+    /// returned in chronological order for charting.
+    pub fn rate_timeline(data: &GachaData, uid: &str, bucket: TimeBucket) -> Vec<RateBucket> {
+        let mut buckets: BTreeMap<i64, RateBucket> = BTreeMap::new();
+
+        for record in data.records.iter().filter(|r| r.uid == uid) {
+            let start_ts = bucket_start(record.timestamp, bucket);
+            let entry = buckets.entry(start_ts).or_insert_with(|| RateBucket {
+                label: bucket_label(start_ts, bucket),
+                start_ts,
+                total_pulls: 0,
+                six_star_count: 0,
+                six_star_rate: 0.0,
+            });
+            entry.total_pulls += 1;
+            if record.rarity >= 6 {
+                entry.six_star_count += 1;
+            }
+        }
+
+        buckets
+            .into_values()
+            .map(|mut b| {
+                b.six_star_rate =
+                    (b.six_star_count as f64 / b.total_pulls as f64 * 1000.0).round() / 10.0;
+                b
+            })
+            .collect()
+    }
+
+    /// Count ten-pulls versus single pulls per pool type for one UID.
+    ///
+    /// Records are grouped by `batch_id`; older data without it falls back to
+    /// grouping by pool name and timestamp, which the API shares within a batch.
+    pub fn pull_breakdown(data: &GachaData, uid: &str) -> PullBreakdown {
+        let mut batches: HashMap<(String, String, i64, Option<u32>), u32> = HashMap::new();
+        for record in data.records.iter().filter(|r| r.uid == uid) {
+            let key = match record.batch_id {
+                Some(id) => (record.pool_type.clone(), String::new(), 0, Some(id)),
+                None => (
+                    record.pool_type.clone(),
+                    record.pool_name.clone(),
+                    record.timestamp,
+                    None,
+                ),
+            };
+            *batches.entry(key).or_insert(0) += 1;
+        }
+
+        let mut result = PullBreakdown {
+            uid: uid.to_string(),
+            ten_pulls: 0,
+            single_pulls: 0,
+            other_pulls: 0,
+            by_pool: HashMap::new(),
+        };
+        for ((pool_type, ..), size) in batches {
+            let pool =
+                result
+                    .by_pool
+                    .entry(pool_type.clone())
+                    .or_insert_with(|| PoolPullBreakdown {
+                        pool_type,
+                        ..Default::default()
+                    });
+            match size {
+                10 => {
+                    pool.ten_pulls += 1;
+                    result.ten_pulls += 1;
+                }
+                1 => {
+                    pool.single_pulls += 1;
+                    result.single_pulls += 1;
+                }
+                n => {
+                    pool.other_pulls += n;
+                    result.other_pulls += n;
+                }
+            }
+            pool.total_pulls += size;
+        }
+        result
+    }
+
     // ── Export ────────────────────────────────────────────────────────────────
 
+    /// Records passing `filter`, in their stored order. Every export format
+    /// goes through here.
+    pub fn filter_records(records: &[GachaRecord], filter: &ExportFilter) -> Vec<GachaRecord> {
+        records
+            .iter()
+            .filter(|r| filter.matches(r))
+            .cloned()
+            .collect()
+    }
+
+    /// Export in `format` ("json" | "csv" | "xlsx" | "markdown").
+    pub fn export_as(records: &[GachaRecord], format: &str, dest_path: &str) -> Result<()> {
+        match format {
+            "json" => Self::export_json(records, dest_path),
+            "csv" => Self::export_csv(records, dest_path),
+            "markdown" => Self::export_markdown(records, dest_path),
+            "xlsx" => Self::export_xlsx(records, dest_path),
+            _ => Err(anyhow!("不支持的导出格式：{format}")),
+        }
+    }
+
     pub fn export_json(records: &[GachaRecord], dest_path: &str) -> Result<()> {
         std::fs::write(dest_path, serde_json::to_string_pretty(records)?)?;
         Ok(())
     }
 
     pub fn export_csv(records: &[GachaRecord], dest_path: &str) -> Result<()> {
-        let mut out =
-            String::from("\u{FEFF}时间,卡池,卡池类型,干员/物品,稀有度,是否新干员,水位\n");
-        for r in records {
-            let dt = format_ts(r.timestamp);
-            let pool_cn = pool_type_cn(&r.pool_type);
-            out.push_str(&format!(
-                "{},{},{},{},{},{},{}\n",
-                dt,
-                csv_escape(&r.pool_name),
-                pool_cn,
-                csv_escape(&r.item_name),
-                r.rarity,
-                if r.is_new { "是" } else { "否" },
-                r.pity,
-            ));
+        std::fs::write(dest_path, csv_report(records).as_bytes())?;
+        Ok(())
+    }
+
+    pub fn export_markdown(records: &[GachaRecord], dest_path: &str) -> Result<()> {
+        std::fs::write(dest_path, markdown_report(records))?;
+        Ok(())
+    }
+
+    /// The "markdown" export as a string, for the clipboard.
+    pub fn to_markdown(records: &[GachaRecord]) -> String {
+        markdown_report(records)
+    }
+
+    /// "完整导出": one zip with the uid's records (JSON and CSV), the computed
+    /// stats and a self-contained HTML report.
+    pub fn export_bundle(data: &GachaData, uid: &str, dest_path: &str) -> Result<()> {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let data = GachaData {
+            uid: uid.to_string(),
+            game_id: data.game_id.clone(),
+            records: data
+                .records
+                .iter()
+                .filter(|r| r.uid == uid)
+                .cloned()
+                .collect(),
+            fetched_at: data.fetched_at,
+        };
+        if data.records.is_empty() {
+            return Err(anyhow!("UID {} 没有可导出的记录", uid));
         }
-        std::fs::write(dest_path, out.as_bytes())?;
+        let stats = Self::compute_stats(&data);
+
+        let files: [(&str, Vec<u8>); 4] = [
+            ("records.json", serde_json::to_vec_pretty(&data)?),
+            ("records.csv", csv_report(&data.records).into_bytes()),
+            ("stats.json", serde_json::to_vec_pretty(&stats)?),
+            ("report.html", html_report(&data, &stats).into_bytes()),
+        ];
+
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(dest_path)?);
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, bytes) in files {
+            zip.start_file(name, options)?;
+            zip.write_all(&bytes)?;
+        }
+        zip.finish()?;
+        log::info!(
+            "[gacha] exported bundle for {} uid {} ({} records) to {}",
+            data.game_id,
+            uid,
+            data.records.len(),
+            dest_path
+        );
         Ok(())
     }
 
@@ -440,6 +1494,65 @@ impl GachaManager {
 
 // ─── Private helpers ─────────────────────────────────────────────────────────
 
+/// Upper bound on records kept in memory during one fetch.
+const MAX_FETCH_RECORDS: usize = 200_000;
+
+/// Pause between pages.
+const PAGE_DELAY: std::time::Duration = std::time::Duration::from_millis(300);
+/// Pause between pages once the API has rate-limited this fetch.
+const RATE_LIMITED_PAGE_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+/// Retries of one rate-limited page, waiting RATE_LIMIT_BACKOFF and doubling.
+const RATE_LIMIT_RETRIES: u32 = 4;
+const RATE_LIMIT_BACKOFF: std::time::Duration = std::time::Duration::from_secs(2);
+const RATE_LIMIT_MAX_WAIT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// The inquiry API asked us to slow down.
+#[derive(Debug)]
+struct RateLimited {
+    message: String,
+    /// From a `Retry-After` header, when the server sent one.
+    retry_after: Option<std::time::Duration>,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// The inquiry API reports throttling in the envelope (HTTP 200) with a
+/// non-zero code; the code isn't documented, so match it by message as well
+/// as the 429 that some gateways pass through.
+fn is_rate_limit_reply(code: i64, msg: &str) -> bool {
+    let msg = msg.to_lowercase();
+    code == 429
+        || ["频繁", "稍后", "too many", "rate limit", "frequent"]
+            .iter()
+            .any(|k| msg.contains(k))
+}
+
+/// Tolerated gap between server record timestamps and the local clock.
+const CLOCK_SKEW_THRESHOLD_SECS: i64 = 300;
+
+/// A pull cannot happen in the future, so a newest record ahead of "now" means
+/// the local clock is behind. (A clock running ahead is indistinguishable from
+/// simply not having pulled recently and is not reported.)
+fn clock_skew(newest_ts: i64) -> Option<i64> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let skew = newest_ts - now;
+    if skew > CLOCK_SKEW_THRESHOLD_SECS {
+        log::warn!("[gacha] newest record is {skew}s ahead of the local clock");
+        Some(skew)
+    } else {
+        None
+    }
+}
+
 #[derive(Deserialize)]
 struct RawChar {
     name: String,
@@ -456,56 +1569,275 @@ struct RawEntry {
     chars: Vec<RawChar>,
 }
 
-fn build_records_with_pity(
-    uid: &str,
-    game_id: &str,
-    mut entries: Vec<RawEntry>,
-) -> Vec<GachaRecord> {
-    // Entries come back newest-first; reverse for chronological order
-    entries.reverse();
+impl RawEntry {
+    /// One `{ts, pool, chars}` object as returned by the API (and official exports).
+    fn from_json(entry: &serde_json::Value) -> Self {
+        let ts = entry.get("ts").and_then(|t| t.as_i64()).unwrap_or(0);
+        let pool = entry
+            .get("pool")
+            .and_then(|p| p.as_str())
+            .unwrap_or("")
+            .to_string();
+        let chars: Vec<RawChar> = entry
+            .get("chars")
+            .and_then(|c| serde_json::from_value(c.clone()).ok())
+            .unwrap_or_default();
+        RawEntry { ts, pool, chars }
+    }
+}
 
-    let mut pity_counter: HashMap<String, u32> = HashMap::new();
-    let mut records = Vec::new();
-    let mut global_idx: u32 = 0;
+/// Append one API entry's records in newest-first order, leaving the
+/// order-dependent fields for `finalize_records`.
+fn push_entry_records(records: &mut Vec<GachaRecord>, game_id: &str, entry: RawEntry, batch: u32) {
+    let pool_type = classify_pool(game_id, &entry.pool);
 
-    for entry in entries {
-        let pool_type = classify_pool(game_id, &entry.pool).to_string();
+    // Reversed so that flipping the whole list later restores in-batch order.
+    for ch in entry.chars.into_iter().rev() {
+        let rarity = ch.rarity.saturating_add(1).min(6); // API is 0-indexed (5 = 6★)
 
-        for ch in entry.chars {
-            let counter = pity_counter.entry(pool_type.clone()).or_insert(0);
-            *counter += 1;
-            let pity = *counter;
-            let rarity = ch.rarity.saturating_add(1).min(6); // API is 0-indexed (5 = 6★)
+        let item_type = if ch.item_type.eq_ignore_ascii_case("CHAR") {
+            "character"
+        } else {
+            "weapon"
+        };
 
-            let item_type = if ch.item_type.eq_ignore_ascii_case("CHAR") {
-                "character"
-            } else {
-                "weapon"
-            };
+        records.push(GachaRecord {
+            id: String::new(),
+            uid: String::new(),
+            game_id: game_id.to_string(),
+            pool_name: entry.pool.clone(),
+            pool_type: pool_type.to_string(),
+            item_name: ch.name,
+            item_type: item_type.to_string(),
+            rarity,
+            timestamp: entry.ts,
+            is_new: ch.is_new,
+            pity: 0,
+            batch_id: Some(batch),
+        });
+    }
+}
 
-            records.push(GachaRecord {
-                id: format!("{uid}_{global_idx}"),
-                uid: uid.to_string(),
-                game_id: game_id.to_string(),
-                pool_name: entry.pool.clone(),
-                pool_type: pool_type.clone(),
-                item_name: ch.name,
-                item_type: item_type.to_string(),
-                rarity,
-                timestamp: entry.ts,
-                is_new: ch.is_new,
-                pity,
-            });
+/// Attempts made by `retry_locked`, with doubling delays starting at 50 ms.
+const LOCK_RETRIES: u32 = 4;
+
+/// Run a file operation, retrying briefly while the file is locked by another
+/// process (antivirus, sync tools or an overlapping save on Windows).
+fn retry_locked<T>(
+    what: &str,
+    path: &Path,
+    mut op: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut delay = std::time::Duration::from_millis(50);
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if attempt < LOCK_RETRIES && is_lock_error(&e) => {
+                log::warn!(
+                    "[gacha] {} {} failed ({}), retry {}/{}",
+                    what,
+                    path.display(),
+                    e,
+                    attempt,
+                    LOCK_RETRIES - 1
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
 
-            global_idx += 1;
+/// Sharing/lock violations (Windows error 32/33) and the access-denied error
+/// Windows reports for files pending deletion or replacement.
+fn is_lock_error(e: &std::io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(32) | Some(33) if cfg!(windows))
+        || e.kind() == std::io::ErrorKind::PermissionDenied
+}
 
-            if rarity >= 6 {
-                *pity_counter.get_mut(&pool_type).unwrap() = 0;
-            }
+/// Group key of a snapshot file: its name without the trailing `_{timestamp}`.
+fn snapshot_key(path: &Path) -> Option<String> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let (key, _ts) = stem.rsplit_once('_')?;
+    Some(key.to_string())
+}
+
+/// Fill an empty `data.uid` from the first record that has one, and stamp it
+/// onto records missing it. Returns false when no record carries a uid.
+fn backfill_uid(data: &mut GachaData) -> bool {
+    let Some(uid) = data
+        .records
+        .iter()
+        .find(|r| !r.uid.is_empty())
+        .map(|r| r.uid.clone())
+    else {
+        return false;
+    };
+    for record in data.records.iter_mut().filter(|r| r.uid.is_empty()) {
+        // Ids were built as "{uid}_{idx}" with the empty uid.
+        record.id = format!("{uid}{}", record.id);
+        record.uid = uid.clone();
+    }
+    data.uid = uid;
+    true
+}
+
+/// Records of a UIGF-like `{info, list}` file; see `GachaManager::import_json`.
+fn parse_interchange(game_id: &str, root: &serde_json::Value) -> Result<GachaData> {
+    let info = &root["info"];
+    if let Some(other) = info["game_id"].as_str().filter(|g| *g != game_id) {
+        return Err(anyhow!(
+            "导入文件属于 {}，与当前游戏 {} 不符",
+            other,
+            game_id
+        ));
+    }
+    let uid = match &info["uid"] {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Number(n) => n.to_string(),
+        _ => String::new(),
+    };
+    let tz_hours = info["region_time_zone"].as_i64().unwrap_or(8);
+    let list = root["list"]
+        .as_array()
+        .ok_or_else(|| anyhow!("list 字段不是数组"))?;
+
+    let field = |item: &serde_json::Value, keys: &[&str]| -> Option<String> {
+        keys.iter().find_map(|k| match &item[*k] {
+            serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        })
+    };
+
+    let mut records = Vec::with_capacity(list.len());
+    for (i, item) in list.iter().enumerate() {
+        let timestamp = match field(item, &["timestamp", "ts"]) {
+            Some(ts) => ts.parse::<i64>().ok(),
+            None => field(item, &["time"]).and_then(|t| parse_datetime(&t, tz_hours)),
         }
+        .ok_or_else(|| anyhow!("第 {} 条记录缺少有效时间", i + 1))?;
+        let item_name = field(item, &["item_name", "name", "itemName"])
+            .ok_or_else(|| anyhow!("第 {} 条记录缺少物品名称", i + 1))?;
+        let pool_name =
+            field(item, &["pool_name", "gacha_type", "pool", "poolName"]).unwrap_or_default();
+        let rarity = field(item, &["rank", "rank_type", "rarity"])
+            .and_then(|r| r.parse::<u8>().ok())
+            .unwrap_or(3)
+            .clamp(1, 6);
+        let pool_type = field(item, &["pool_type", "poolType"])
+            .unwrap_or_else(|| classify_pool(game_id, &pool_name).to_string());
+        let item_type = field(item, &["item_type", "itemType"])
+            .map(|t| match t.to_lowercase().as_str() {
+                "char" | "character" | "角色" | "干员" => "character".to_string(),
+                _ => "weapon".to_string(),
+            })
+            .unwrap_or_else(|| "character".to_string());
+        let is_new = item["is_new"]
+            .as_bool()
+            .or_else(|| item["isNew"].as_bool())
+            .unwrap_or(false);
+
+        records.push(GachaRecord {
+            id: String::new(),
+            uid: uid.clone(),
+            game_id: game_id.to_string(),
+            pool_name,
+            pool_type,
+            item_name,
+            item_type,
+            rarity,
+            timestamp,
+            is_new,
+            pity: 0,
+            batch_id: None,
+        });
     }
 
-    records
+    let fetched_at = records.iter().map(|r| r.timestamp).max().unwrap_or(0);
+    Ok(GachaData {
+        uid,
+        game_id: game_id.to_string(),
+        records,
+        fetched_at,
+    })
+}
+
+/// Sort records chronologically (stable, so in-batch order is kept) and
+/// recompute ids, batch ids (one per distinct time + pool) and pity.
+fn renumber_records(uid: &str, records: &mut [GachaRecord]) {
+    records.sort_by_key(|r| r.timestamp);
+    let mut pity_counter: HashMap<String, u32> = HashMap::new();
+    let mut batch = 0u32;
+    for i in 0..records.len() {
+        if i > 0
+            && (records[i].timestamp != records[i - 1].timestamp
+                || records[i].pool_name != records[i - 1].pool_name)
+        {
+            batch += 1;
+        }
+        let record = &mut records[i];
+        record.id = format!("{uid}_{i}");
+        record.uid = uid.to_string();
+        record.batch_id = Some(batch);
+
+        let counter = pity_counter.entry(record.pool_type.clone()).or_insert(0);
+        *counter += 1;
+        record.pity = *counter;
+        if record.rarity >= 6 {
+            *counter = 0;
+        }
+    }
+}
+
+/// "YYYY-MM-DD HH:MM[:SS]" (or with a `T`) at UTC+`tz_hours` → Unix seconds.
+fn parse_datetime(s: &str, tz_hours: i64) -> Option<i64> {
+    let s = s.trim();
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())?;
+    let offset = FixedOffset::east_opt(i32::try_from(tz_hours * 3600).ok()?)?;
+    offset
+        .from_local_datetime(&naive)
+        .single()
+        .map(|d| d.timestamp())
+}
+
+/// Put newest-first records into chronological order and assign ids, batch
+/// ids and pity (pulls since last 6★ in the same pool type).
+fn finalize_records(uid: &str, records: &mut [GachaRecord], batches: u32) {
+    records.reverse();
+
+    let mut pity_counter: HashMap<String, u32> = HashMap::new();
+
+    for (global_idx, record) in records.iter_mut().enumerate() {
+        record.id = format!("{uid}_{global_idx}");
+        record.uid = uid.to_string();
+        record.batch_id = record.batch_id.map(|b| batches - 1 - b);
+
+        let counter = pity_counter.entry(record.pool_type.clone()).or_insert(0);
+        *counter += 1;
+        record.pity = *counter;
+        if record.rarity >= 6 {
+            *counter = 0;
+        }
+    }
+}
+
+fn matching_anchors<'a>(
+    data: &GachaData,
+    anchors: &'a [PityAnchor],
+) -> impl Iterator<Item = &'a PityAnchor> {
+    let game_id = data.game_id.clone();
+    let uid = data.uid.clone();
+    anchors
+        .iter()
+        .filter(move |a| a.game_id == game_id && a.uid == uid)
 }
 
 fn classify_pool(game_id: &str, pool_name: &str) -> &'static str {
@@ -535,6 +1867,40 @@ fn classify_pool(game_id: &str, pool_name: &str) -> &'static str {
     }
 }
 
+/// Reject scanned URLs that are truncated or padded with binary garbage:
+/// they must parse, use https, point at the game's inquiry host, carry a
+/// query string, and contain no control or replacement characters.
+fn is_valid_gacha_url(url: &str, url_pattern: &str) -> bool {
+    if url
+        .chars()
+        .any(|c| c.is_control() || c == char::REPLACEMENT_CHARACTER)
+    {
+        return false;
+    }
+    let expected_host = url_pattern.split('/').next().unwrap_or_default();
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    parsed.scheme() == "https"
+        && parsed.host_str() == Some(expected_host)
+        && parsed.query().is_some_and(|q| !q.is_empty())
+}
+
+/// Grant tokens are well over this length; anything shorter was cut off.
+const MIN_TOKEN_LEN: usize = 16;
+
+/// A token that was clipped while copying: too short, elided, or ending in
+/// the middle of a percent-escape.
+fn is_truncated_token(token: &str) -> bool {
+    let bytes = token.as_bytes();
+    let n = bytes.len();
+    token.len() < MIN_TOKEN_LEN
+        || token.contains("...")
+        || token.contains('…')
+        || (n >= 1 && bytes[n - 1] == b'%')
+        || (n >= 2 && bytes[n - 2] == b'%')
+}
+
 fn build_page_url(base_url: &str, seq_num: i64, size: u32) -> String {
     let sep = if base_url.contains('?') { "&" } else { "?" };
     if seq_num == 0 {
@@ -556,48 +1922,154 @@ fn extract_query_param(url: &str, param: &str) -> Option<String> {
     None
 }
 
+/// "YYYY-MM-DD HH:MM:SS" in the local timezone, as the player saw it in game.
 fn format_ts(ts: i64) -> String {
+    format_ts_in(ts, &Local)
+}
+
+fn format_ts_in<Tz: TimeZone>(ts: i64, tz: &Tz) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
     if ts <= 0 {
         return String::from("1970-01-01 00:00:00");
     }
-    let secs = ts as u64;
-    let time_of_day = secs % 86400;
-    let days = secs / 86400;
-    let h = time_of_day / 3600;
-    let m = (time_of_day % 3600) / 60;
-    let s = time_of_day % 60;
-    let (year, month, day) = days_to_ymd(days);
-    format!("{year:04}-{month:02}-{day:02} {h:02}:{m:02}:{s:02}")
+    tz.timestamp_opt(ts, 0)
+        .earliest()
+        .map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
 }
 
-fn days_to_ymd(mut days: u64) -> (u64, u64, u64) {
-    let mut year = 1970u64;
-    loop {
-        let days_in_year = if is_leap(year) { 366 } else { 365 };
-        if days < days_in_year {
-            break;
-        }
-        days -= days_in_year;
-        year += 1;
-    }
-    let month_days: [u64; 12] = if is_leap(year) {
-        [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
-    } else {
-        [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
+/// Start of the local week (Monday) or month containing `ts`.
+fn bucket_start(ts: i64, bucket: TimeBucket) -> i64 {
+    bucket_start_in(ts, bucket, &Local)
+}
+
+fn bucket_start_in<Tz: TimeZone>(ts: i64, bucket: TimeBucket, tz: &Tz) -> i64 {
+    let Some(local) = tz.timestamp_opt(ts.max(0), 0).earliest() else {
+        return ts;
     };
-    let mut month = 1u64;
-    for dm in &month_days {
-        if days < *dm {
-            break;
-        }
-        days -= dm;
-        month += 1;
+    let date = local.date_naive();
+    let start = match bucket {
+        TimeBucket::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+        TimeBucket::Month => date.with_day(1).unwrap_or(date),
+    };
+    // Midnight can fall in a DST gap; take the first hour that exists.
+    (0..3)
+        .find_map(|h| {
+            tz.from_local_datetime(&start.and_hms_opt(h, 0, 0)?)
+                .earliest()
+        })
+        .map(|d| d.timestamp())
+        .unwrap_or(ts)
+}
+
+fn bucket_label(start_ts: i64, bucket: TimeBucket) -> String {
+    let Some(start) = Local.timestamp_opt(start_ts, 0).earliest() else {
+        return String::new();
+    };
+    match bucket {
+        TimeBucket::Week => start.format("%Y-%m-%d").to_string(),
+        TimeBucket::Month => start.format("%Y-%m").to_string(),
+    }
+}
+
+fn csv_report(records: &[GachaRecord]) -> String {
+    let mut out = String::from("\u{FEFF}时间,卡池,卡池类型,干员/物品,稀有度,是否新干员,水位\n");
+    for r in records {
+        let dt = format_ts(r.timestamp);
+        let pool_cn = pool_type_cn(&r.pool_type);
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            dt,
+            csv_escape(&r.pool_name),
+            pool_cn,
+            csv_escape(&r.item_name),
+            r.rarity,
+            if r.is_new { "是" } else { "否" },
+            r.pity,
+        ));
+    }
+    out
+}
+
+/// Markdown table for pasting into chat (Discord, forums).
+fn markdown_report(records: &[GachaRecord]) -> String {
+    let mut out =
+        String::from("| 时间 | 卡池 | 干员/物品 | 稀有度 | 水位 |\n|---|---|---|---|---|\n");
+    for r in records {
+        out.push_str(&format!(
+            "| {} | {}（{}） | {} | {}★ | {} |\n",
+            format_ts(r.timestamp),
+            markdown_escape(&r.pool_name),
+            pool_type_cn(&r.pool_type),
+            markdown_escape(&r.item_name),
+            r.rarity,
+            r.pity,
+        ));
+    }
+    out
+}
+
+/// Readable summary: per-pool stats and every 6★ with its pity.
+fn html_report(data: &GachaData, stats: &GachaStatsResult) -> String {
+    let mut pools: Vec<&PoolStats> = stats.by_pool.values().collect();
+    pools.sort_by(|a, b| a.pool_type.cmp(&b.pool_type));
+
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html lang=\"zh-CN\"><head><meta charset=\"utf-8\">\
+         <title>抽卡记录</title><style>\
+         body{font-family:sans-serif;margin:2em}table{border-collapse:collapse;margin-bottom:2em}\
+         td,th{border:1px solid #ccc;padding:4px 10px;text-align:left}\
+         </style></head><body>\n",
+    );
+    out.push_str(&format!(
+        "<h1>{} 抽卡记录</h1>\n<p>UID {} · 共 {} 抽 · 更新于 {}</p>\n",
+        html_escape(&data.game_id),
+        html_escape(&stats.uid),
+        stats.total_pulls,
+        format_ts(stats.fetched_at)
+    ));
+
+    out.push_str(
+        "<table><tr><th>卡池类型</th><th>抽数</th><th>六星</th><th>五星</th>\
+         <th>六星率</th><th>平均出货</th><th>当前水位</th></tr>\n",
+    );
+    for p in pools {
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}%</td><td>{}</td><td>{}</td></tr>\n",
+            pool_type_cn(&p.pool_type),
+            p.total_pulls,
+            p.six_star_count,
+            p.five_star_count,
+            p.six_star_rate,
+            p.avg_pity,
+            p.current_pity
+        ));
     }
-    (year, month, days + 1)
+    out.push_str("</table>\n");
+
+    out.push_str(
+        "<h2>六星记录</h2>\n<table><tr><th>时间</th><th>卡池</th><th>干员</th><th>水位</th></tr>\n",
+    );
+    for r in data.records.iter().rev().filter(|r| r.rarity == 6) {
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            format_ts(r.timestamp),
+            html_escape(&r.pool_name),
+            html_escape(&r.item_name),
+            r.pity
+        ));
+    }
+    out.push_str("</table>\n</body></html>\n");
+    out
 }
 
-fn is_leap(y: u64) -> bool {
-    (y % 4 == 0 && y % 100 != 0) || (y % 400 == 0)
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
 fn csv_escape(s: &str) -> String {
@@ -608,6 +2080,11 @@ fn csv_escape(s: &str) -> String {
     }
 }
 
+/// Keep names from breaking out of a table cell.
+fn markdown_escape(s: &str) -> String {
+    s.replace('|', "\\|").replace(['\n', '\r'], " ")
+}
+
 fn pool_type_cn(pool_type: &str) -> &'static str {
     match pool_type {
         "standard" => "标准",
@@ -617,3 +2094,189 @@ fn pool_type_cn(pool_type: &str) -> &'static str {
         _ => "其他",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Chronological records with `rarities`, pity assigned like
+    /// `finalize_records`.
+    fn records(pool_type: &str, rarities: &[u8]) -> Vec<GachaRecord> {
+        let mut pity = 0;
+        rarities
+            .iter()
+            .enumerate()
+            .map(|(i, &rarity)| {
+                pity += 1;
+                let record = GachaRecord {
+                    id: format!("{pool_type}_{i}"),
+                    uid: "1".into(),
+                    game_id: "arknights".into(),
+                    pool_name: pool_type.into(),
+                    pool_type: pool_type.into(),
+                    item_name: format!("item{i}"),
+                    item_type: "character".into(),
+                    rarity,
+                    timestamp: 1_700_000_000 + i as i64,
+                    is_new: false,
+                    pity,
+                    batch_id: None,
+                };
+                if rarity >= 6 {
+                    pity = 0;
+                }
+                record
+            })
+            .collect()
+    }
+
+    fn data(records: Vec<GachaRecord>) -> GachaData {
+        GachaData {
+            uid: "1".into(),
+            game_id: "arknights".into(),
+            records,
+            fetched_at: 0,
+        }
+    }
+
+    #[test]
+    fn five_and_six_star_pity_reset_independently() {
+        let stats =
+            GachaManager::compute_stats(&data(records("limited", &[3, 5, 4, 6, 3, 3, 5, 3, 6, 4])));
+        let pool = &stats.by_pool["limited"];
+
+        // 6★ at pulls 4 and 9 (pity 4, 5); the 5★s don't reset it.
+        assert_eq!(pool.current_pity, 1);
+        assert_eq!(pool.avg_pity, 4.5);
+        assert_eq!(pool.six_star_rate, 20.0);
+        // 5★ after 2 and 5 pulls; the 6★s don't reset it.
+        assert_eq!(pool.five_star_current_pity, 3);
+        assert_eq!(pool.five_star_avg_pity, 3.5);
+        assert_eq!(pool.five_star_rate, 20.0);
+    }
+
+    #[test]
+    fn five_star_pity_is_per_pool() {
+        let mut all = records("limited", &[3, 3, 5, 3]);
+        all.extend(records("standard", &[3, 3, 3]));
+        let stats = GachaManager::compute_stats(&data(all));
+
+        assert_eq!(stats.by_pool["limited"].five_star_current_pity, 1);
+        assert_eq!(stats.by_pool["standard"].five_star_current_pity, 3);
+        assert_eq!(stats.by_pool["standard"].five_star_avg_pity, 0.0);
+    }
+
+    // ─── Import ──────────────────────────────────────────────────────────────
+
+    fn write_json(dir: &Path, value: serde_json::Value) -> String {
+        let path = dir.join("export.json");
+        std::fs::write(&path, value.to_string()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn official_export_parses_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = write_json(
+            tmp.path(),
+            serde_json::json!({ "uid": "42", "list": [{
+                "ts": 1_700_000_000,
+                "pool": "常驻标准寻访",
+                "chars": [{ "name": "能天使", "type": "CHAR", "rarity": 5, "isNew": true }]
+            }]}),
+        );
+
+        let data = GachaManager::import_official_export("arknights", &path).unwrap();
+
+        assert_eq!(data.uid, "42");
+        assert_eq!(data.records.len(), 1);
+        assert_eq!(data.records[0].rarity, 6);
+        assert_eq!(data.records[0].timestamp, 1_700_000_000);
+    }
+
+    #[test]
+    fn official_export_rejects_other_list_formats() {
+        let tmp = tempfile::tempdir().unwrap();
+        for value in [
+            serde_json::json!({
+                "info": { "uid": "42" },
+                "list": [{ "item_name": "能天使", "rank": 6, "time": "2024-01-01 12:00:00" }]
+            }),
+            serde_json::json!({ "list": [{ "foo": 1 }] }),
+            serde_json::json!({ "list": [] }),
+        ] {
+            let path = write_json(tmp.path(), value);
+
+            let err = GachaManager::import_official_export("arknights", &path).unwrap_err();
+
+            assert_eq!(err.to_string(), "无法识别的导出格式");
+        }
+    }
+
+    #[test]
+    fn parse_import_file_reads_interchange_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = write_json(
+            tmp.path(),
+            serde_json::json!({
+                "info": { "uid": "42", "region_time_zone": 8 },
+                "list": [{
+                    "pool_name": "常驻标准寻访",
+                    "item_name": "能天使",
+                    "rank": 6,
+                    "time": "2024-01-01 12:00:00"
+                }]
+            }),
+        );
+
+        let data = GachaManager::parse_import_file("arknights", &path).unwrap();
+
+        assert_eq!(data.uid, "42");
+        assert_eq!(data.records.len(), 1);
+        assert_eq!(data.records[0].item_name, "能天使");
+        assert_eq!(data.records[0].timestamp, 1_704_081_600);
+    }
+
+    // ─── Time formatting ─────────────────────────────────────────────────────
+
+    /// 2023-11-14 22:13:20 UTC, a Tuesday.
+    const TS: i64 = 1_700_000_000;
+
+    fn utc8() -> FixedOffset {
+        FixedOffset::east_opt(8 * 3600).unwrap()
+    }
+
+    #[test]
+    fn format_ts_uses_the_given_timezone() {
+        assert_eq!(format_ts_in(TS, &chrono::Utc), "2023-11-14 22:13:20");
+        assert_eq!(format_ts_in(TS, &utc8()), "2023-11-15 06:13:20");
+        assert_eq!(format_ts_in(0, &utc8()), "1970-01-01 00:00:00");
+    }
+
+    #[test]
+    fn format_ts_matches_local_offset() {
+        let offset = *Local.timestamp_opt(TS, 0).unwrap().offset();
+        assert_eq!(format_ts(TS), format_ts_in(TS, &offset));
+    }
+
+    #[test]
+    fn bucket_start_uses_local_calendar() {
+        let tz = utc8();
+        let label = |ts| format_ts_in(ts, &tz);
+
+        // Already Wednesday in UTC+8, so the week starts Monday the 13th.
+        assert_eq!(
+            label(bucket_start_in(TS, TimeBucket::Week, &tz)),
+            "2023-11-13 00:00:00"
+        );
+        assert_eq!(
+            label(bucket_start_in(TS, TimeBucket::Month, &tz)),
+            "2023-11-01 00:00:00"
+        );
+        // 2023-11-30 20:00 UTC is already December in UTC+8.
+        assert_eq!(
+            label(bucket_start_in(1_701_374_400, TimeBucket::Month, &tz)),
+            "2023-12-01 00:00:00"
+        );
+    }
+}
@@ -1,3 +1,10 @@
 pub mod auth;
+pub mod estimate;
 pub mod manager;
-pub use manager::{GachaData, GachaManager, GachaRecord, GachaStatsResult, PoolStats};
+pub use estimate::{PullEstimate, SimulationResult};
+pub use manager::{
+    CompactReport, ExportFilter, FetchedRecords, GachaData, GachaManager, GachaRecord,
+    GachaStatsResult, GachaUrlCheck, GameGachaSummary, ImportReport, ItemSummary, MonthStats,
+    PoolPullBreakdown, PoolStats, PullBreakdown, RateBucket, SixStarEntry, SixStarMilestone,
+    TimeBucket, SNAPSHOTS_DIR,
+};
@@ -0,0 +1,127 @@
+use serde::Serialize;
+
+use super::{hypergryph, manager, GAME_IDS};
+use crate::gacha::auth;
+
+// ─── Types ────────────────────────────────────────────────────────────────────
+
+/// Result of probing one Hypergryph endpoint for the response shape we parse.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointCompat {
+    /// e.g. "arknights:get_latest"
+    pub endpoint: String,
+    pub url: String,
+    /// The endpoint answered with parseable JSON.
+    pub reachable: bool,
+    /// All fields we depend on were present.
+    pub compatible: bool,
+    /// Dotted paths (e.g. "data.pagination") that were expected but absent.
+    pub missing_fields: Vec<String>,
+    pub error: Option<String>,
+}
+
+// ─── Self-check ───────────────────────────────────────────────────────────────
+
+/// Hit every known endpoint and check that the top-level fields our parsers
+/// rely on are still there. Distinguishes "the app is broken" from
+/// "Hypergryph changed their API".
+pub async fn check_api_compat(client: &reqwest::Client) -> Vec<EndpointCompat> {
+    let mut report = Vec::new();
+    let platform = hypergryph::Platform::host();
+
+    for game_id in GAME_IDS {
+        if let Some(url) =
+            hypergryph::manifest_url(game_id, hypergryph::GameChannel::Official, platform)
+        {
+            let json = fetch_json(client, &url).await;
+            report.push(evaluate(format!("{game_id}:get_latest"), url, json, |_| {
+                vec!["version", "pkg", "pkg.packs"]
+            }));
+        }
+
+        if let Some(url) =
+            manager::version_url(game_id, hypergryph::GameChannel::Official, platform)
+        {
+            let json = fetch_json(client, &url).await;
+            report.push(evaluate(format!("{game_id}:version"), url, json, |v| {
+                // Either field is enough for fetch_latest_version.
+                if v.get("resVersion").is_some() {
+                    vec!["resVersion"]
+                } else {
+                    vec!["clientVersion"]
+                }
+            }));
+        }
+
+        // Without a token the inquiry API answers with an error code, so the
+        // `data` shape can only be checked when the call actually succeeds.
+        let url = auth::gacha_inquiry_base(game_id).to_string();
+        let json = fetch_json(client, &url).await;
+        report.push(evaluate(format!("{game_id}:gacha"), url, json, |v| {
+            if v.get("code").and_then(|c| c.as_i64()) == Some(0) {
+                vec!["code", "data", "data.list", "data.pagination"]
+            } else {
+                vec!["code"]
+            }
+        }));
+    }
+
+    report
+}
+
+async fn fetch_json(client: &reqwest::Client, url: &str) -> Result<serde_json::Value, String> {
+    let resp = client
+        .get(url)
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    resp.json().await.map_err(|e| format!("响应不是 JSON：{e}"))
+}
+
+fn evaluate(
+    endpoint: String,
+    url: String,
+    json: Result<serde_json::Value, String>,
+    required: impl Fn(&serde_json::Value) -> Vec<&'static str>,
+) -> EndpointCompat {
+    match json {
+        Ok(value) => {
+            let missing_fields: Vec<String> = required(&value)
+                .into_iter()
+                .filter(|path| lookup(&value, path).is_none())
+                .map(str::to_string)
+                .collect();
+            if !missing_fields.is_empty() {
+                log::warn!("[compat] {endpoint} missing fields: {missing_fields:?}");
+            }
+            EndpointCompat {
+                endpoint,
+                url,
+                reachable: true,
+                compatible: missing_fields.is_empty(),
+                missing_fields,
+                error: None,
+            }
+        }
+        Err(e) => {
+            log::warn!("[compat] {endpoint} unreachable: {e}");
+            EndpointCompat {
+                endpoint,
+                url,
+                reachable: false,
+                compatible: false,
+                missing_fields: Vec::new(),
+                error: Some(e),
+            }
+        }
+    }
+}
+
+/// Resolve a dotted path such as "data.pagination" in a JSON value.
+fn lookup<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.')
+        .try_fold(value, |v, key| v.get(key))
+        .filter(|v| !v.is_null())
+}
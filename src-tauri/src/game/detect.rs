@@ -0,0 +1,256 @@
+// Propose install directories without making the user browse for them.
+//
+// Sources, in order: the Windows uninstall registry entries written by the
+// official installers and launcher, the launcher's default locations on every
+// drive, and Steam libraries. Every source only yields directories that exist;
+// `installed` then says whether a game executable was actually found there.
+
+use super::{manager, GAME_IDS};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectedInstall {
+    pub game_id: String,
+    pub path: String,
+    /// The game executable was found in `path`
+    pub installed: bool,
+}
+
+/// Folder names a game is installed under, below a launcher or Steam root.
+fn install_dir_names(game_id: &str) -> &'static [&'static str] {
+    match game_id {
+        "arknights" => &["Arknights", "明日方舟"],
+        "endfield" => &[
+            "Endfield",
+            "ArknightsEndfield",
+            "Arknights Endfield",
+            "终末地",
+        ],
+        _ => &[],
+    }
+}
+
+/// Which game an uninstall entry belongs to, by its display name. Endfield is
+/// checked first because its name also contains "Arknights".
+fn game_for_display_name(name: &str) -> Option<&'static str> {
+    let lower = name.to_lowercase();
+    if lower.contains("endfield") || name.contains("终末地") {
+        Some("endfield")
+    } else if lower.contains("arknights") || name.contains("明日方舟") {
+        Some("arknights")
+    } else {
+        None
+    }
+}
+
+/// Display names of the Hypergryph launcher itself.
+fn is_launcher_name(name: &str) -> bool {
+    name.to_lowercase().contains("hypergryph") || name.contains("鹰角")
+}
+
+/// Scan every source and return unique candidates, installed ones first.
+pub fn detect_install_paths() -> Vec<DetectedInstall> {
+    let mut found: Vec<(String, PathBuf)> = Vec::new();
+    let mut launcher_roots: Vec<PathBuf> = Vec::new();
+
+    for entry in uninstall_entries() {
+        let Some(location) = entry.install_location else {
+            continue;
+        };
+        if let Some(game_id) = game_for_display_name(&entry.display_name) {
+            found.push((game_id.to_string(), location));
+        } else if is_launcher_name(&entry.display_name) {
+            launcher_roots.push(location);
+        }
+    }
+    launcher_roots.extend(default_launcher_roots());
+
+    for root in &launcher_roots {
+        probe_root(root, &mut found);
+        probe_root(&root.join("games"), &mut found);
+    }
+    for library in steam_libraries() {
+        probe_root(&library.join("steamapps").join("common"), &mut found);
+    }
+
+    let mut seen = HashSet::new();
+    let mut detected: Vec<DetectedInstall> = found
+        .into_iter()
+        .filter(|(_, path)| path.is_dir())
+        .filter(|(game_id, path)| seen.insert((game_id.clone(), dedup_key(path))))
+        .map(|(game_id, path)| {
+            let path = path.to_string_lossy().into_owned();
+            DetectedInstall {
+                installed: manager::check_game_installed(&game_id, &path),
+                game_id,
+                path,
+            }
+        })
+        .collect();
+    detected.sort_by_key(|d| !d.installed);
+    log::info!("[game] auto-detect found {} candidate(s)", detected.len());
+    detected
+}
+
+fn dedup_key(path: &Path) -> String {
+    let p = path.to_string_lossy();
+    let p = p.trim_end_matches(['/', '\\']);
+    if cfg!(windows) {
+        p.to_lowercase()
+    } else {
+        p.to_string()
+    }
+}
+
+/// Add `<root>/<dir name>` for every game.
+fn probe_root(root: &Path, found: &mut Vec<(String, PathBuf)>) {
+    for game_id in GAME_IDS {
+        for name in install_dir_names(game_id) {
+            found.push((game_id.to_string(), root.join(name)));
+        }
+    }
+}
+
+/// The launcher's usual install locations on every fixed drive.
+fn default_launcher_roots() -> Vec<PathBuf> {
+    if !cfg!(windows) {
+        return Vec::new();
+    }
+    ('C'..='Z')
+        .map(|d| PathBuf::from(format!("{d}:\\")))
+        .filter(|d| d.is_dir())
+        .flat_map(|d| {
+            [
+                d.join("Program Files").join("Hypergryph Launcher"),
+                d.join("Hypergryph Launcher"),
+                d.join("Hypergryph"),
+            ]
+        })
+        .collect()
+}
+
+// ─── Registry ─────────────────────────────────────────────────────────────────
+
+const UNINSTALL_KEYS: &[&str] = &[
+    r"HKCU\Software\Microsoft\Windows\CurrentVersion\Uninstall",
+    r"HKLM\Software\Microsoft\Windows\CurrentVersion\Uninstall",
+    r"HKLM\Software\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall",
+];
+
+const STEAM_KEY: &str = r"HKCU\Software\Valve\Steam";
+
+#[derive(Debug, Default)]
+struct UninstallEntry {
+    display_name: String,
+    install_location: Option<PathBuf>,
+}
+
+/// Uninstall entries that have a display name, from all hives.
+fn uninstall_entries() -> Vec<UninstallEntry> {
+    UNINSTALL_KEYS
+        .iter()
+        .filter_map(|key| reg_query(key, true))
+        .flat_map(|out| parse_reg_keys(&out))
+        .filter_map(|values| {
+            let display_name = reg_value(&values, "DisplayName")?.to_string();
+            let install_location = reg_value(&values, "InstallLocation")
+                .map(|p| p.trim_matches('"'))
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from);
+            Some(UninstallEntry {
+                display_name,
+                install_location,
+            })
+        })
+        .collect()
+}
+
+/// Run `reg query`; None off Windows or when the key doesn't exist.
+fn reg_query(key: &str, recursive: bool) -> Option<String> {
+    if !cfg!(windows) {
+        return None;
+    }
+    let mut cmd = std::process::Command::new("reg");
+    cmd.args(["query", key]);
+    if recursive {
+        cmd.arg("/s");
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+    }
+    let out = cmd.output().ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Split `reg query` output into one `(name, data)` list per key. Value lines
+/// look like `    Name    REG_SZ    Data`.
+fn parse_reg_keys(out: &str) -> Vec<Vec<(String, String)>> {
+    let mut keys: Vec<Vec<(String, String)>> = Vec::new();
+    for line in out.lines() {
+        if line.starts_with("HKEY_") {
+            keys.push(Vec::new());
+            continue;
+        }
+        let Some(values) = keys.last_mut() else {
+            continue;
+        };
+        let mut parts = line.trim().splitn(3, "    ");
+        let (Some(name), Some(kind), Some(data)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        if kind.starts_with("REG_") {
+            values.push((name.to_string(), data.trim().to_string()));
+        }
+    }
+    keys
+}
+
+fn reg_value<'a>(values: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    values
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+// ─── Steam ────────────────────────────────────────────────────────────────────
+
+/// Steam library folders listed in `libraryfolders.vdf`, including the Steam
+/// install itself.
+fn steam_libraries() -> Vec<PathBuf> {
+    let Some(steam) = reg_query(STEAM_KEY, false).and_then(|out| {
+        let keys = parse_reg_keys(&out);
+        keys.first()
+            .and_then(|v| reg_value(v, "SteamPath"))
+            .map(PathBuf::from)
+    }) else {
+        return Vec::new();
+    };
+    let vdf = steam.join("steamapps").join("libraryfolders.vdf");
+    let mut libraries = vec![steam];
+    if let Ok(raw) = std::fs::read_to_string(&vdf) {
+        libraries.extend(parse_library_paths(&raw));
+    }
+    libraries
+}
+
+/// The `"path" "D:\\SteamLibrary"` entries of a libraryfolders.vdf.
+fn parse_library_paths(vdf: &str) -> Vec<PathBuf> {
+    vdf.lines()
+        .filter_map(|line| {
+            let mut quoted = line.split('"').skip(1).step_by(2);
+            let key = quoted.next()?;
+            let value = quoted.next()?;
+            key.eq_ignore_ascii_case("path")
+                .then(|| PathBuf::from(value.replace("\\\\", "\\")))
+        })
+        .collect()
+}
@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 // ─── API response types ───────────────────────────────────────────────────────
 
@@ -39,6 +40,9 @@ pub struct GamePack {
     pub size: u64,
     /// Filename derived from URL
     pub filename: String,
+    /// Fallback URLs for the same file, see `add_mirrors`
+    #[serde(default)]
+    pub mirrors: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +57,73 @@ pub struct GameManifest {
     pub file_path: String,
 }
 
+/// Server a game install belongs to. Packages, patches and gacha history
+/// differ per channel, so it is chosen per game in settings.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GameChannel {
+    /// Hypergryph official server (官服)
+    #[default]
+    Official,
+    /// Bilibili server (B 服)
+    Bilibili,
+}
+
+impl GameChannel {
+    /// `channel` / `sub_channel` for the launcher API.
+    fn launcher_ids(self) -> (u32, u32) {
+        match self {
+            GameChannel::Official => (1, 1),
+            GameChannel::Bilibili => (2, 2),
+        }
+    }
+
+    /// `channelId` for the gacha inquiry API.
+    pub fn channel_id(self) -> u32 {
+        self.launcher_ids().0
+    }
+
+    /// Directory of the channel's files under `/config/prod/` on the config CDN.
+    pub fn config_dir(self) -> &'static str {
+        match self {
+            GameChannel::Official => "official",
+            GameChannel::Bilibili => "b",
+        }
+    }
+}
+
+/// Client build a manifest or version is requested for.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Windows,
+    Macos,
+    Android,
+}
+
+impl Platform {
+    /// The platform the launcher itself is running on. Other desktop OSes
+    /// (Linux) are treated as Windows, the only desktop build the games ship.
+    pub fn host() -> Self {
+        if cfg!(target_os = "macos") {
+            Platform::Macos
+        } else if cfg!(target_os = "android") {
+            Platform::Android
+        } else {
+            Platform::Windows
+        }
+    }
+
+    /// Spelling used in launcher query strings and config CDN paths.
+    pub fn api_name(self) -> &'static str {
+        match self {
+            Platform::Windows => "Windows",
+            Platform::Macos => "MacOS",
+            Platform::Android => "Android",
+        }
+    }
+}
+
 // ─── Game config registry ─────────────────────────────────────────────────────
 
 struct GameApiConfig {
@@ -61,19 +132,28 @@ struct GameApiConfig {
     sub_channel: u32,
 }
 
-fn game_api_config(game_id: &str) -> Option<GameApiConfig> {
-    match game_id {
-        "arknights" => Some(GameApiConfig {
-            appcode: "GzD1CpaWgmSq1wew",
-            channel: 1,
-            sub_channel: 1,
-        }),
-        "endfield" => Some(GameApiConfig {
-            appcode: "6LL0KJuqHBVz33WK",
-            channel: 1,
-            sub_channel: 1,
-        }),
-        _ => None,
+fn game_api_config(game_id: &str, channel: GameChannel) -> Option<GameApiConfig> {
+    let appcode = match game_id {
+        "arknights" => "GzD1CpaWgmSq1wew",
+        "endfield" => "6LL0KJuqHBVz33WK",
+        _ => return None,
+    };
+    if !supports_channel(game_id, channel) {
+        return None;
+    }
+    let (channel, sub_channel) = channel.launcher_ids();
+    Some(GameApiConfig {
+        appcode,
+        channel,
+        sub_channel,
+    })
+}
+
+/// Endfield is only distributed through the official channel.
+pub fn supports_channel(game_id: &str, channel: GameChannel) -> bool {
+    match channel {
+        GameChannel::Official => true,
+        GameChannel::Bilibili => game_id == "arknights",
     }
 }
 
@@ -81,18 +161,29 @@ fn game_api_config(game_id: &str) -> Option<GameApiConfig> {
 
 const LAUNCHER_API_BASE: &str = "https://launcher.hypergryph.com/api/game";
 
+/// The `get_latest` launcher endpoint for a game on `channel` and `platform`,
+/// or None if unsupported.
+pub fn manifest_url(game_id: &str, channel: GameChannel, platform: Platform) -> Option<String> {
+    let cfg = game_api_config(game_id, channel)?;
+    Some(format!(
+        "{}/get_latest?appcode={}&channel={}&sub_channel={}&platform={}",
+        LAUNCHER_API_BASE,
+        cfg.appcode,
+        cfg.channel,
+        cfg.sub_channel,
+        platform.api_name()
+    ))
+}
+
 /// Fetch the latest full-install package manifest for a game.
 pub async fn fetch_game_manifest(
     game_id: &str,
+    channel: GameChannel,
+    platform: Platform,
     client: &reqwest::Client,
 ) -> Result<GameManifest> {
-    let cfg = game_api_config(game_id)
-        .ok_or_else(|| anyhow!("game '{}' 暂不支持下载", game_id))?;
-
-    let url = format!(
-        "{}/get_latest?appcode={}&channel={}&sub_channel={}&platform=Windows",
-        LAUNCHER_API_BASE, cfg.appcode, cfg.channel, cfg.sub_channel
-    );
+    let url = manifest_url(game_id, channel, platform)
+        .ok_or_else(|| anyhow!("game '{}' 暂不支持下载（渠道 {:?}）", game_id, channel))?;
 
     let resp = client
         .get(&url)
@@ -103,13 +194,9 @@ pub async fn fetch_game_manifest(
 
     let data: GetLatestResponse = resp.json().await?;
 
-    let pkg = data
-        .pkg
-        .ok_or_else(|| anyhow!("API 返回中没有 pkg 字段"))?;
+    let pkg = data.pkg.ok_or_else(|| anyhow!("API 返回中没有 pkg 字段"))?;
 
-    let raw_packs = pkg
-        .packs
-        .unwrap_or_default();
+    let raw_packs = pkg.packs.unwrap_or_default();
 
     let packs: Vec<GamePack> = raw_packs
         .into_iter()
@@ -127,6 +214,7 @@ pub async fn fetch_game_manifest(
                 md5: p.md5,
                 size,
                 filename,
+                mirrors: Vec::new(),
             }
         })
         .collect();
@@ -147,20 +235,77 @@ pub async fn fetch_game_manifest(
     })
 }
 
+/// How long a fetched manifest is reused by `fetch_game_manifest_cached`.
+/// Short, because pack URLs may carry expiring CDN auth keys.
+const MANIFEST_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+type ManifestKey = (String, GameChannel, Platform);
+
+/// (game_id, channel, platform) → (fetched at, manifest)
+static MANIFEST_CACHE: std::sync::LazyLock<
+    std::sync::RwLock<HashMap<ManifestKey, (std::time::Instant, GameManifest)>>,
+> = std::sync::LazyLock::new(Default::default);
+
+/// `fetch_game_manifest` with a short in-memory cache, so the UI showing a
+/// manifest and the install that follows share one request. `refresh`
+/// bypasses the cache and stores the new result.
+pub async fn fetch_game_manifest_cached(
+    game_id: &str,
+    channel: GameChannel,
+    platform: Platform,
+    client: &reqwest::Client,
+    refresh: bool,
+) -> Result<GameManifest> {
+    let key = (game_id.to_string(), channel, platform);
+    if !refresh {
+        let cache = MANIFEST_CACHE.read().unwrap_or_else(|e| e.into_inner());
+        if let Some((at, manifest)) = cache.get(&key) {
+            if at.elapsed() < MANIFEST_TTL {
+                return Ok(manifest.clone());
+            }
+        }
+    }
+    let manifest = fetch_game_manifest(game_id, channel, platform, client).await?;
+    MANIFEST_CACHE
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, (std::time::Instant::now(), manifest.clone()));
+    Ok(manifest)
+}
+
+/// Give every pack a fallback URL per mirror base: the pack URL's path and
+/// query (which carries any CDN auth key) under the mirror's host.
+pub fn add_mirrors(manifest: &mut GameManifest, bases: &[String]) {
+    for pack in &mut manifest.packs {
+        let Ok(url) = reqwest::Url::parse(&pack.url) else {
+            continue;
+        };
+        let path_and_query = match url.query() {
+            Some(q) => format!("{}?{}", url.path(), q),
+            None => url.path().to_string(),
+        };
+        pack.mirrors = bases
+            .iter()
+            .map(|b| b.trim().trim_end_matches('/'))
+            .filter(|b| !b.is_empty())
+            .map(|b| format!("{b}{path_and_query}"))
+            .filter(|m| *m != pack.url)
+            .collect();
+    }
+}
+
 /// Fetch update patch manifest (from current version to latest).
 /// Returns None if no patch is available (clean install required).
 pub async fn fetch_patch_manifest(
     game_id: &str,
+    channel: GameChannel,
+    platform: Platform,
     current_version: &str,
     client: &reqwest::Client,
 ) -> Result<Option<GameManifest>> {
-    let cfg = game_api_config(game_id)
-        .ok_or_else(|| anyhow!("game '{}' 暂不支持", game_id))?;
-
-    let url = format!(
-        "{}/get_latest?appcode={}&channel={}&sub_channel={}&platform=Windows&current_version={}",
-        LAUNCHER_API_BASE, cfg.appcode, cfg.channel, cfg.sub_channel, current_version
-    );
+    let base = manifest_url(game_id, channel, platform)
+        .ok_or_else(|| anyhow!("game '{}' 暂不支持（渠道 {:?}）", game_id, channel))?;
+    let url = format!("{}&current_version={}", base, current_version);
 
     let resp = client
         .get(&url)
@@ -190,7 +335,13 @@ pub async fn fetch_patch_manifest(
                 .unwrap_or(raw_name)
                 .to_string();
             let size = p.package_size.parse::<u64>().unwrap_or(0);
-            GamePack { url: p.url, md5: p.md5, size, filename }
+            GamePack {
+                url: p.url,
+                md5: p.md5,
+                size,
+                filename,
+                mirrors: Vec::new(),
+            }
         })
         .collect();
 
@@ -1,6 +1,7 @@
+use super::hypergryph::{GameChannel, Platform};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameInfo {
@@ -13,8 +14,8 @@ pub struct GameInfo {
 
 /// Common helper / installer executables to skip during auto-detection (all lowercase).
 const EXCLUDED_EXES: &[&str] = &[
-    "unitycrashandler64.exe",
-    "unitycrashandler32.exe",
+    "unitycrashhandler64.exe",
+    "unitycrashhandler32.exe",
     "crashreportclient.exe",
     "uninstall.exe",
     "uninstallgame.exe",
@@ -24,43 +25,143 @@ const EXCLUDED_EXES: &[&str] = &[
     "vcredist_x86.exe",
 ];
 
-/// Known exe names to try first (fast path), per game.
-fn known_exe_names(game_id: &str) -> &'static [&'static str] {
-    match game_id {
-        "arknights" => &["Arknights.exe", "明日方舟.exe"],
-        "endfield" => &["ArknightsEndfield.exe", "EndField.exe", "Endfield.exe"],
+/// Known exe names to try first (fast path), per game and platform. Builds
+/// without a confirmed name rely on the extension scan.
+fn known_exe_names(game_id: &str, platform: Platform) -> &'static [&'static str] {
+    match (game_id, platform) {
+        ("arknights", Platform::Windows) => &["Arknights.exe", "明日方舟.exe"],
+        ("endfield", Platform::Windows) => {
+            &["ArknightsEndfield.exe", "EndField.exe", "Endfield.exe"]
+        }
         _ => &[],
     }
 }
 
+/// Lowercase extension of a launchable game entry on `platform`. macOS apps
+/// are `.app` bundle directories; Android builds are installer packages.
+fn exe_extension(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Windows => ".exe",
+        Platform::Macos => ".app",
+        Platform::Android => ".apk",
+    }
+}
+
+/// Outcome of looking for a game's executable.
+#[derive(Debug)]
+pub enum ExeLookup {
+    Found(PathBuf),
+    /// Several plausible executables and no way to tell which one is the game.
+    Ambiguous(Vec<PathBuf>),
+    NotFound,
+}
+
 /// Find the game executable inside an install directory.
-/// 1. Try known names first (fast).
-/// 2. Fall back to the largest .exe in the directory (excluding known helpers).
-fn find_game_exe(game_id: &str, install_path: &str) -> Option<std::path::PathBuf> {
+/// 1. Use the user-selected override if it still exists.
+/// 2. Try known names (fast).
+/// 3. Fall back to scanning for .exe files (excluding known helpers), going up
+///    to `EXE_SEARCH_DEPTH` subdirectory levels down when a level has none; a
+///    single hit is used directly, several are returned for the user to choose
+///    from.
+pub fn find_game_exe(game_id: &str, install_path: &str, exe_override: Option<&str>) -> ExeLookup {
+    if let Some(path) = exe_override {
+        let p = PathBuf::from(path);
+        if p.is_file() {
+            return ExeLookup::Found(p);
+        }
+        log::warn!(
+            "[game] exe override for {} no longer exists: {}",
+            game_id,
+            path
+        );
+    }
+
     let base = Path::new(install_path);
+    let platform = Platform::host();
 
-    for name in known_exe_names(game_id) {
+    for name in known_exe_names(game_id, platform) {
         let p = base.join(name);
         if p.exists() {
-            return Some(p);
+            return ExeLookup::Found(p);
         }
     }
 
-    // Scan directory: pick the largest .exe that isn't a known helper
-    std::fs::read_dir(base)
-        .ok()?
+    let mut candidates = exe_candidates(base, platform);
+    match candidates.len() {
+        0 => ExeLookup::NotFound,
+        1 => ExeLookup::Found(candidates.remove(0)),
+        _ => ExeLookup::Ambiguous(candidates),
+    }
+}
+
+/// Subdirectory levels below the install dir searched for executables, for
+/// builds that nest the game under a versioned folder.
+const EXE_SEARCH_DEPTH: usize = 2;
+
+/// Non-helper executables at the shallowest level that has any, from `base`
+/// down to `EXE_SEARCH_DEPTH`, largest first.
+fn exe_candidates(base: &Path, platform: Platform) -> Vec<PathBuf> {
+    let mut dirs = vec![base.to_path_buf()];
+    for depth in 0..=EXE_SEARCH_DEPTH {
+        let mut found: Vec<(PathBuf, u64)> = dirs
+            .iter()
+            .flat_map(|dir| exes_in_dir(dir, platform))
+            .collect();
+        if !found.is_empty() {
+            if depth > 0 {
+                log::info!("[game] exe search found candidates {} level(s) down", depth);
+            }
+            found.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+            return found.into_iter().map(|(path, _)| path).collect();
+        }
+        dirs = dirs.iter().flat_map(|dir| subdirs(dir, platform)).collect();
+    }
+    Vec::new()
+}
+
+/// Subdirectories of `dir` to descend into; `.app` bundles are candidates
+/// themselves, not folders to search.
+fn subdirs(dir: &Path, platform: Platform) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let ext = exe_extension(platform);
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|e| {
+            !e.file_name()
+                .to_string_lossy()
+                .to_lowercase()
+                .ends_with(ext)
+        })
+        .map(|e| e.path())
+        .collect()
+}
+
+/// Every non-helper executable for `platform` directly inside `dir`, with its
+/// size. `.app` bundles are directories and count as size 0.
+fn exes_in_dir(dir: &Path, platform: Platform) -> Vec<(PathBuf, u64)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let ext = exe_extension(platform);
+    entries
         .filter_map(|e| e.ok())
         .filter(|e| {
             let name = e.file_name();
             let lower = name.to_string_lossy().to_lowercase();
-            lower.ends_with(".exe") && !EXCLUDED_EXES.iter().any(|ex| lower == *ex)
+            lower.ends_with(ext) && !EXCLUDED_EXES.iter().any(|ex| lower == *ex)
         })
         .filter_map(|e| {
             let meta = e.metadata().ok()?;
-            meta.is_file().then_some((e.path(), meta.len()))
+            if platform == Platform::Macos {
+                meta.is_dir().then_some((e.path(), 0))
+            } else {
+                meta.is_file().then_some((e.path(), meta.len()))
+            }
         })
-        .max_by_key(|(_, size)| *size)
-        .map(|(path, _)| path)
+        .collect()
 }
 
 /// Check if a directory looks like a valid install for the given game.
@@ -72,13 +173,38 @@ pub fn validate_install_path(_game_id: &str, path: &str) -> bool {
 
 /// Returns true if the game executable can be found inside the given directory.
 pub fn check_game_installed(game_id: &str, install_path: &str) -> bool {
-    find_game_exe(game_id, install_path).is_some()
+    !matches!(
+        find_game_exe(game_id, install_path, None),
+        ExeLookup::NotFound
+    )
 }
 
-/// Stricter check used before launching: returns the exe path or an error.
-pub fn require_game_exe(game_id: &str, install_path: &str) -> Result<std::path::PathBuf> {
-    find_game_exe(game_id, install_path)
-        .ok_or_else(|| anyhow::anyhow!("在 {} 中找不到 {} 可执行文件", install_path, game_id))
+/// Stricter check used before launching: returns the exe path, or an error
+/// listing the candidates when the user has to pick one via `select_game_exe`.
+pub fn require_game_exe(
+    game_id: &str,
+    install_path: &str,
+    exe_override: Option<&str>,
+) -> Result<PathBuf> {
+    match find_game_exe(game_id, install_path, exe_override) {
+        ExeLookup::Found(p) => Ok(p),
+        ExeLookup::Ambiguous(candidates) => {
+            let names: Vec<String> = candidates
+                .iter()
+                .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+                .collect();
+            Err(anyhow::anyhow!(
+                "在 {} 中找到多个可执行文件，请先选择要启动的程序：{}",
+                install_path,
+                names.join("、")
+            ))
+        }
+        ExeLookup::NotFound => Err(anyhow::anyhow!(
+            "在 {} 中找不到 {} 可执行文件",
+            install_path,
+            game_id
+        )),
+    }
 }
 
 // ─── Version API ─────────────────────────────────────────────────────────────
@@ -91,19 +217,34 @@ struct AkVersionResponse {
     client_version: Option<String>,
 }
 
+/// Hypergryph's client version endpoint for a game's `platform` build on
+/// `channel`.
+pub fn version_url(game_id: &str, channel: GameChannel, platform: Platform) -> Option<String> {
+    let host = match game_id {
+        "arknights" => "ak-conf.hypergryph.com",
+        "endfield" => "beyond-conf.hypergryph.com",
+        _ => return None,
+    };
+    Some(format!(
+        "https://{host}/config/prod/{}/{}/version",
+        channel.config_dir(),
+        platform.api_name()
+    ))
+}
+
 /// Fetch the latest client version string for a game from Hypergryph's CDN.
 pub async fn fetch_latest_version(
     game_id: &str,
+    channel: GameChannel,
+    platform: Platform,
     client: &reqwest::Client,
 ) -> Result<Option<String>> {
-    let url = match game_id {
-        "arknights" => "https://ak-conf.hypergryph.com/config/prod/official/Windows/version",
-        "endfield" => "https://beyond-conf.hypergryph.com/config/prod/official/Windows/version",
-        _ => return Ok(None),
+    let Some(url) = version_url(game_id, channel, platform) else {
+        return Ok(None);
     };
 
     let resp = client
-        .get(url)
+        .get(&url)
         .timeout(std::time::Duration::from_secs(10))
         .send()
         .await?;
@@ -116,12 +257,214 @@ pub async fn fetch_latest_version(
     Ok(data.client_version.or(data.res_version))
 }
 
-/// Read the local client version from the game's `version` file.
-/// Arknights/Endfield store a JSON file at `{install_path}/version`
-/// with `clientVersion` (and/or `resVersion`) fields.
-pub fn read_local_version(install_path: &str) -> Option<String> {
-    let path = std::path::Path::new(install_path).join("version");
-    let raw = std::fs::read_to_string(path).ok()?;
-    let v: AkVersionResponse = serde_json::from_str(&raw).ok()?;
-    v.client_version.or(v.res_version)
+/// How long a fetched latest version is reused by `latest_version_cached`.
+const LATEST_VERSION_TTL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+type VersionKey = (String, GameChannel, Platform);
+
+/// (game_id, channel, platform) → (fetched at, latest version)
+type VersionCache = std::collections::HashMap<VersionKey, (std::time::Instant, Option<String>)>;
+
+static LATEST_VERSION_CACHE: std::sync::LazyLock<std::sync::Mutex<VersionCache>> =
+    std::sync::LazyLock::new(Default::default);
+
+/// `fetch_latest_version` for the host platform with a short in-memory cache,
+/// so background update checks stay cheap. `refresh` bypasses the cache and
+/// re-queries the CDN.
+pub async fn latest_version_cached(
+    game_id: &str,
+    channel: GameChannel,
+    client: &reqwest::Client,
+    refresh: bool,
+) -> Result<Option<String>> {
+    let platform = Platform::host();
+    let key = (game_id.to_string(), channel, platform);
+    if !refresh {
+        let cache = LATEST_VERSION_CACHE
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some((at, version)) = cache.get(&key) {
+            if at.elapsed() < LATEST_VERSION_TTL {
+                return Ok(version.clone());
+            }
+        }
+    }
+    let version = fetch_latest_version(game_id, channel, platform, client).await?;
+    LATEST_VERSION_CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, (std::time::Instant::now(), version.clone()));
+    Ok(version)
+}
+
+/// How to pull the version out of a game's version file.
+#[derive(Debug, Clone, Copy)]
+enum VersionFormat {
+    /// Parse the file as JSON and pluck the string (or number) at this key path
+    JsonPath(&'static [&'static str]),
+    /// The bare version string
+    PlainText,
+}
+
+/// `{"clientVersion": ..., "resVersion": ...}` like the CDN endpoint.
+const CLIENT_VERSION: VersionFormat = VersionFormat::JsonPath(&["clientVersion"]);
+const RES_VERSION: VersionFormat = VersionFormat::JsonPath(&["resVersion"]);
+
+/// Written by `write_local_version` when the user corrects the version by hand.
+/// Checked before the game's own files so the correction takes effect.
+const VERSION_OVERRIDE_FILE: &str = ".highgarden_version";
+
+/// Version files to try, in order, per game (relative to the install dir).
+/// A file may be listed more than once with different formats.
+fn version_files(game_id: &str) -> &'static [(&'static str, VersionFormat)] {
+    match game_id {
+        "arknights" => &[
+            (VERSION_OVERRIDE_FILE, VersionFormat::PlainText),
+            ("version", CLIENT_VERSION),
+            ("version", RES_VERSION),
+            ("version.json", CLIENT_VERSION),
+            ("version.json", RES_VERSION),
+            ("version.txt", VersionFormat::PlainText),
+        ],
+        "endfield" => &[
+            (VERSION_OVERRIDE_FILE, VersionFormat::PlainText),
+            ("version", CLIENT_VERSION),
+            ("version", RES_VERSION),
+            ("version.json", CLIENT_VERSION),
+            ("version.json", RES_VERSION),
+            // Launcher-written config blob
+            ("config.json", VersionFormat::JsonPath(&["version"])),
+            ("config.json", VersionFormat::JsonPath(&["game", "version"])),
+            ("version.txt", VersionFormat::PlainText),
+        ],
+        _ => &[
+            (VERSION_OVERRIDE_FILE, VersionFormat::PlainText),
+            ("version", CLIENT_VERSION),
+            ("version", RES_VERSION),
+        ],
+    }
+}
+
+/// Read the local client version, trying each known version file for the
+/// game. Files that are missing, not UTF-8 or not in the expected format are
+/// skipped; returns None only when none of them yields a version.
+pub fn read_local_version(game_id: &str, install_path: &str) -> Option<String> {
+    let base = Path::new(install_path);
+    version_files(game_id).iter().find_map(|(name, format)| {
+        let raw = std::fs::read_to_string(base.join(name)).ok()?;
+        parse_version(&raw, *format)
+    })
+}
+
+fn parse_version(raw: &str, format: VersionFormat) -> Option<String> {
+    let raw = raw.trim_start_matches('\u{feff}');
+    let version = match format {
+        VersionFormat::JsonPath(path) => {
+            let root: serde_json::Value = serde_json::from_str(raw).ok()?;
+            let value = path.iter().try_fold(&root, |v, key| v.get(key))?;
+            match value {
+                serde_json::Value::String(s) => s.trim().to_string(),
+                serde_json::Value::Number(n) => n.to_string(),
+                _ => return None,
+            }
+        }
+        VersionFormat::PlainText => raw.lines().next()?.trim().to_string(),
+    };
+    (!version.is_empty()).then_some(version)
+}
+
+/// Record a user-supplied local version, or remove the override when empty.
+pub fn write_local_version(install_path: &str, version: &str) -> Result<()> {
+    let path = Path::new(install_path).join(VERSION_OVERRIDE_FILE);
+    let version = version.trim();
+    if version.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    std::fs::write(path, version)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &Path, size: usize) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, vec![0u8; size]).unwrap();
+    }
+
+    fn relative(base: &Path, found: Vec<PathBuf>) -> Vec<PathBuf> {
+        found
+            .into_iter()
+            .map(|p| p.strip_prefix(base).unwrap().to_path_buf())
+            .collect()
+    }
+
+    #[test]
+    fn exe_candidates_finds_nested_exe() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path();
+        touch(&base.join("Game_1.0").join("Arknights.exe"), 10);
+        touch(&base.join("Game_1.0").join("UnityCrashHandler64.exe"), 20);
+
+        let found = exe_candidates(base, Platform::Windows);
+
+        assert_eq!(
+            relative(base, found),
+            [Path::new("Game_1.0").join("Arknights.exe")]
+        );
+    }
+
+    #[test]
+    fn exe_candidates_prefers_shallowest_level() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path();
+        touch(&base.join("Launcher.exe"), 1);
+        touch(&base.join("bin").join("Game.exe"), 100);
+
+        let found = exe_candidates(base, Platform::Windows);
+
+        assert_eq!(relative(base, found), [PathBuf::from("Launcher.exe")]);
+    }
+
+    #[test]
+    fn exe_candidates_sorts_by_size_and_stops_at_depth() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path();
+        touch(&base.join("a").join("b").join("small.exe"), 1);
+        touch(&base.join("a").join("b").join("large.exe"), 50);
+        touch(&base.join("x").join("y").join("z").join("deep.exe"), 100);
+
+        let found = exe_candidates(base, Platform::Windows);
+
+        let b = Path::new("a").join("b");
+        assert_eq!(
+            relative(base, found),
+            [b.join("large.exe"), b.join("small.exe")]
+        );
+    }
+
+    #[test]
+    fn exe_candidates_ignores_exes_below_search_depth() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path();
+        touch(&base.join("x").join("y").join("z").join("deep.exe"), 1);
+
+        assert!(exe_candidates(base, Platform::Windows).is_empty());
+    }
+
+    #[test]
+    fn version_url_follows_channel() {
+        assert_eq!(
+            version_url("arknights", GameChannel::Official, Platform::Android).as_deref(),
+            Some("https://ak-conf.hypergryph.com/config/prod/official/Android/version")
+        );
+        assert_eq!(
+            version_url("arknights", GameChannel::Bilibili, Platform::Android).as_deref(),
+            Some("https://ak-conf.hypergryph.com/config/prod/b/Android/version")
+        );
+    }
 }
@@ -1,8 +1,20 @@
+pub mod compat;
+pub mod detect;
 pub mod hypergryph;
 pub mod manager;
+pub mod sessions;
+pub mod verify;
 
-pub use hypergryph::{fetch_game_manifest, fetch_patch_manifest, GameManifest};
+/// All games the launcher knows how to manage.
+pub const GAME_IDS: &[&str] = &["arknights", "endfield"];
+
+pub use compat::{check_api_compat, EndpointCompat};
+pub use detect::{detect_install_paths, DetectedInstall};
+pub use hypergryph::{
+    add_mirrors, fetch_game_manifest, fetch_game_manifest_cached, fetch_patch_manifest,
+    supports_channel, GameChannel, GameManifest, Platform,
+};
 pub use manager::{
-    check_game_installed, fetch_latest_version, read_local_version,
-    require_game_exe, validate_install_path,
+    check_game_installed, fetch_latest_version, find_game_exe, latest_version_cached,
+    read_local_version, require_game_exe, validate_install_path, write_local_version, ExeLookup,
 };
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+// ─── Types ─────────────────────────────────────────────────────────────────
+
+/// One observed run of a game, from process detection to exit (Unix seconds).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameSession {
+    pub game_id: String,
+    pub start_ts: i64,
+    pub end_ts: i64,
+}
+
+/// Sessions kept across all games; the oldest are dropped beyond this.
+const MAX_SESSIONS: usize = 200;
+
+// ─── Persistence ───────────────────────────────────────────────────────────
+
+static SESSIONS_LOCK: Mutex<()> = Mutex::new(());
+
+/// All stored sessions, oldest first.
+pub fn load_sessions(path: &Path) -> Vec<GameSession> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// Append a finished session, trimming the file to `MAX_SESSIONS`.
+pub fn record_session(path: &Path, session: GameSession) -> Result<()> {
+    let _guard = SESSIONS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut sessions = load_sessions(path);
+    sessions.push(session);
+    let excess = sessions.len().saturating_sub(MAX_SESSIONS);
+    sessions.drain(..excess);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&sessions)?)?;
+    Ok(())
+}
+
+/// Most recent sessions of one game, newest first.
+pub fn recent_sessions(path: &Path, game_id: &str, limit: usize) -> Vec<GameSession> {
+    load_sessions(path)
+        .into_iter()
+        .rev()
+        .filter(|s| s.game_id == game_id)
+        .take(limit)
+        .collect()
+}
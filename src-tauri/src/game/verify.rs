@@ -0,0 +1,242 @@
+use super::GameManifest;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+// ─── Types ────────────────────────────────────────────────────────────────────
+
+/// One asset listed in the manifest's `game_files` index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameFileEntry {
+    pub path: String,
+    pub md5: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileIssue {
+    Missing,
+    Corrupt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BadFile {
+    pub path: String,
+    pub issue: FileIssue,
+    pub expected_md5: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyProgress {
+    pub game_id: String,
+    pub files_done: usize,
+    pub files_total: usize,
+}
+
+const HASH_BUF_SIZE: usize = 1024 * 1024;
+
+// ─── File index ───────────────────────────────────────────────────────────────
+
+/// Download the per-asset index that lives next to the manifest's `file_path`.
+/// Each non-empty line is a JSON object `{"path", "md5", "size"}`. The index
+/// itself is checked against the manifest's `game_files_md5`, so a truncated
+/// or stale list can't make a broken install look healthy.
+pub async fn fetch_file_list(
+    manifest: &GameManifest,
+    client: &reqwest::Client,
+) -> Result<Vec<GameFileEntry>> {
+    if manifest.file_path.is_empty() {
+        return Err(anyhow!("清单中没有文件列表地址"));
+    }
+    let url = format!("{}/game_files", manifest.file_path.trim_end_matches('/'));
+    let body = client
+        .get(&url)
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    if !manifest.game_files_md5.is_empty() {
+        let actual = format!("{:x}", md5::compute(&body));
+        if !actual.eq_ignore_ascii_case(&manifest.game_files_md5) {
+            log::error!(
+                "[verify] game_files md5 mismatch expected={} got={}",
+                manifest.game_files_md5,
+                actual
+            );
+            return Err(anyhow!("文件列表校验失败，请稍后重试"));
+        }
+    }
+
+    let body = String::from_utf8_lossy(&body);
+    body.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|l| serde_json::from_str(l).map_err(|e| anyhow!("文件列表格式错误：{e}")))
+        .collect()
+}
+
+/// Download URL of one asset. Assets are served under `file_path` at their
+/// index-relative path.
+pub fn asset_url(manifest: &GameManifest, rel: &str) -> String {
+    format!(
+        "{}/{}",
+        manifest.file_path.trim_end_matches('/'),
+        rel.replace('\\', "/").trim_start_matches('/')
+    )
+}
+
+/// Resolve a manifest-relative path under `install_path`, rejecting anything
+/// that would escape it.
+pub fn resolve_entry_path(install_path: &Path, rel: &str) -> Option<PathBuf> {
+    let rel = Path::new(rel);
+    rel.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        .then(|| install_path.join(rel))
+}
+
+// ─── Hashing ──────────────────────────────────────────────────────────────────
+
+/// Hash every listed file and return those that are missing or don't match.
+///
+/// Files are hashed on blocking threads, at most `concurrency` at a time.
+/// Parallel reads help on SSDs but make an HDD seek back and forth between
+/// files, which is slower than reading them one after another, so callers
+/// should default to 1 and let users raise it.
+///
+/// Entries whose path would escape `install_path` are skipped and left out
+/// of the progress total.
+pub async fn verify_files<F>(
+    install_path: &Path,
+    entries: Vec<GameFileEntry>,
+    concurrency: usize,
+    on_progress: F,
+) -> Result<Vec<BadFile>>
+where
+    F: Fn(usize, usize),
+{
+    let mut files = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let Some(path) = resolve_entry_path(install_path, &entry.path) else {
+            log::warn!("[verify] skipping unsafe path {}", entry.path);
+            continue;
+        };
+        files.push((path, entry));
+    }
+    let total = files.len();
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut join_set = JoinSet::new();
+
+    // Everything is queued up front and each task waits for its own permit,
+    // so the loop below reports progress while files are still hashing.
+    for (path, entry) in files {
+        let semaphore = semaphore.clone();
+        join_set.spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            tokio::task::spawn_blocking(move || check_file(&path, entry))
+                .await
+                .map_err(|e| anyhow!("校验线程异常：{e}"))
+        });
+    }
+
+    let mut bad = Vec::new();
+    let mut done = 0;
+    while let Some(result) = join_set.join_next().await {
+        if let Some(b) = result.map_err(|e| anyhow!("校验线程异常：{e}"))?? {
+            bad.push(b);
+        }
+        done += 1;
+        on_progress(done, total);
+    }
+    bad.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(bad)
+}
+
+fn check_file(path: &Path, entry: GameFileEntry) -> Option<BadFile> {
+    // A size mismatch settles it without reading the file.
+    let wrong_size = entry.size > 0
+        && std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() != entry.size);
+    let issue = if wrong_size {
+        FileIssue::Corrupt
+    } else {
+        match md5_file(path) {
+            Ok(actual) if actual.eq_ignore_ascii_case(&entry.md5) => return None,
+            Ok(_) => FileIssue::Corrupt,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => FileIssue::Missing,
+            Err(e) => {
+                log::warn!("[verify] cannot read {}: {}", path.display(), e);
+                FileIssue::Corrupt
+            }
+        }
+    };
+    Some(BadFile {
+        path: entry.path,
+        issue,
+        expected_md5: entry.md5,
+    })
+}
+
+fn md5_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0u8; HASH_BUF_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        context.consume(&buf[..n]);
+    }
+    Ok(format!("{:x}", context.compute()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn entry(path: &str, contents: &str) -> GameFileEntry {
+        GameFileEntry {
+            path: path.into(),
+            md5: format!("{:x}", md5::compute(contents)),
+            size: contents.len() as u64,
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_counts_only_checked_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("a.bin"), "aaa").unwrap();
+        std::fs::write(tmp.path().join("b.bin"), "bad").unwrap();
+        let entries = vec![
+            entry("a.bin", "aaa"),
+            entry("b.bin", "bbb"),
+            entry("c.bin", "ccc"),
+            entry("../outside.bin", "xxx"),
+        ];
+        let calls = Mutex::new(Vec::new());
+
+        let bad = verify_files(tmp.path(), entries, 1, |done, total| {
+            calls.lock().unwrap().push((done, total));
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(*calls.lock().unwrap(), vec![(1, 3), (2, 3), (3, 3)]);
+        let issues: Vec<_> = bad.iter().map(|b| (b.path.as_str(), b.issue)).collect();
+        assert_eq!(
+            issues,
+            vec![("b.bin", FileIssue::Corrupt), ("c.bin", FileIssue::Missing)]
+        );
+    }
+}
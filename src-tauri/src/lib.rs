@@ -3,6 +3,7 @@ mod config;
 mod download;
 mod gacha;
 mod game;
+mod net;
 
 use commands::{AppState, *};
 use download::DownloadManager;
@@ -13,17 +14,27 @@ use tokio::sync::RwLock;
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
-        .plugin(tauri_plugin_log::Builder::new().build())
+        .plugin(
+            tauri_plugin_log::Builder::new()
+                .filter(download::logging::allows)
+                .build(),
+        )
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_process::init())
         .setup(|app| {
-            // Load persisted config (blocking is fine at startup)
-            let cfg =
-                tauri::async_runtime::block_on(config::load_config(app.handle()))
-                    .unwrap_or_default();
+            // Load persisted config (blocking is fine at startup). On failure
+            // run with defaults that are never saved over the user's file.
+            let cfg = tauri::async_runtime::block_on(config::load_config(app.handle()))
+                .unwrap_or_else(|e| config::defaults_after_load_error(&e));
+            let network = cfg.settings.network_options();
+            let verify_mode = cfg.settings.verify_mode;
+            let keep_files = cfg.settings.debug_keep_all_files;
+            let download_concurrency = cfg.settings.download_concurrency;
+            let download_threads = cfg.settings.download_threads;
+            let speed_limit = cfg.settings.max_download_speed_kbps;
             let config_state: Arc<RwLock<config::AppConfig>> =
                 Arc::new(RwLock::new(cfg));
             app.manage(config_state);
@@ -34,25 +45,39 @@ pub fn run() {
                     .app_data_dir()
                     .map(|d| d.join("downloads.json"))
                     .ok();
-                let dm = DownloadManager::new(3, None, persist_path)
+                let dm = DownloadManager::new(download_concurrency, &network, persist_path)
                     .expect("Failed to create download manager");
+                dm.set_verify_mode(verify_mode);
+                dm.set_keep_files(keep_files);
+                dm.set_download_threads(download_threads);
+                dm.set_speed_limit(speed_limit);
                 tauri::async_runtime::block_on(dm.load_persisted())
                     .unwrap_or_else(|e| log::error!("Failed to load persisted downloads: {e}"));
                 dm
             };
 
-            let http_client = reqwest::Client::builder()
-                .user_agent("Mozilla/5.0 Highgarden/0.1.0")
-                .build()
-                .expect("Failed to create HTTP client");
+            // Surface extractions interrupted by a crash; the frontend offers
+            // to resume them via get_interrupted_extractions.
+            if let Ok(dir) = app.path().app_data_dir() {
+                let journal = download::journal::load_journal(&dir.join("extract_journal.json"));
+                for game_id in journal.keys() {
+                    log::warn!("[extract] interrupted extraction found for {game_id}");
+                }
+            }
+
+            let http_client = net::http_client(&network).expect("Failed to create HTTP client");
 
             let state = Arc::new(RwLock::new(AppState {
                 download_manager: Arc::new(download_manager),
                 http_client,
                 running_games: std::collections::HashMap::new(),
+                install_groups: std::collections::HashMap::new(),
+                extractions: std::collections::HashMap::new(),
             }));
 
             app.manage(state);
+
+            tauri::async_runtime::spawn(commands::reconcile_running_games(app.handle().clone()));
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -64,34 +89,91 @@ pub fn run() {
             get_app_config,
             set_settings,
             set_game_path,
+            set_game_channel,
+            find_path_conflicts,
             // Game
             launch_game,
+            stop_game,
+            set_launch_options,
+            get_recent_sessions,
             validate_game_path,
+            auto_detect_game_paths,
+            list_game_exe_candidates,
+            select_game_exe,
             fetch_game_version,
             select_game_path,
             select_download_path,
             // Game download
             fetch_game_manifest,
+            check_install_space,
             start_game_install,
+            suspend_game_install,
+            resume_game_install,
+            cancel_game_install,
+            get_install_eta,
             // Download tasks
             get_download_tasks,
+            get_download_history,
+            get_task_detail,
             start_download_task,
+            retry_download_task,
             pause_download_task,
             cancel_download_task,
+            reverify_download,
+            get_concurrency_status,
+            set_download_log_level,
+            get_download_log_level,
+            resolve_download_filename,
+            probe_mirrors,
+            benchmark_download,
+            test_proxy,
             // Cache
             clear_game_cache,
+            get_app_storage_breakdown,
+            clear_app_cache,
             // Version / update
             check_game_update,
+            force_check_update,
+            set_local_version,
             fetch_update_manifest,
+            get_update_download_size,
+            start_game_patch,
+            // API self-check
+            check_api_compat,
+            verify_game_files,
+            repair_game_files,
             // Extraction
             extract_game_packs,
+            get_interrupted_extractions,
+            resume_extraction,
+            cancel_extraction,
+            extract_entry,
+            list_archive_entries,
             // Gacha analysis
             scan_gacha_url,
+            validate_gacha_url,
             fetch_gacha_records,
             get_local_gacha_records,
             get_gacha_stats,
+            estimate_pulls_for_target,
+            simulate_pulls,
+            set_pity_anchor,
+            gacha_rate_timeline,
+            pull_breakdown,
+            six_star_inventory,
+            get_gacha_item_summary,
+            fix_gacha_uid,
+            compact_gacha_storage,
+            get_all_games_summary,
             export_gacha_records,
+            export_gacha_markdown,
+            export_rare_pulls,
+            export_gacha_bundle,
+            import_official_export,
+            analyze_import,
             select_gacha_export_path,
+            import_gacha_records,
+            select_gacha_import_path,
             // Hypergryph auth
             get_hypergryph_session,
             hypergryph_login_password,
@@ -99,6 +181,7 @@ pub fn run() {
             hypergryph_login_by_code,
             hypergryph_logout,
             fetch_gacha_with_login,
+            fetch_all_games_gacha,
         ])
         .run(tauri::generate_context!())
         .expect("error while running highgarden");
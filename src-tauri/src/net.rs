@@ -0,0 +1,210 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::ClientBuilder;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+// ─── Types ────────────────────────────────────────────────────────────────────
+
+/// Which IP family outgoing connections may use.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IpMode {
+    /// Whatever the OS resolver returns (default).
+    #[default]
+    System,
+    Ipv4,
+    Ipv6,
+}
+
+/// Connectivity options applied to every HTTP client the app builds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkOptions {
+    pub ip_mode: IpMode,
+    /// DNS-over-HTTPS JSON endpoint, e.g. "https://dns.alidns.com/resolve".
+    pub doh_url: Option<String>,
+    /// HTTP(S) proxy for all requests, validated by `validate_proxy_url`.
+    pub proxy_url: Option<String>,
+}
+
+// ─── Client configuration ─────────────────────────────────────────────────────
+
+pub fn apply_network_options(mut builder: ClientBuilder, opts: &NetworkOptions) -> ClientBuilder {
+    // Binding to the unspecified address of one family makes the connector
+    // skip resolved addresses of the other family.
+    match opts.ip_mode {
+        IpMode::System => {}
+        IpMode::Ipv4 => builder = builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        IpMode::Ipv6 => builder = builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+    }
+    if let Some(url) = opts.doh_url.as_deref().filter(|u| !u.is_empty()) {
+        builder = builder.dns_resolver(Arc::new(DohResolver::new(url, opts.ip_mode)));
+    }
+    if let Some(url) = opts.proxy_url.as_deref().filter(|u| !u.is_empty()) {
+        match reqwest::Proxy::all(url) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            // Settings are validated on save; this only guards old configs.
+            Err(e) => log::warn!("[net] ignoring invalid proxy {}: {}", url, e),
+        }
+    }
+    builder
+}
+
+/// The shared client for API calls (gacha, auth, manifests).
+pub fn http_client(opts: &NetworkOptions) -> reqwest::Result<reqwest::Client> {
+    apply_network_options(
+        reqwest::Client::builder().user_agent("Mozilla/5.0 Highgarden/0.1.0"),
+        opts,
+    )
+    .build()
+}
+
+/// Accept only `http://` or `https://` proxies with a host; SOCKS isn't
+/// compiled into reqwest here.
+pub fn validate_proxy_url(url: &str) -> anyhow::Result<()> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| anyhow::anyhow!("代理地址无效：{e}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(anyhow::anyhow!(
+            "不支持的代理类型：{}（仅支持 http/https）",
+            parsed.scheme()
+        ));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(anyhow::anyhow!("代理地址缺少主机名"));
+    }
+    reqwest::Proxy::all(url).map_err(|e| anyhow::anyhow!("代理地址无效：{e}"))?;
+    Ok(())
+}
+
+// ─── DNS-over-HTTPS ───────────────────────────────────────────────────────────
+
+/// Resolves hostnames through a DoH server's JSON API (`application/dns-json`,
+/// supported by AliDNS, Google and Cloudflare). The DoH host itself is looked
+/// up with the system resolver.
+struct DohResolver {
+    client: reqwest::Client,
+    endpoint: String,
+    ip_mode: IpMode,
+}
+
+impl DohResolver {
+    fn new(endpoint: &str, ip_mode: IpMode) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoint: endpoint.to_string(),
+            ip_mode,
+        }
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let client = self.client.clone();
+        let endpoint = self.endpoint.clone();
+        let record_types: &'static [u16] = match self.ip_mode {
+            IpMode::System => &[1, 28],
+            IpMode::Ipv4 => &[1],
+            IpMode::Ipv6 => &[28],
+        };
+        Box::pin(async move {
+            let mut addrs: Vec<SocketAddr> = Vec::new();
+            for record_type in record_types {
+                let resp: serde_json::Value = client
+                    .get(&endpoint)
+                    .query(&[("name", name.as_str()), ("type", &record_type.to_string())])
+                    .header(reqwest::header::ACCEPT, "application/dns-json")
+                    .timeout(std::time::Duration::from_secs(5))
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                let answers = resp["Answer"].as_array().cloned().unwrap_or_default();
+                // CNAME answers carry names, not addresses; the final A/AAAA
+                // records of the chain parse and the rest are skipped.
+                addrs.extend(
+                    answers
+                        .iter()
+                        .filter_map(|a| a["data"].as_str()?.parse::<IpAddr>().ok())
+                        .map(|ip| SocketAddr::new(ip, 0)),
+                );
+            }
+            if addrs.is_empty() {
+                log::warn!("[net] DoH returned no addresses for {}", name.as_str());
+                return Err(format!("DoH 无法解析 {}", name.as_str()).into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+// ─── Proxy test ───────────────────────────────────────────────────────────────
+
+/// Two-step proxy check. A proxy can answer plain HTTP yet refuse `CONNECT`
+/// to the CDN's HTTPS port, which is what downloads actually need.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyTestResult {
+    pub cdn_host: String,
+    /// The proxy answered a plain-HTTP request (no tunnel)
+    pub proxy_reachable: bool,
+    pub proxy_error: Option<String>,
+    /// An HTTPS request to the CDN got through a `CONNECT` tunnel
+    pub tunnel_ok: bool,
+    pub tunnel_error: Option<String>,
+}
+
+/// Test `proxy_url` against the host of `cdn_url` (an https pack URL).
+pub async fn test_proxy(
+    proxy_url: &str,
+    cdn_url: &str,
+    opts: &NetworkOptions,
+) -> anyhow::Result<ProxyTestResult> {
+    let cdn = reqwest::Url::parse(cdn_url)?;
+    let cdn_host = cdn
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("无效的 CDN 地址：{}", cdn_url))?
+        .to_string();
+
+    // Only the proxy under test, not the configured one.
+    let opts = NetworkOptions {
+        proxy_url: None,
+        ..opts.clone()
+    };
+    let builder = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(proxy_url)?)
+        .timeout(std::time::Duration::from_secs(10));
+    let client = apply_network_options(builder, &opts).build()?;
+
+    // Plain HTTP is forwarded by the proxy itself; any status means it's up.
+    let plain = client.head(format!("http://{}/", cdn_host)).send().await;
+    let (proxy_reachable, proxy_error) = match plain {
+        Ok(_) => (true, None),
+        Err(e) => (false, Some(e.to_string())),
+    };
+
+    // Any HTTP status over HTTPS means the tunnel and TLS handshake worked.
+    let tls = client
+        .get(cdn)
+        .header(reqwest::header::RANGE, "bytes=0-0")
+        .send()
+        .await;
+    let (tunnel_ok, tunnel_error) = match tls {
+        Ok(_) => (true, None),
+        Err(e) => (false, Some(e.to_string())),
+    };
+
+    log::info!(
+        "[net] proxy test host={} reachable={} tunnel={}",
+        cdn_host,
+        proxy_reachable,
+        tunnel_ok
+    );
+    Ok(ProxyTestResult {
+        cdn_host,
+        proxy_reachable,
+        proxy_error,
+        tunnel_ok,
+        tunnel_error,
+    })
+}